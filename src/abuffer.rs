@@ -0,0 +1,158 @@
+use crate::blend::BlendState;
+use crate::color::ColorSpace;
+use crate::state::RenderState;
+use sdl2::pixels::Color;
+
+// Order independent transparency with per-pixel fragment lists.
+//
+// Translucent fragments are not blended when they are drawn but appended to a linked list
+// per pixel, kept in one shared node pool. At resolve time each list is sorted by depth and
+// composited back to front over the opaque color, so intersecting translucent triangles
+// blend correctly where no per-triangle order could.
+//
+// The pool holds at most `max_fragments` nodes. Once it is full, further fragments fall back
+// to being blended into the color buffer right away, which is the unsorted result. Those
+// pixels may come out in the wrong order but nothing is lost and memory stays bounded.
+
+const END: u32 = u32::MAX;
+
+#[derive(Clone, Copy)]
+struct Node {
+    color: Color,
+    depth: f32,
+    blend: BlendState,
+    color_space: ColorSpace,
+    next: u32,
+}
+
+// What happened to the fragments of the last frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ABufferStats {
+    pub stored: usize,
+    pub overflowed: usize,
+    pub deepest_list: usize,
+}
+
+pub struct ABuffer {
+    heads: Vec<u32>,
+    nodes: Vec<Node>,
+    max_fragments: usize,
+    overflowed: usize,
+    // Scratch list reused while resolving a pixel
+    sorted: Vec<Node>,
+}
+
+impl ABuffer {
+    pub fn new(width: u32, height: u32, max_fragments: usize) -> Self {
+        Self {
+            heads: vec![END; (width * height) as usize],
+            nodes: Vec::new(),
+            max_fragments: max_fragments.min(END as usize),
+            overflowed: 0,
+            sorted: Vec::new(),
+        }
+    }
+
+    pub fn max_fragments(&self) -> usize {
+        self.max_fragments
+    }
+
+    pub fn clear(&mut self) {
+        self.heads.fill(END);
+        self.nodes.clear();
+        self.overflowed = 0;
+    }
+
+    // Stores a fragment for the pixel, false when the pool is full and the caller has to fall back
+    pub fn insert(&mut self, pixel: usize, color: Color, depth: f32, state: &RenderState) -> bool {
+        if self.nodes.len() >= self.max_fragments {
+            self.overflowed += 1;
+            return false;
+        }
+        self.nodes.push(Node {
+            color,
+            depth,
+            blend: state.blend,
+            color_space: state.color_space,
+            next: self.heads[pixel],
+        });
+        self.heads[pixel] = (self.nodes.len() - 1) as u32;
+        true
+    }
+
+    // Composites every pixel's fragments from back to front over `color`. Fragments behind
+    // `depth`, from opaque geometry drawn after them, are dropped.
+    pub fn resolve(&mut self, color: &mut [Color], depth: &[f32]) -> ABufferStats {
+        let mut deepest_list = 0;
+        for pixel in 0..self.heads.len() {
+            let mut node = self.heads[pixel];
+            if node == END {
+                continue;
+            }
+            self.sorted.clear();
+            while node != END {
+                let fragment = self.nodes[node as usize];
+                if fragment.depth <= depth[pixel] {
+                    self.sorted.push(fragment);
+                }
+                node = fragment.next;
+            }
+            deepest_list = deepest_list.max(self.sorted.len());
+            self.sorted.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+            for fragment in &self.sorted {
+                color[pixel] = fragment.blend.blend_in(fragment.color, color[pixel], fragment.color_space);
+            }
+        }
+
+        let stats = ABufferStats { stored: self.nodes.len(), overflowed: self.overflowed, deepest_list };
+        self.clear();
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fragments_past_the_cap_are_refused_and_counted() {
+        let mut buffer = ABuffer::new(2, 1, 3);
+        let state = RenderState::TRANSPARENT;
+        let red = Color::RGBA(255, 0, 0, 128);
+        let inserted: Vec<bool> = (0..5).map(|i| buffer.insert(i % 2, red, 0.5, &state)).collect();
+        assert_eq!(inserted, vec![true, true, true, false, false]);
+
+        let mut color = vec![Color::RGB(0, 0, 0); 2];
+        let stats = buffer.resolve(&mut color, &[1.0, 1.0]);
+        assert_eq!(stats, ABufferStats { stored: 3, overflowed: 2, deepest_list: 2 });
+        // Resolving empties the pool for the next frame
+        assert!(buffer.insert(0, red, 0.5, &state));
+    }
+
+    #[test]
+    fn fragments_are_composited_back_to_front() {
+        let state = RenderState::TRANSPARENT;
+        let near = Color::RGBA(0, 255, 0, 128);
+        let middle = Color::RGBA(255, 0, 0, 128);
+        let far = Color::RGBA(0, 0, 255, 128);
+        let background = Color::RGB(20, 20, 20);
+        let composite = |layers: [Color; 2]| {
+            layers.iter().fold(background, |dst, &src| state.blend.blend_in(src, dst, state.color_space))
+        };
+        let expected = composite([middle, near]);
+        assert_ne!(expected, composite([near, middle]));
+
+        // Any insertion order gives the same result, the far fragment behind the opaque depth is dropped
+        for order in [[0, 1, 2], [2, 1, 0], [1, 0, 2], [2, 0, 1]] {
+            let mut buffer = ABuffer::new(1, 1, 16);
+            let fragments = [(near, 0.2), (middle, 0.5), (far, 0.8)];
+            for i in order {
+                buffer.insert(0, fragments[i].0, fragments[i].1, &state);
+            }
+            let mut color = vec![background];
+            let stats = buffer.resolve(&mut color, &[0.6]);
+            assert_eq!(color[0], expected, "order {:?}", order);
+            assert_eq!(stats.deepest_list, 2);
+        }
+    }
+}
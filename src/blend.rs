@@ -0,0 +1,132 @@
+use crate::color::{linear_to_srgb, srgb_to_linear, ColorSpace};
+use crate::hdr::HdrColor;
+use sdl2::pixels::Color;
+
+// Blending of incoming fragment colors with the framebuffer, following the usual GPU model:
+//
+//     result = equation(src * src_factor, dst * dst_factor)
+//
+// evaluated per channel on colors normalized to [0, 1], either directly on the sRGB values or
+// in linear light. Alpha goes through the same factors as the color channels.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColor,
+    OneMinusSrcColor,
+    DstColor,
+    OneMinusDstColor,
+    SrcAlpha,
+    OneMinusSrcAlpha,
+    DstAlpha,
+    OneMinusDstAlpha,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendEquation {
+    Add,
+    Subtract,
+    ReverseSubtract,
+    Min,
+    Max,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlendState {
+    pub equation: BlendEquation,
+    pub src_factor: BlendFactor,
+    pub dst_factor: BlendFactor,
+}
+
+impl BlendState {
+    // Source replaces the destination
+    pub const OPAQUE: BlendState = BlendState::new(BlendEquation::Add, BlendFactor::One, BlendFactor::Zero);
+    // Classic "over" for straight alpha
+    pub const ALPHA: BlendState = BlendState::new(BlendEquation::Add, BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha);
+    // "over" for colors already multiplied by their alpha
+    pub const PREMULTIPLIED: BlendState = BlendState::new(BlendEquation::Add, BlendFactor::One, BlendFactor::OneMinusSrcAlpha);
+    // Light adds up, weighted by the source alpha
+    pub const ADDITIVE: BlendState = BlendState::new(BlendEquation::Add, BlendFactor::SrcAlpha, BlendFactor::One);
+    // Darkens the destination by the source color
+    pub const MULTIPLY: BlendState = BlendState::new(BlendEquation::Add, BlendFactor::DstColor, BlendFactor::Zero);
+
+    pub const fn new(equation: BlendEquation, src_factor: BlendFactor, dst_factor: BlendFactor) -> Self {
+        Self { equation, src_factor, dst_factor }
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        *self == Self::OPAQUE
+    }
+
+    pub fn blend(&self, src: Color, dst: Color) -> Color {
+        self.blend_in(src, dst, ColorSpace::Srgb)
+    }
+
+    pub fn blend_in(&self, src: Color, dst: Color, space: ColorSpace) -> Color {
+        // Covers the common cases of solid geometry without touching floats
+        if self.is_opaque() || (*self == Self::ALPHA && src.a == 255) {
+            return src;
+        }
+        let out = self.combine(normalize(src, space), normalize(dst, space));
+        let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let channel = |v: f32| match space {
+            ColorSpace::Srgb => byte(v),
+            ColorSpace::Linear => linear_to_srgb(v),
+        };
+        Color::RGBA(channel(out[0]), channel(out[1]), channel(out[2]), byte(out[3]))
+    }
+
+    // Blending of linear light HDR colors, the color channels are not clamped to 1
+    pub fn blend_hdr(&self, src: HdrColor, dst: HdrColor) -> HdrColor {
+        if self.is_opaque() || (*self == Self::ALPHA && src[3] >= 1.0) {
+            return src;
+        }
+        let mut out = self.combine(src, dst);
+        out[..3].iter_mut().for_each(|v| *v = v.max(0.0));
+        out[3] = out[3].clamp(0.0, 1.0);
+        out
+    }
+
+    fn combine(&self, s: [f32; 4], d: [f32; 4]) -> [f32; 4] {
+        let src_weight = factor(self.src_factor, &s, &d);
+        let dst_weight = factor(self.dst_factor, &s, &d);
+
+        let mut out = [0.0; 4];
+        for i in 0..4 {
+            let (a, b) = (s[i] * src_weight[i], d[i] * dst_weight[i]);
+            out[i] = match self.equation {
+                BlendEquation::Add => a + b,
+                BlendEquation::Subtract => a - b,
+                BlendEquation::ReverseSubtract => b - a,
+                // Min and max ignore the factors, as on GPUs
+                BlendEquation::Min => s[i].min(d[i]),
+                BlendEquation::Max => s[i].max(d[i]),
+            };
+        }
+        out
+    }
+}
+
+fn normalize(color: Color, space: ColorSpace) -> [f32; 4] {
+    let channel = |c: u8| match space {
+        ColorSpace::Srgb => c as f32 / 255.0,
+        ColorSpace::Linear => srgb_to_linear(c),
+    };
+    [channel(color.r), channel(color.g), channel(color.b), color.a as f32 / 255.0]
+}
+
+fn factor(factor: BlendFactor, s: &[f32; 4], d: &[f32; 4]) -> [f32; 4] {
+    match factor {
+        BlendFactor::Zero => [0.0; 4],
+        BlendFactor::One => [1.0; 4],
+        BlendFactor::SrcColor => *s,
+        BlendFactor::OneMinusSrcColor => s.map(|v| 1.0 - v),
+        BlendFactor::DstColor => *d,
+        BlendFactor::OneMinusDstColor => d.map(|v| 1.0 - v),
+        BlendFactor::SrcAlpha => [s[3]; 4],
+        BlendFactor::OneMinusSrcAlpha => [1.0 - s[3]; 4],
+        BlendFactor::DstAlpha => [d[3]; 4],
+        BlendFactor::OneMinusDstAlpha => [1.0 - d[3]; 4],
+    }
+}
@@ -0,0 +1,46 @@
+use crate::math::{Mat4, Vec3};
+
+// A perspective camera looking from `position` at `target`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    // Vertical field of view in radians
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    pub fn new(position: Vec3, target: Vec3) -> Self {
+        Self { position, target, up: Vec3::UP, fov_y: 60f32.to_radians(), near: 0.1, far: 100.0 }
+    }
+
+    // On a circle of `radius` around `target` at `height` above it, `angle` radians around the y axis
+    pub fn orbit(target: Vec3, radius: f32, height: f32, angle: f32) -> Self {
+        let position = target + Vec3::new(angle.sin() * radius, height, angle.cos() * radius);
+        Self::new(position, target)
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at(self.position, self.target, self.up)
+    }
+
+    pub fn projection(&self, aspect: f32) -> Mat4 {
+        Mat4::perspective(self.fov_y, aspect, self.near, self.far)
+    }
+
+    pub fn view_projection(&self, aspect: f32) -> Mat4 {
+        self.projection(aspect) * self.view()
+    }
+
+    // Unit direction of the view ray through the point in normalized device coordinates
+    pub fn ray(&self, ndc_x: f32, ndc_y: f32, aspect: f32) -> Vec3 {
+        let forward = (self.target - self.position).normalized();
+        let right = forward.cross(self.up).normalized();
+        let up = right.cross(forward);
+        let half_height = (self.fov_y / 2.0).tan();
+        (forward + right * (ndc_x * half_height * aspect) + up * (ndc_y * half_height)).normalized()
+    }
+}
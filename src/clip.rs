@@ -0,0 +1,262 @@
+use crate::color::interpolate_color;
+use crate::line::draw_bresenham_line;
+use sdl2::pixels::Color;
+
+// Clipping lines against a rectangular viewport before they are stepped.
+//
+// Both classic algorithms are available so they can be compared. Cohen–Sutherland
+// classifies the end points with region outcodes and moves one outside end point onto a
+// border per iteration, Liang–Barsky solves for the entry and exit parameters directly.
+// Both report the visible part as the parameters t1 <= t2 along the original line, which
+// keeps the color interpolation of the clipped line identical to the unclipped one.
+// The math is done in f64 so far away end points do not lose the visible part.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipAlgorithm {
+    CohenSutherland,
+    LiangBarsky,
+}
+
+// Pixel rectangle, both bounds inclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClipRect {
+    pub x_min: i32,
+    pub y_min: i32,
+    pub x_max: i32,
+    pub y_max: i32,
+}
+
+impl ClipRect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x_min: x,
+            y_min: y,
+            x_max: x + width as i32 - 1,
+            y_max: y + height as i32 - 1,
+        }
+    }
+}
+
+// The clip state handed to the line drawing functions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineClip {
+    pub rect: ClipRect,
+    pub algorithm: ClipAlgorithm,
+}
+
+// The visible part of a line, with its parameters along the original line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClippedLine {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
+    pub t1: f32,
+    pub t2: f32,
+}
+
+pub fn clip_line(clip: &LineClip, x1: i32, y1: i32, x2: i32, y2: i32) -> Option<ClippedLine> {
+    let (t1, t2) = match clip.algorithm {
+        ClipAlgorithm::CohenSutherland => cohen_sutherland(&clip.rect, x1, y1, x2, y2)?,
+        ClipAlgorithm::LiangBarsky => liang_barsky(&clip.rect, x1, y1, x2, y2)?,
+    };
+    let (dx, dy) = (x2 as f64 - x1 as f64, y2 as f64 - y1 as f64);
+    let at = |t: f64, start: i32, delta: f64| (start as f64 + t * delta).round() as i32;
+    Some(ClippedLine {
+        x1: at(t1, x1, dx),
+        y1: at(t1, y1, dy),
+        x2: at(t2, x1, dx),
+        y2: at(t2, y1, dy),
+        t1: t1 as f32,
+        t2: t2 as f32,
+    })
+}
+
+const INSIDE: u8 = 0;
+const LEFT: u8 = 1;
+const RIGHT: u8 = 2;
+const TOP: u8 = 4;
+const BOTTOM: u8 = 8;
+
+fn outcode(rect: &ClipRect, x: f64, y: f64) -> u8 {
+    let mut code = INSIDE;
+    if x < rect.x_min as f64 {
+        code |= LEFT;
+    } else if x > rect.x_max as f64 {
+        code |= RIGHT;
+    }
+    if y < rect.y_min as f64 {
+        code |= TOP;
+    } else if y > rect.y_max as f64 {
+        code |= BOTTOM;
+    }
+    code
+}
+
+fn cohen_sutherland(rect: &ClipRect, x1: i32, y1: i32, x2: i32, y2: i32) -> Option<(f64, f64)> {
+    let (x1, y1) = (x1 as f64, y1 as f64);
+    let (dx, dy) = (x2 as f64 - x1, y2 as f64 - y1);
+    let point = |t: f64| (x1 + t * dx, y1 + t * dy);
+
+    let (mut t1, mut t2) = (0.0, 1.0);
+    let mut code1 = outcode(rect, x1, y1);
+    let mut code2 = outcode(rect, x2 as f64, y2 as f64);
+
+    loop {
+        if code1 | code2 == INSIDE {
+            return Some((t1, t2));
+        }
+        if code1 & code2 != INSIDE {
+            return None;
+        }
+
+        // Move the end point that is outside onto the border it lies beyond
+        let code = if code1 != INSIDE { code1 } else { code2 };
+        let t = if code & LEFT != 0 {
+            (rect.x_min as f64 - x1) / dx
+        } else if code & RIGHT != 0 {
+            (rect.x_max as f64 - x1) / dx
+        } else if code & TOP != 0 {
+            (rect.y_min as f64 - y1) / dy
+        } else {
+            (rect.y_max as f64 - y1) / dy
+        };
+
+        let (x, y) = point(t);
+        // Snap the coordinate that was solved for, so rounding cannot leave it outside again
+        let (x, y) = if code & (LEFT | RIGHT) != 0 {
+            (if code & LEFT != 0 { rect.x_min as f64 } else { rect.x_max as f64 }, y)
+        } else {
+            (x, if code & TOP != 0 { rect.y_min as f64 } else { rect.y_max as f64 })
+        };
+        if code == code1 {
+            t1 = t;
+            code1 = outcode(rect, x, y);
+        } else {
+            t2 = t;
+            code2 = outcode(rect, x, y);
+        }
+    }
+}
+
+fn liang_barsky(rect: &ClipRect, x1: i32, y1: i32, x2: i32, y2: i32) -> Option<(f64, f64)> {
+    let (x1, y1) = (x1 as f64, y1 as f64);
+    let (dx, dy) = (x2 as f64 - x1, y2 as f64 - y1);
+
+    // p * t <= q for each of the left, right, top and bottom borders
+    let borders = [
+        (-dx, x1 - rect.x_min as f64),
+        (dx, rect.x_max as f64 - x1),
+        (-dy, y1 - rect.y_min as f64),
+        (dy, rect.y_max as f64 - y1),
+    ];
+
+    let (mut t1, mut t2) = (0.0f64, 1.0f64);
+    for (p, q) in borders {
+        if p == 0.0 {
+            // Parallel to this border, visible only when on the inner side
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t1 = t1.max(q / p);
+        } else {
+            t2 = t2.min(q / p);
+        }
+    }
+
+    if t1 > t2 {
+        None
+    } else {
+        Some((t1, t2))
+    }
+}
+
+// Clips the line and steps only the visible part, colors are taken at the clipped ends
+#[allow(clippy::too_many_arguments)]
+pub fn draw_clipped_line<F: FnMut(i32, i32, Color)>(
+    clip: &LineClip,
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+    c1: Color,
+    c2: Color,
+    resolution: i32,
+    plot: F,
+) {
+    if let Some(line) = clip_line(clip, x1, y1, x2, y2) {
+        draw_bresenham_line(
+            line.x1,
+            line.y1,
+            line.x2,
+            line.y2,
+            interpolate_color(c1, c2, line.t1),
+            interpolate_color(c1, c2, line.t2),
+            resolution,
+            plot,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECT: ClipRect = ClipRect { x_min: 10, y_min: 10, x_max: 100, y_max: 100 };
+
+    fn clip_both(x1: i32, y1: i32, x2: i32, y2: i32) -> Option<ClippedLine> {
+        let clip = |algorithm| clip_line(&LineClip { rect: RECT, algorithm }, x1, y1, x2, y2);
+        let (cohen_sutherland, liang_barsky) = (clip(ClipAlgorithm::CohenSutherland), clip(ClipAlgorithm::LiangBarsky));
+        match (cohen_sutherland, liang_barsky) {
+            (Some(a), Some(b)) => {
+                assert_eq!((a.x1, a.y1, a.x2, a.y2), (b.x1, b.y1, b.x2, b.y2));
+                assert!((a.t1 - b.t1).abs() < 1e-6 && (a.t2 - b.t2).abs() < 1e-6, "{:?} {:?}", a, b);
+            }
+            (None, None) => {}
+            (a, b) => panic!("the algorithms disagree: {:?} and {:?}", a, b),
+        }
+        cohen_sutherland
+    }
+
+    fn ends(line: Option<ClippedLine>) -> Option<(i32, i32, i32, i32)> {
+        line.map(|line| (line.x1, line.y1, line.x2, line.y2))
+    }
+
+    #[test]
+    fn lines_inside_are_kept_whole() {
+        let line = clip_both(20, 30, 80, 70).unwrap();
+        assert_eq!((line.x1, line.y1, line.x2, line.y2, line.t1, line.t2), (20, 30, 80, 70, 0.0, 1.0));
+        assert_eq!(ends(clip_both(50, 50, 50, 50)), Some((50, 50, 50, 50)));
+    }
+
+    #[test]
+    fn lines_outside_are_rejected() {
+        assert_eq!(ends(clip_both(0, 20, 5, 90)), None);
+        assert_eq!(ends(clip_both(120, 0, 300, 50)), None);
+        assert_eq!(ends(clip_both(5, 5, 5, 5)), None);
+        // Passes the top left corner on the outside, so neither end can be trivially rejected
+        assert_eq!(ends(clip_both(0, 15, 15, 0)), None);
+        assert_eq!(ends(clip_both(95, 0, 120, 25)), None);
+    }
+
+    #[test]
+    fn lines_crossing_corners_are_cut_at_both_borders() {
+        let line = clip_both(0, 0, 110, 110).unwrap();
+        assert_eq!((line.x1, line.y1, line.x2, line.y2), (10, 10, 100, 100));
+        assert!((line.t1 - 10.0 / 110.0).abs() < 1e-6 && (line.t2 - 100.0 / 110.0).abs() < 1e-6);
+        assert_eq!(ends(clip_both(110, 110, 0, 0)), Some((100, 100, 10, 10)));
+        assert_eq!(ends(clip_both(0, 60, 60, 0)), Some((10, 50, 50, 10)));
+        // Touching the corner only leaves that single point
+        assert_eq!(ends(clip_both(0, 20, 20, 0)), Some((10, 10, 10, 10)));
+    }
+
+    #[test]
+    fn bounds_are_inclusive() {
+        assert_eq!(ends(clip_both(100, 0, 100, 200)), Some((100, 10, 100, 100)));
+        assert_eq!(ends(clip_both(-50, 10, 200, 10)), Some((10, 10, 100, 10)));
+        assert_eq!(ends(clip_both(10, 10, 100, 100)), Some((10, 10, 100, 100)));
+        assert_eq!(ends(clip_both(101, 0, 101, 200)), None);
+        assert_eq!(ends(clip_both(-50, 9, 200, 9)), None);
+    }
+}
@@ -0,0 +1,168 @@
+use sdl2::pixels::Color;
+use std::sync::OnceLock;
+
+// The space colors are interpolated and blended in. Stored colors are always sRGB bytes, in
+// linear light the channels are decoded before the math and encoded again afterwards, which
+// keeps gradients between saturated colors from turning dark and muddy in the middle.
+// Alpha is linear in both.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+// sRGB byte to linear light in [0, 1], through a table since it runs per fragment
+pub fn srgb_to_linear(channel: u8) -> f32 {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        std::array::from_fn(|i| {
+            let c = i as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    })[channel as usize]
+}
+
+// Linear light in [0, 1] to the nearest sRGB byte
+pub fn linear_to_srgb(value: f32) -> u8 {
+    (encode_srgb(value) * 255.0).round() as u8
+}
+
+// Linear light RGB to an opaque sRGB color
+pub fn linear_rgb(rgb: [f32; 3]) -> Color {
+    Color::RGB(linear_to_srgb(rgb[0]), linear_to_srgb(rgb[1]), linear_to_srgb(rgb[2]))
+}
+
+// Linear light in [0, 1] to the sRGB encoded value in [0, 1], before quantization
+pub fn encode_srgb(value: f32) -> f32 {
+    let v = value.clamp(0.0, 1.0);
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Simple Linear Interpolation for color
+pub fn interpolate_color(c1: Color, c2: Color, t: f32) -> Color {
+    let r = (c1.r as f32 * (1.0 - t) + c2.r as f32 * t) as u8;
+    let g = (c1.g as f32 * (1.0 - t) + c2.g as f32 * t) as u8;
+    let b = (c1.b as f32 * (1.0 - t) + c2.b as f32 * t) as u8;
+    let a = (c1.a as f32 * (1.0 - t) + c2.a as f32 * t) as u8;
+    Color::RGBA(r, g, b, a)
+}
+
+// Scales the color channels by alpha, for the premultiplied blend mode
+pub fn premultiply(color: Color) -> Color {
+    let scale = |channel: u8| (channel as u16 * color.a as u16 / 255) as u8;
+    Color::RGBA(scale(color.r), scale(color.g), scale(color.b), color.a)
+}
+
+// Channel wise product, used to tint texels with the vertex color
+pub fn modulate(c1: Color, c2: Color) -> Color {
+    let mul = |a: u8, b: u8| (a as u16 * b as u16 / 255) as u8;
+    Color::RGBA(mul(c1.r, c2.r), mul(c1.g, c2.g), mul(c1.b, c2.b), mul(c1.a, c2.a))
+}
+
+// A CSS color : #rgb, #rrggbb, rgb() with numbers or percentages, or one of the basic color names
+pub fn parse_color(value: &str) -> Result<Color, String> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits: Option<Vec<u8>> = hex.chars().map(|c| c.to_digit(16).map(|d| d as u8)).collect();
+        return match digits.as_deref() {
+            Some(&[r, g, b]) => Ok(Color::RGB(r * 17, g * 17, b * 17)),
+            Some(&[r1, r2, g1, g2, b1, b2]) => Ok(Color::RGB(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2)),
+            _ => Err(format!("Invalid color \"{}\"", value)),
+        };
+    }
+    if let Some(inner) = value.strip_prefix("rgb(").and_then(|v| v.strip_suffix(')')) {
+        let channels: Vec<&str> = inner.split(',').map(str::trim).collect();
+        if channels.len() != 3 {
+            return Err(format!("Invalid color \"{}\"", value));
+        }
+        let mut rgb = [0u8; 3];
+        for (channel, text) in rgb.iter_mut().zip(channels) {
+            let number = match text.strip_suffix('%') {
+                Some(percent) => percent.parse::<f32>().map(|p| p * 2.55),
+                None => text.parse::<f32>(),
+            };
+            *channel = number.map_err(|_| format!("Invalid color \"{}\"", value))?.clamp(0.0, 255.0) as u8;
+        }
+        return Ok(Color::RGB(rgb[0], rgb[1], rgb[2]));
+    }
+
+    let named = match value.to_ascii_lowercase().as_str() {
+        "black" => (0, 0, 0),
+        "white" => (255, 255, 255),
+        "red" => (255, 0, 0),
+        "lime" => (0, 255, 0),
+        "green" => (0, 128, 0),
+        "blue" => (0, 0, 255),
+        "yellow" => (255, 255, 0),
+        "cyan" | "aqua" => (0, 255, 255),
+        "magenta" | "fuchsia" => (255, 0, 255),
+        "gray" | "grey" => (128, 128, 128),
+        "silver" => (192, 192, 192),
+        "maroon" => (128, 0, 0),
+        "olive" => (128, 128, 0),
+        "navy" => (0, 0, 128),
+        "teal" => (0, 128, 128),
+        "purple" => (128, 0, 128),
+        "orange" => (255, 165, 0),
+        "pink" => (255, 192, 203),
+        "brown" => (165, 42, 42),
+        _ => return Err(format!("Unknown color \"{}\"", value)),
+    };
+    Ok(Color::RGB(named.0, named.1, named.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(parse_color("#f80"), Ok(Color::RGB(255, 136, 0)));
+        assert_eq!(parse_color(" #1E90ff "), Ok(Color::RGB(30, 144, 255)));
+        assert!(parse_color("#12345").is_err());
+        assert!(parse_color("#+ff").is_err());
+        assert!(parse_color("#ggg").is_err());
+    }
+
+    #[test]
+    fn non_ascii_hex_is_an_error() {
+        assert!(parse_color("#éa").is_err());
+        assert!(parse_color("#aé0b").is_err());
+    }
+
+    #[test]
+    fn rgb_colors() {
+        assert_eq!(parse_color("rgb(255, 128, 0)"), Ok(Color::RGB(255, 128, 0)));
+        assert_eq!(parse_color("rgb(100%,0%,50%)"), Ok(Color::RGB(255, 0, 127)));
+        assert_eq!(parse_color("rgb(300, -5, 0)"), Ok(Color::RGB(255, 0, 0)));
+        assert!(parse_color("rgb(1, 2)").is_err());
+        assert!(parse_color("rgb(a, b, c)").is_err());
+    }
+
+    #[test]
+    fn named_colors() {
+        assert_eq!(parse_color("Navy"), Ok(Color::RGB(0, 0, 128)));
+        assert_eq!(parse_color("grey"), parse_color("gray"));
+        assert!(parse_color("chartreuse-ish").is_err());
+    }
+
+    #[test]
+    fn srgb_round_trips_through_linear() {
+        for channel in 0..=255u8 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(channel)), channel);
+        }
+        assert_eq!((srgb_to_linear(0), srgb_to_linear(255)), (0.0, 1.0));
+        assert!((1..=255u8).all(|channel| srgb_to_linear(channel) > srgb_to_linear(channel - 1)));
+        // Out of range light is clamped
+        assert_eq!((linear_to_srgb(-0.5), linear_to_srgb(3.0)), (0, 255));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::camera::Camera;
+use crate::color::{linear_rgb, srgb_to_linear};
+use crate::environment::EquirectMap;
+use crate::framebuffer::Framebuffer;
+use crate::light::Surface;
+use crate::math::Vec3;
+use crate::state::RenderState;
+use crate::texture::Texture;
+use crate::viewport::Viewport;
+
+// Cube map environments.
+//
+// Six square faces in linear light around the origin, in the order and orientation of OpenGL
+// cube maps : +x, -x, +y, -y, +z, -z, each seen from the inside with its rows going down. A
+// direction picks the face of its largest component and is looked up bilinearly there.
+// Besides lookups for reflections and refractions in fragment shading, the map can be drawn as
+// a skybox : every background cell shows the environment along the view ray through it.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    // Direction through the face coordinates s, t in [0, 1], s to the right and t down
+    pub fn direction(self, s: f32, t: f32) -> Vec3 {
+        let (sc, tc) = (s * 2.0 - 1.0, t * 2.0 - 1.0);
+        match self {
+            CubeFace::PositiveX => Vec3::new(1.0, -tc, -sc),
+            CubeFace::NegativeX => Vec3::new(-1.0, -tc, sc),
+            CubeFace::PositiveY => Vec3::new(sc, 1.0, tc),
+            CubeFace::NegativeY => Vec3::new(sc, -1.0, -tc),
+            CubeFace::PositiveZ => Vec3::new(sc, -tc, 1.0),
+            CubeFace::NegativeZ => Vec3::new(-sc, -tc, -1.0),
+        }
+    }
+
+    // The face a direction points into and the coordinates there
+    pub fn locate(direction: Vec3) -> (CubeFace, f32, f32) {
+        let Vec3 { x, y, z } = direction;
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+        let (face, sc, tc, major) = if ax >= ay && ax >= az {
+            if x > 0.0 { (CubeFace::PositiveX, -z, -y, ax) } else { (CubeFace::NegativeX, z, -y, ax) }
+        } else if ay >= az {
+            if y > 0.0 { (CubeFace::PositiveY, x, z, ay) } else { (CubeFace::NegativeY, x, -z, ay) }
+        } else if z > 0.0 {
+            (CubeFace::PositiveZ, x, -y, az)
+        } else {
+            (CubeFace::NegativeZ, -x, -y, az)
+        };
+        let major = major.max(f32::MIN_POSITIVE);
+        (face, (sc / major + 1.0) / 2.0, (tc / major + 1.0) / 2.0)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CubeMap {
+    size: u32,
+    faces: [Vec<Vec3>; 6],
+}
+
+impl CubeMap {
+    // Six square 8 bit textures of one size in the order of CubeFace::ALL, decoded from sRGB
+    // and scaled by the intensity
+    pub fn from_faces(faces: [&Texture; 6], intensity: f32) -> Result<Self, String> {
+        let size = faces[0].width();
+        if let Some(face) = faces.iter().find(|face| face.width() != size || face.height() != size) {
+            return Err(format!("Cube map faces must all be {}x{}, got {}x{}", size, size, face.width(), face.height()));
+        }
+        let faces = faces.map(|face| {
+            (0..size * size)
+                .map(|i| {
+                    let c = face.texel(i % size, i / size);
+                    Vec3::new(srgb_to_linear(c.r), srgb_to_linear(c.g), srgb_to_linear(c.b)) * intensity
+                })
+                .collect()
+        });
+        Ok(Self { size, faces })
+    }
+
+    // Resamples an equirectangular environment onto faces of `size` texels
+    pub fn from_equirect(environment: &EquirectMap, size: u32) -> Self {
+        let size = size.max(1);
+        let faces = CubeFace::ALL.map(|face| {
+            (0..size * size)
+                .map(|i| {
+                    let s = ((i % size) as f32 + 0.5) / size as f32;
+                    let t = ((i / size) as f32 + 0.5) / size as f32;
+                    environment.sample(face.direction(s, t))
+                })
+                .collect()
+        });
+        Self { size, faces }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn texel(&self, face: CubeFace, x: u32, y: u32) -> Vec3 {
+        self.faces[face as usize][(y * self.size + x) as usize]
+    }
+
+    // Bilinear lookup in the direction, clamped at the edges of the face
+    pub fn sample(&self, direction: Vec3) -> Vec3 {
+        let (face, s, t) = CubeFace::locate(direction);
+        let last = (self.size - 1) as f32;
+        let x = (s * self.size as f32 - 0.5).clamp(0.0, last);
+        let y = (t * self.size as f32 - 0.5).clamp(0.0, last);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.size - 1), (y0 + 1).min(self.size - 1));
+        let (fx, fy) = (x.fract(), y.fract());
+        let top = self.texel(face, x0, y0).lerp(self.texel(face, x1, y0), fx);
+        let bottom = self.texel(face, x0, y1).lerp(self.texel(face, x1, y1), fx);
+        top.lerp(bottom, fy)
+    }
+
+    // The environment mirrored in the surface, as seen from the eye
+    pub fn reflection(&self, surface: &Surface, eye: Vec3) -> Vec3 {
+        let incident = (surface.position - eye).normalized();
+        self.sample(incident.reflect(surface.normal))
+    }
+
+    // The environment seen through the surface of a material with the index of refraction,
+    // bent once at the surface. Total internal reflection falls back to the reflection.
+    pub fn refraction(&self, surface: &Surface, eye: Vec3, ior: f32) -> Vec3 {
+        let incident = (surface.position - eye).normalized();
+        match incident.refract(surface.normal, 1.0 / ior) {
+            Some(direction) => self.sample(direction),
+            None => self.sample(incident.reflect(surface.normal)),
+        }
+    }
+
+    // Fills the background around the geometry. The sky is depth tested at the far plane of
+    // the viewport without writing depth, so it works drawn before or after the geometry.
+    pub fn draw_skybox(&self, frame: &mut Framebuffer, camera: &Camera, viewport: &Viewport, resolution: i32) {
+        let resolution = resolution.max(1);
+        let rect = viewport.rect();
+        let state = RenderState { depth_write: false, scissor: Some(rect), ..RenderState::OPAQUE };
+        let (width, height) = (viewport.width as f32, viewport.height as f32);
+        let first = |min: i32| min.div_euclid(resolution) * resolution;
+        for y in (first(rect.y_min)..=rect.y_max).step_by(resolution as usize) {
+            for x in (first(rect.x_min)..=rect.x_max).step_by(resolution as usize) {
+                // Through the center of the cell
+                let center_x = x as f32 + resolution as f32 / 2.0 - viewport.x as f32;
+                let center_y = y as f32 + resolution as f32 / 2.0 - viewport.y as f32;
+                let ndc = (center_x / width * 2.0 - 1.0, 1.0 - center_y / height * 2.0);
+                let color = linear_rgb(self.sample(camera.ray(ndc.0, ndc.1, viewport.aspect())).to_array());
+                frame.write_fragment(x, y, resolution, color, viewport.max_depth, &state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axis_directions_pick_their_face_at_its_center() {
+        let axes = [
+            (Vec3::new(1.0, 0.0, 0.0), CubeFace::PositiveX),
+            (Vec3::new(-1.0, 0.0, 0.0), CubeFace::NegativeX),
+            (Vec3::new(0.0, 1.0, 0.0), CubeFace::PositiveY),
+            (Vec3::new(0.0, -1.0, 0.0), CubeFace::NegativeY),
+            (Vec3::new(0.0, 0.0, 1.0), CubeFace::PositiveZ),
+            (Vec3::new(0.0, 0.0, -1.0), CubeFace::NegativeZ),
+        ];
+        for (direction, expected) in axes {
+            let (face, s, t) = CubeFace::locate(direction * 3.0);
+            assert_eq!(face, expected);
+            assert!((s - 0.5).abs() < 1e-6 && (t - 0.5).abs() < 1e-6, "{:?} at {} {}", face, s, t);
+        }
+    }
+
+    #[test]
+    fn off_axis_directions_pick_the_largest_component() {
+        assert_eq!(CubeFace::locate(Vec3::new(0.9, 0.5, -0.8)).0, CubeFace::PositiveX);
+        assert_eq!(CubeFace::locate(Vec3::new(-0.3, -0.7, 0.6)).0, CubeFace::NegativeY);
+        assert_eq!(CubeFace::locate(Vec3::new(0.6, 0.2, -0.61)).0, CubeFace::NegativeZ);
+    }
+
+    #[test]
+    fn face_coordinates_round_trip_through_directions() {
+        for face in CubeFace::ALL {
+            for (s, t) in [(0.1, 0.2), (0.5, 0.9), (0.8, 0.35), (0.99, 0.01)] {
+                let (located, ls, lt) = CubeFace::locate(face.direction(s, t).normalized());
+                assert_eq!(located, face);
+                assert!((ls - s).abs() < 1e-5 && (lt - t).abs() < 1e-5, "{:?} {} {} -> {} {}", face, s, t, ls, lt);
+            }
+        }
+    }
+
+    #[test]
+    fn faces_are_seen_from_inside_with_rows_going_down() {
+        // The top of every side face looks up, and right on +z looks along +x
+        for face in [CubeFace::PositiveX, CubeFace::NegativeX, CubeFace::PositiveZ, CubeFace::NegativeZ] {
+            assert!(face.direction(0.5, 0.0).y > 0.0 && face.direction(0.5, 1.0).y < 0.0, "{:?}", face);
+        }
+        assert!(CubeFace::PositiveZ.direction(1.0, 0.5).x > 0.0);
+        assert!(CubeFace::NegativeZ.direction(1.0, 0.5).x < 0.0);
+    }
+}
@@ -0,0 +1,222 @@
+use crate::color::linear_rgb;
+use crate::framebuffer::Framebuffer;
+use crate::light::{PointLight, ShadingModel, Surface};
+use crate::math::Vec3;
+use crate::ssao::Ssao;
+use sdl2::pixels::Color;
+
+// Deferred shading.
+//
+// Rasterization only stores the nearest surface of every pixel, its world position, normal,
+// albedo and depth, in the G-buffer. Lighting then runs once per covered pixel in a screen
+// space pass, so its cost no longer grows with the overdraw of the scene. Each channel can
+// also be shown on its own, for inspecting what the lighting pass gets, along with the tangent
+// frame normal mapping used. Ambient occlusion computed from the G-buffer can be passed in to
+// darken the ambient light.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GBufferChannel {
+    Lit,
+    Position,
+    Normal,
+    Tangent,
+    Bitangent,
+    Albedo,
+    // Roughness in green and metallic in blue, as in glTF textures
+    MetallicRoughness,
+    Depth,
+    Occlusion,
+}
+
+pub struct GBuffer {
+    width: u32,
+    height: u32,
+    surfaces: Vec<Surface>,
+    depth: Vec<f32>,
+}
+
+impl GBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let size = (width * height) as usize;
+        Self { width, height, surfaces: vec![Surface::default(); size], depth: vec![f32::INFINITY; size] }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn clear(&mut self) {
+        self.depth.fill(f32::INFINITY);
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            None
+        } else {
+            Some((y as u32 * self.width + x as u32) as usize)
+        }
+    }
+
+    // The nearest surface at the pixel, None where nothing was drawn
+    pub fn surface(&self, x: i32, y: i32) -> Option<&Surface> {
+        self.index(x, y).filter(|&i| self.depth[i].is_finite()).map(|i| &self.surfaces[i])
+    }
+
+    pub fn depth(&self, x: i32, y: i32) -> Option<f32> {
+        self.index(x, y).map(|i| self.depth[i])
+    }
+
+    // Stores the surface in the cell where it is nearer than what is there
+    pub fn write(&mut self, x: i32, y: i32, resolution: i32, depth: f32, surface: &Surface) {
+        let (x0, x1) = (x.max(0), (x + resolution).min(self.width as i32));
+        let (y0, y1) = (y.max(0), (y + resolution).min(self.height as i32));
+        for row in y0..y1 {
+            for column in x0..x1 {
+                let i = (row as u32 * self.width + column as u32) as usize;
+                if depth <= self.depth[i] {
+                    self.depth[i] = depth;
+                    self.surfaces[i] = *surface;
+                }
+            }
+        }
+    }
+
+    // Position and index of every covered pixel
+    fn covered(&self) -> impl Iterator<Item = (i32, i32, usize)> + '_ {
+        let width = self.width as usize;
+        (0..self.surfaces.len())
+            .filter(|&i| self.depth[i].is_finite())
+            .map(move |i| ((i % width) as i32, (i / width) as i32, i))
+    }
+
+    // The lighting pass, writes the lit color of every covered pixel into the frame
+    pub fn shade(&self, frame: &mut Framebuffer, lighting: &dyn ShadingModel, lights: &[PointLight], eye: Vec3, occlusion: Option<&Ssao>) {
+        self.show(GBufferChannel::Lit, frame, lighting, lights, eye, occlusion);
+    }
+
+    // Writes one channel of every covered pixel into the frame as a color
+    pub fn show(
+        &self,
+        channel: GBufferChannel,
+        frame: &mut Framebuffer,
+        lighting: &dyn ShadingModel,
+        lights: &[PointLight],
+        eye: Vec3,
+        occlusion: Option<&Ssao>,
+    ) {
+        let visibility = |x: i32, y: i32| occlusion.map_or(1.0, |ssao| ssao.visibility(x, y));
+        let (mut low, mut high) = (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY));
+        let (mut near, mut far) = (f32::INFINITY, f32::NEG_INFINITY);
+        for (_, _, i) in self.covered() {
+            let p = self.surfaces[i].position;
+            low = Vec3::new(low.x.min(p.x), low.y.min(p.y), low.z.min(p.z));
+            high = Vec3::new(high.x.max(p.x), high.y.max(p.y), high.z.max(p.z));
+            near = near.min(self.depth[i]);
+            far = far.max(self.depth[i]);
+        }
+        let normalize = |v: f32, low: f32, high: f32| if high > low { (v - low) / (high - low) } else { 0.5 };
+
+        for (x, y, i) in self.covered() {
+            let surface = &self.surfaces[i];
+            let color = match channel {
+                GBufferChannel::Lit => linear_rgb(lighting.shade(surface, lights, eye, visibility(x, y)).to_array()),
+                // The bounding box of the visible surfaces mapped to the RGB cube
+                GBufferChannel::Position => {
+                    let p = surface.position;
+                    bytes(Vec3::new(normalize(p.x, low.x, high.x), normalize(p.y, low.y, high.y), normalize(p.z, low.z, high.z)))
+                }
+                GBufferChannel::Normal => bytes(surface.normal * 0.5 + Vec3::splat(0.5)),
+                GBufferChannel::Tangent => bytes(surface.tangent * 0.5 + Vec3::splat(0.5)),
+                GBufferChannel::Bitangent => bytes(surface.bitangent * 0.5 + Vec3::splat(0.5)),
+                GBufferChannel::Albedo => linear_rgb(surface.albedo.to_array()),
+                GBufferChannel::MetallicRoughness => bytes(Vec3::new(0.0, surface.roughness, surface.metallic)),
+                // Near is white, far is black
+                GBufferChannel::Depth => bytes(Vec3::splat(1.0 - normalize(self.depth[i], near, far))),
+                GBufferChannel::Occlusion => bytes(Vec3::splat(visibility(x, y))),
+            };
+            frame.set_pixel(x, y, color);
+        }
+    }
+}
+
+// Values in [0, 1] straight to bytes
+fn bytes(value: Vec3) -> Color {
+    let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color::RGB(byte(value.x), byte(value.y), byte(value.z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::srgb_to_linear;
+    use crate::light::BlinnPhong;
+
+    fn surface() -> Surface {
+        Surface {
+            position: Vec3::new(1.0, -2.0, 3.5),
+            normal: Vec3::new(0.6, 0.0, -0.8),
+            albedo: Vec3::new(0.8, 0.2, 0.05),
+            metallic: 1.0,
+            roughness: 0.4,
+            view_depth: 4.0,
+            tangent: Vec3::new(0.8, 0.0, 0.6),
+            bitangent: Vec3::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    // The channel at pixel (1, 1) of a G-buffer holding `surface()` there
+    fn shown(channel: GBufferChannel) -> Color {
+        let mut gbuffer = GBuffer::new(3, 3);
+        gbuffer.write(1, 1, 1, 0.5, &surface());
+        let mut frame = Framebuffer::new(3, 3);
+        gbuffer.show(channel, &mut frame, &BlinnPhong::default(), &[], Vec3::ZERO, None);
+        frame.pixel(1, 1).unwrap()
+    }
+
+    fn unit(color: Color) -> Vec3 {
+        Vec3::new(color.r as f32, color.g as f32, color.b as f32) / 255.0
+    }
+
+    fn assert_close(a: Vec3, b: Vec3, tolerance: f32) {
+        assert!((a - b).length() < tolerance, "{:?} and {:?}", a, b);
+    }
+
+    #[test]
+    fn surfaces_round_trip_and_the_nearest_wins() {
+        let mut gbuffer = GBuffer::new(4, 4);
+        let near = surface();
+        let far = Surface { albedo: Vec3::splat(1.0), ..surface() };
+        gbuffer.write(0, 0, 2, 0.5, &near);
+        gbuffer.write(1, 1, 2, 0.7, &far);
+
+        assert_eq!(gbuffer.surface(1, 1), Some(&near));
+        assert_eq!(gbuffer.depth(1, 1), Some(0.5));
+        assert_eq!(gbuffer.surface(2, 2), Some(&far));
+        assert_eq!(gbuffer.surface(3, 3), None);
+        assert_eq!(gbuffer.surface(-1, 0), None);
+
+        gbuffer.clear();
+        assert_eq!(gbuffer.surface(1, 1), None);
+    }
+
+    #[test]
+    fn channels_decode_back_to_the_stored_values() {
+        let decode_signed = |color: Color| unit(color) * 2.0 - Vec3::splat(1.0);
+        assert_close(decode_signed(shown(GBufferChannel::Normal)), surface().normal, 0.01);
+        assert_close(decode_signed(shown(GBufferChannel::Tangent)), surface().tangent, 0.01);
+        assert_close(decode_signed(shown(GBufferChannel::Bitangent)), surface().bitangent, 0.01);
+
+        let albedo = shown(GBufferChannel::Albedo);
+        let linear = Vec3::new(srgb_to_linear(albedo.r), srgb_to_linear(albedo.g), srgb_to_linear(albedo.b));
+        assert_close(linear, surface().albedo, 0.01);
+
+        assert_close(unit(shown(GBufferChannel::MetallicRoughness)), Vec3::new(0.0, 0.4, 1.0), 0.005);
+        // A single surface is both the nearest and the farthest, so it shows as mid grey
+        assert_eq!(shown(GBufferChannel::Depth), Color::RGB(128, 128, 128));
+        assert_eq!(shown(GBufferChannel::Occlusion), Color::RGB(255, 255, 255));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::color::parse_color;
+use sdl2::pixels::Color;
+
+// Quantizing frames to a limited palette, for retro and e-ink style output.
+//
+// Every pixel is replaced by the nearest palette color. Dithering hides the lost levels:
+// ordered dithering adds a tiled Bayer threshold pattern before the lookup, which is stable
+// between frames, while error diffusion pushes the difference between a pixel and its palette
+// color on to its unvisited neighbours. Floyd–Steinberg passes on all of the error,
+// Atkinson only three quarters of it, which keeps more contrast in light and dark areas.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DitherMethod {
+    Nearest,
+    Bayer,
+    FloydSteinberg,
+    Atkinson,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<Color>,
+    // Typical distance between neighbouring colors per channel, the amplitude of the Bayer pattern
+    spread: f32,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Color>) -> Result<Self, String> {
+        if colors.is_empty() {
+            return Err("A palette needs at least one color".to_string());
+        }
+        let mut total = 0.0;
+        for (i, a) in colors.iter().enumerate() {
+            let nearest = colors.iter().enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, b)| distance(rgb(*a), rgb(*b)).sqrt())
+                .fold(f32::INFINITY, f32::min);
+            total += if nearest.is_finite() { nearest } else { 0.0 };
+        }
+        let spread = total / colors.len() as f32 / 3f32.sqrt();
+        Ok(Self { colors, spread })
+    }
+
+    // Colors in any of the SVG color notations, separated by whitespace
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let colors = spec.split_whitespace().map(parse_color).collect::<Result<Vec<_>, _>>()?;
+        Self::new(colors)
+    }
+
+    // 1 bit black and white
+    pub fn monochrome() -> Self {
+        Self::new(vec![Color::RGB(0, 0, 0), Color::RGB(255, 255, 255)]).unwrap()
+    }
+
+    // The four greens of the original Game Boy
+    pub fn game_boy() -> Self {
+        Self::new(vec![
+            Color::RGB(15, 56, 15),
+            Color::RGB(48, 98, 48),
+            Color::RGB(139, 172, 15),
+            Color::RGB(155, 188, 15),
+        ])
+        .unwrap()
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub fn nearest(&self, value: [f32; 3]) -> Color {
+        let mut best = (f32::INFINITY, self.colors[0]);
+        for &color in &self.colors {
+            let d = distance(value, rgb(color));
+            if d < best.0 {
+                best = (d, color);
+            }
+        }
+        best.1
+    }
+}
+
+fn rgb(color: Color) -> [f32; 3] {
+    [color.r as f32, color.g as f32, color.b as f32]
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum()
+}
+
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+// Replaces the row major `pixels` of an image `width` pixels wide by palette colors, alpha is kept
+pub fn quantize(pixels: &mut [Color], width: usize, palette: &Palette, method: DitherMethod) {
+    if width == 0 {
+        return;
+    }
+    match method {
+        DitherMethod::Nearest => {
+            for pixel in pixels.iter_mut() {
+                *pixel = with_alpha(palette.nearest(rgb(*pixel)), pixel.a);
+            }
+        }
+        DitherMethod::Bayer => {
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                let threshold = (BAYER_8X8[i / width % 8][i % width % 8] as f32 + 0.5) / 64.0 - 0.5;
+                let value = rgb(*pixel).map(|c| c + threshold * palette.spread);
+                *pixel = with_alpha(palette.nearest(value), pixel.a);
+            }
+        }
+        DitherMethod::FloydSteinberg => diffuse(pixels, width, palette, &[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0),
+        DitherMethod::Atkinson => diffuse(
+            pixels,
+            width,
+            palette,
+            &[(1, 0, 1.0), (2, 0, 1.0), (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0), (0, 2, 1.0)],
+            8.0,
+        ),
+    }
+}
+
+// Error diffusion with the given (dx, dy, weight) neighbours, weights are divided by `total`
+fn diffuse(pixels: &mut [Color], width: usize, palette: &Palette, kernel: &[(i32, i32, f32)], total: f32) {
+    let height = pixels.len() / width;
+    let mut values: Vec<[f32; 3]> = pixels.iter().map(|&pixel| rgb(pixel)).collect();
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let chosen = palette.nearest(values[i]);
+            let error = {
+                let target = rgb(chosen);
+                [0, 1, 2].map(|c| values[i][c] - target[c])
+            };
+            pixels[i] = with_alpha(chosen, pixels[i].a);
+
+            for &(dx, dy, weight) in kernel {
+                let (nx, ny) = (x as i32 + dx, y + dy as usize);
+                if nx < 0 || nx >= width as i32 || ny >= height {
+                    continue;
+                }
+                let neighbour = &mut values[ny * width + nx as usize];
+                for c in 0..3 {
+                    neighbour[c] += error[c] * weight / total;
+                }
+            }
+        }
+    }
+}
+
+fn with_alpha(color: Color, alpha: u8) -> Color {
+    Color::RGBA(color.r, color.g, color.b, alpha)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METHODS: [DitherMethod; 4] =
+        [DitherMethod::Nearest, DitherMethod::Bayer, DitherMethod::FloydSteinberg, DitherMethod::Atkinson];
+
+    // A 16 x 8 gradient with a different alpha in every pixel
+    fn gradient() -> Vec<Color> {
+        (0..128u32)
+            .map(|i| Color::RGBA((i % 16 * 17) as u8, (i / 16 * 36) as u8, (i * 2) as u8, (i * 2 + 1) as u8))
+            .collect()
+    }
+
+    #[test]
+    fn output_is_in_the_palette_and_keeps_alpha() {
+        for palette in [Palette::monochrome(), Palette::game_boy()] {
+            for method in METHODS {
+                let original = gradient();
+                let mut pixels = original.clone();
+                quantize(&mut pixels, 16, &palette, method);
+                for (pixel, before) in pixels.iter().zip(&original) {
+                    let in_palette = palette.colors().iter().any(|c| (c.r, c.g, c.b) == (pixel.r, pixel.g, pixel.b));
+                    assert!(in_palette, "{:?} gave {:?}", method, pixel);
+                    assert_eq!(pixel.a, before.a, "{:?}", method);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zero_width_leaves_the_pixels_alone() {
+        for method in METHODS {
+            let mut pixels = gradient();
+            quantize(&mut pixels, 0, &Palette::monochrome(), method);
+            assert_eq!(pixels, gradient());
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::color::srgb_to_linear;
+use crate::math::Vec3;
+use crate::texture::Texture;
+use std::f32::consts::PI;
+
+// Environment maps and the image based lighting precomputed from them.
+//
+// Environments are equirectangular : u runs once around the y axis starting at -z, v from
+// straight up at the top to straight down at the bottom, and texels hold linear light
+// without a limit, so a sun can be much brighter than white. Image based lighting splits
+// the lighting integral the way real time renderers do : diffuse light comes from an
+// irradiance map, the environment convolved with a cosine lobe, and specular light from
+// copies of the environment prefiltered with GGX lobes of increasing roughness, scaled by
+// a table of the integrated BRDF over the viewing angle and roughness.
+
+#[derive(Clone, Debug)]
+pub struct EquirectMap {
+    width: u32,
+    height: u32,
+    texels: Vec<Vec3>,
+}
+
+impl EquirectMap {
+    // Evaluates `radiance` at the direction through the center of every texel
+    pub fn from_fn<F: Fn(Vec3) -> Vec3>(width: u32, height: u32, radiance: F) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        let texels = (0..width * height)
+            .map(|i| {
+                let u = ((i % width) as f32 + 0.5) / width as f32;
+                let v = ((i / width) as f32 + 0.5) / height as f32;
+                radiance(direction(u, v))
+            })
+            .collect();
+        Self { width, height, texels }
+    }
+
+    // An 8 bit texture, decoded from sRGB and scaled by the intensity
+    pub fn from_texture(texture: &Texture, intensity: f32) -> Self {
+        let (width, height) = (texture.width(), texture.height());
+        let texels = (0..width * height)
+            .map(|i| {
+                let c = texture.texel(i % width, i / width);
+                Vec3::new(srgb_to_linear(c.r), srgb_to_linear(c.g), srgb_to_linear(c.b)) * intensity
+            })
+            .collect();
+        Self { width, height, texels }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn texel(&self, x: u32, y: u32) -> Vec3 {
+        self.texels[(y * self.width + x) as usize]
+    }
+
+    // Bilinear lookup in the direction, which does not need to be unit length
+    pub fn sample(&self, direction: Vec3) -> Vec3 {
+        let (u, v) = equirect_uv(direction);
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let column = |x: f32| (x as i32).rem_euclid(self.width as i32) as u32;
+        let row = |y: f32| (y as u32).min(self.height - 1);
+        let top = self.texel(column(x0), row(y0)).lerp(self.texel(column(x0 + 1.0), row(y0)), fx);
+        let bottom = self.texel(column(x0), row(y0 + 1.0)).lerp(self.texel(column(x0 + 1.0), row(y0 + 1.0)), fx);
+        top.lerp(bottom, fy)
+    }
+
+    // The map at another size, averaging the texels covered by each new one when it shrinks
+    pub fn resized(&self, width: u32, height: u32) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        if width >= self.width || height >= self.height {
+            return Self::from_fn(width, height, |direction| self.sample(direction));
+        }
+        let texels = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let (x0, x1) = (x * self.width / width, (x + 1) * self.width / width);
+                let (y0, y1) = (y * self.height / height, (y + 1) * self.height / height);
+                let mut sum = Vec3::ZERO;
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        sum += self.texel(sx, sy);
+                    }
+                }
+                sum / ((x1 - x0) * (y1 - y0)) as f32
+            })
+            .collect();
+        Self { width, height, texels }
+    }
+
+    // Direction, solid angle and radiance of every texel
+    fn texel_directions(&self) -> impl Iterator<Item = (Vec3, f32, Vec3)> + '_ {
+        let texel_size = (2.0 * PI / self.width as f32) * (PI / self.height as f32);
+        (0..self.width * self.height).map(move |i| {
+            let v = ((i / self.width) as f32 + 0.5) / self.height as f32;
+            let u = ((i % self.width) as f32 + 0.5) / self.width as f32;
+            (direction(u, v), texel_size * (v * PI).sin(), self.texels[i as usize])
+        })
+    }
+}
+
+// Unit direction of the equirectangular coordinates
+fn direction(u: f32, v: f32) -> Vec3 {
+    let (sin_theta, cos_theta) = (v * PI).sin_cos();
+    let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+    Vec3::new(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi)
+}
+
+// Equirectangular coordinates of the direction
+pub fn equirect_uv(direction: Vec3) -> (f32, f32) {
+    let d = direction.normalized();
+    let u = d.x.atan2(-d.z) / (2.0 * PI);
+    (u.rem_euclid(1.0), d.y.clamp(-1.0, 1.0).acos() / PI)
+}
+
+// Prefiltered specular copies, for roughness 0 to 1 in even steps
+const SPECULAR_LEVELS: usize = 5;
+const BRDF_TABLE_SIZE: usize = 32;
+
+pub struct ImageBasedLight {
+    irradiance: EquirectMap,
+    specular: Vec<EquirectMap>,
+    // Scale and bias of F0 per cos(view angle) column and roughness row
+    brdf: Vec<(f32, f32)>,
+}
+
+impl ImageBasedLight {
+    // Precomputes the lighting, `samples` GGX samples per prefiltered texel and table entry
+    pub fn new(environment: &EquirectMap, samples: u32) -> Self {
+        let samples = samples.max(1);
+        let base = environment.resized(128, 64);
+
+        // Brute force cosine convolution over a small copy, the irradiance varies slowly
+        let small = base.resized(64, 32);
+        let irradiance = EquirectMap::from_fn(32, 16, |normal| {
+            let mut sum = Vec3::ZERO;
+            for (direction, solid_angle, radiance) in small.texel_directions() {
+                let cosine = normal.dot(direction);
+                if cosine > 0.0 {
+                    sum += radiance * (cosine * solid_angle);
+                }
+            }
+            sum / PI
+        });
+
+        let specular = (0..SPECULAR_LEVELS)
+            .map(|level| {
+                let roughness = level as f32 / (SPECULAR_LEVELS - 1) as f32;
+                if level == 0 {
+                    return base.clone();
+                }
+                // Rougher levels are blurrier, so they get by with fewer texels
+                let source = base.resized(128 >> level.min(2), 64 >> level.min(2));
+                EquirectMap::from_fn(source.width(), source.height(), |normal| prefilter(&source, normal, roughness, samples))
+            })
+            .collect();
+
+        let mut brdf = Vec::with_capacity(BRDF_TABLE_SIZE * BRDF_TABLE_SIZE);
+        for row in 0..BRDF_TABLE_SIZE {
+            let roughness = (row as f32 + 0.5) / BRDF_TABLE_SIZE as f32;
+            for column in 0..BRDF_TABLE_SIZE {
+                let n_dot_v = (column as f32 + 0.5) / BRDF_TABLE_SIZE as f32;
+                brdf.push(integrate_brdf(n_dot_v, roughness, samples));
+            }
+        }
+
+        Self { irradiance, specular, brdf }
+    }
+
+    // Irradiance around the normal, divided by pi, so a white diffuse surface reflects this
+    pub fn irradiance(&self, normal: Vec3) -> Vec3 {
+        self.irradiance.sample(normal)
+    }
+
+    // Environment light arriving along the reflected direction, blurred for the roughness
+    pub fn prefiltered(&self, direction: Vec3, roughness: f32) -> Vec3 {
+        let level = roughness.clamp(0.0, 1.0) * (SPECULAR_LEVELS - 1) as f32;
+        let (low, t) = (level.floor() as usize, level.fract());
+        let color = self.specular[low].sample(direction);
+        if t > 0.0 {
+            color.lerp(self.specular[low + 1].sample(direction), t)
+        } else {
+            color
+        }
+    }
+
+    // Scale and bias applied to F0 by the split sum, bilinear in the table
+    pub fn brdf(&self, n_dot_v: f32, roughness: f32) -> (f32, f32) {
+        let size = BRDF_TABLE_SIZE as f32;
+        let x = (n_dot_v * size - 0.5).clamp(0.0, size - 1.0);
+        let y = (roughness * size - 0.5).clamp(0.0, size - 1.0);
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(BRDF_TABLE_SIZE - 1), (y0 + 1).min(BRDF_TABLE_SIZE - 1));
+        let (fx, fy) = (x.fract(), y.fract());
+        let at = |x: usize, y: usize| self.brdf[y * BRDF_TABLE_SIZE + x];
+        let mix = |a: (f32, f32), b: (f32, f32), t: f32| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+        mix(mix(at(x0, y0), at(x1, y0), fx), mix(at(x0, y1), at(x1, y1), fx), fy)
+    }
+}
+
+// Point i of n of the Hammersley set in the unit square
+fn hammersley(i: u32, n: u32) -> (f32, f32) {
+    (i as f32 / n as f32, i.reverse_bits() as f32 / 4_294_967_296.0)
+}
+
+// Half vector around the normal for GGX importance sampling
+fn sample_ggx(xi: (f32, f32), normal: Vec3, roughness: f32) -> Vec3 {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.0;
+    let cos_theta = ((1.0 - xi.1) / (1.0 + (a * a - 1.0) * xi.1)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    let helper = if normal.y.abs() < 0.999 { Vec3::UP } else { Vec3::new(1.0, 0.0, 0.0) };
+    let tangent = helper.cross(normal).normalized();
+    let bitangent = normal.cross(tangent);
+    (tangent * (phi.cos() * sin_theta) + bitangent * (phi.sin() * sin_theta) + normal * cos_theta).normalized()
+}
+
+// GGX filtered environment, assuming the view and reflection along the normal
+fn prefilter(source: &EquirectMap, normal: Vec3, roughness: f32, samples: u32) -> Vec3 {
+    let (mut sum, mut weight) = (Vec3::ZERO, 0.0);
+    for i in 0..samples {
+        let half = sample_ggx(hammersley(i, samples), normal, roughness);
+        let light = half * (2.0 * normal.dot(half)) - normal;
+        let n_dot_l = normal.dot(light);
+        if n_dot_l > 0.0 {
+            sum += source.sample(light) * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    if weight > 0.0 {
+        sum / weight
+    } else {
+        source.sample(normal)
+    }
+}
+
+// Smith geometry term with the remapping for image based lighting
+fn geometry_ibl(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    let schlick = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+    schlick(n_dot_v) * schlick(n_dot_l)
+}
+
+fn integrate_brdf(n_dot_v: f32, roughness: f32, samples: u32) -> (f32, f32) {
+    let view = Vec3::new((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+    let normal = Vec3::new(0.0, 0.0, 1.0);
+    let (mut scale, mut bias) = (0.0, 0.0);
+    for i in 0..samples {
+        let half = sample_ggx(hammersley(i, samples), normal, roughness);
+        let light = half * (2.0 * view.dot(half)) - view;
+        let (n_dot_l, n_dot_h, v_dot_h) = (light.z, half.z.max(0.0), view.dot(half).max(0.0));
+        if n_dot_l > 0.0 {
+            let visibility = geometry_ibl(n_dot_v, n_dot_l, roughness) * v_dot_h / (n_dot_h * n_dot_v).max(1e-6);
+            let fresnel = (1.0 - v_dot_h).powi(5);
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+    (scale / samples as f32, bias / samples as f32)
+}
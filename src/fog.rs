@@ -0,0 +1,177 @@
+use crate::light::{PointLight, ShadingModel, Surface};
+use crate::math::Vec3;
+
+// Fog, blended over the shaded color of every fragment.
+//
+// Distance fog thickens with the interpolated view depth of the fragment, either linearly
+// between a start and an end depth, or exponentially with a density. Height fog is densest
+// low down and thins out exponentially going up, and its amount is integrated along the ray
+// from the eye, so looking down into a foggy valley differs from looking across it. Both
+// give the fraction of the surface color that gets through, which multiply when combined.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FogMode {
+    // Clear before the start depth, opaque beyond the end depth
+    Linear { start: f32, end: f32 },
+    // Transmittance exp(-density * depth)
+    Exponential { density: f32 },
+    // Transmittance exp(-(density * depth)^2), clear for longer and then thickening quickly
+    ExponentialSquared { density: f32 },
+}
+
+impl FogMode {
+    // Fraction of the light from the given view depth reaching the eye
+    pub fn transmittance(self, view_depth: f32) -> f32 {
+        let depth = view_depth.max(0.0);
+        match self {
+            FogMode::Linear { start, end } => ((end - depth) / (end - start).max(1e-6)).clamp(0.0, 1.0),
+            FogMode::Exponential { density } => (-density * depth).exp(),
+            FogMode::ExponentialSquared { density } => (-(density * depth).powi(2)).exp(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeightFog {
+    // Height at which the fog has its full density
+    pub base: f32,
+    pub density: f32,
+    // How quickly the density drops per unit of height above the base
+    pub falloff: f32,
+}
+
+impl HeightFog {
+    // Fraction of the light from the position reaching the eye, from the density integrated along the ray
+    pub fn transmittance(&self, eye: Vec3, position: Vec3) -> f32 {
+        let distance = (position - eye).length();
+        let rise = position.y - eye.y;
+        let at_eye = self.density * (-self.falloff * (eye.y - self.base)).exp();
+        // Average of exp(-falloff * h) over the heights the ray passes, relative to the eye
+        let k = self.falloff * rise;
+        let average = if k.abs() > 1e-4 { (1.0 - (-k).exp()) / k } else { 1.0 - k / 2.0 };
+        (-at_eye * average * distance).exp()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    // Linear light color of the fog
+    pub color: Vec3,
+    pub mode: Option<FogMode>,
+    pub height: Option<HeightFog>,
+}
+
+impl Fog {
+    pub fn new(color: Vec3, mode: FogMode) -> Self {
+        Self { color, mode: Some(mode), height: None }
+    }
+
+    pub fn with_height(self, height: HeightFog) -> Self {
+        Self { height: Some(height), ..self }
+    }
+
+    pub fn transmittance(&self, surface: &Surface, eye: Vec3) -> f32 {
+        let distance = self.mode.map_or(1.0, |mode| mode.transmittance(surface.view_depth));
+        let height = self.height.map_or(1.0, |height| height.transmittance(eye, surface.position));
+        distance * height
+    }
+
+    // Blends the fog over the linear light color of the surface
+    pub fn apply(&self, color: Vec3, surface: &Surface, eye: Vec3) -> Vec3 {
+        self.color.lerp(color, self.transmittance(surface, eye))
+    }
+}
+
+// A shading model with fog blended over its result, so forward and deferred shading get it alike
+#[derive(Clone, Copy)]
+pub struct Fogged<'a> {
+    pub shading: &'a dyn ShadingModel,
+    pub fog: Fog,
+}
+
+impl ShadingModel for Fogged<'_> {
+    fn shade(&self, surface: &Surface, lights: &[PointLight], eye: Vec3, ambient_visibility: f32) -> Vec3 {
+        self.fog.apply(self.shading.shade(surface, lights, eye, ambient_visibility), surface, eye)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [FogMode; 3] = [
+        FogMode::Linear { start: 2.0, end: 10.0 },
+        FogMode::Exponential { density: 0.5 },
+        FogMode::ExponentialSquared { density: 0.5 },
+    ];
+
+    #[test]
+    fn every_mode_is_clear_up_close_and_opaque_far_away() {
+        for mode in MODES {
+            assert_eq!(mode.transmittance(0.0), 1.0, "{:?}", mode);
+            assert_eq!(mode.transmittance(-1.0), 1.0, "{:?}", mode);
+            assert!(mode.transmittance(100.0) < 1e-6, "{:?}", mode);
+            assert!(mode.transmittance(3.0) < mode.transmittance(2.5), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn linear_fog_ramps_between_start_and_end() {
+        let mode = FogMode::Linear { start: 2.0, end: 10.0 };
+        assert_eq!(mode.transmittance(2.0), 1.0);
+        assert!((mode.transmittance(6.0) - 0.5).abs() < 1e-6);
+        assert_eq!(mode.transmittance(10.0), 0.0);
+    }
+
+    #[test]
+    fn exponential_modes_follow_their_curves() {
+        assert!((FogMode::Exponential { density: 0.5 }.transmittance(2.0) - (-1.0f32).exp()).abs() < 1e-6);
+        let squared = FogMode::ExponentialSquared { density: 0.5 };
+        assert!((squared.transmittance(2.0) - (-1.0f32).exp()).abs() < 1e-6);
+        // Clearer than plain exponential fog before density * depth reaches 1, thicker after
+        assert!(squared.transmittance(1.0) > FogMode::Exponential { density: 0.5 }.transmittance(1.0));
+        assert!(squared.transmittance(4.0) < FogMode::Exponential { density: 0.5 }.transmittance(4.0));
+    }
+
+    #[test]
+    fn height_fog_thins_out_going_up() {
+        let fog = HeightFog { base: 0.0, density: 0.2, falloff: 0.5 };
+        let across = |y: f32| fog.transmittance(Vec3::new(0.0, y, 0.0), Vec3::new(10.0, y, 0.0));
+        // A level ray sees the density at its height all the way
+        for y in [0.0f32, 1.0, 4.0] {
+            let expected = (-0.2 * (-0.5 * y).exp() * 10.0).exp();
+            assert!((across(y) - expected).abs() < 1e-5, "{} vs {}", across(y), expected);
+        }
+        assert!(across(0.0) < across(1.0) && across(1.0) < across(4.0));
+        assert_eq!(fog.transmittance(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0)), 1.0);
+    }
+
+    #[test]
+    fn height_fog_integrates_the_density_along_the_ray() {
+        let fog = HeightFog { base: 1.0, density: 0.3, falloff: 0.8 };
+        let (eye, position) = (Vec3::new(0.0, 5.0, 0.0), Vec3::new(6.0, 0.0, 2.0));
+        let steps = 10_000;
+        let step = (position - eye).length() / steps as f32;
+        let optical_depth: f32 = (0..steps)
+            .map(|i| {
+                let y = eye.y + (position.y - eye.y) * (i as f32 + 0.5) / steps as f32;
+                fog.density * (-fog.falloff * (y - fog.base)).exp() * step
+            })
+            .sum();
+        let expected = (-optical_depth).exp();
+        assert!((fog.transmittance(eye, position) - expected).abs() < 1e-4);
+        // The same path lets as much through either way, and rising out of the fog lets more through
+        assert!((fog.transmittance(eye, position) - fog.transmittance(position, eye)).abs() < 1e-5);
+        assert!(fog.transmittance(eye, position) < fog.transmittance(eye, Vec3::new(6.0, 10.0, 2.0)));
+    }
+
+    #[test]
+    fn fog_blends_toward_its_color_with_depth() {
+        let fog = Fog::new(Vec3::new(0.5, 0.6, 0.7), FogMode::Exponential { density: 0.5 });
+        let color = Vec3::new(1.0, 0.0, 0.0);
+        let near = Surface { view_depth: 0.0, ..Surface::default() };
+        let far = Surface { view_depth: 100.0, ..Surface::default() };
+        assert_eq!(fog.apply(color, &near, Vec3::ZERO), color);
+        assert!((fog.apply(color, &far, Vec3::ZERO) - fog.color).length() < 1e-5);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::abuffer::{ABuffer, ABufferStats};
+use crate::blend::BlendState;
+use crate::dither::{quantize, DitherMethod, Palette};
+use crate::hdr::{hdr_color, HdrColor, ToneMapping};
+use crate::post::PostImage;
+use crate::state::RenderState;
+use sdl2::pixels::Color;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Software render target with a color, a depth and an 8 bit stencil value per pixel. Primitives
+// write whole `resolution` sized cells given by their top left corner, clipped to the buffer.
+// An A-buffer for order independent transparency and an HDR color buffer can be attached on demand.
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    color: Vec<Color>,
+    depth: Vec<f32>,
+    stencil: Vec<u8>,
+    a_buffer: Option<ABuffer>,
+    hdr: Option<Vec<HdrColor>>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let size = (width * height) as usize;
+        Self {
+            width,
+            height,
+            color: vec![Color::RGBA(0, 0, 0, 255); size],
+            depth: vec![f32::INFINITY; size],
+            stencil: vec![0; size],
+            a_buffer: None,
+            hdr: None,
+        }
+    }
+
+    // With an HDR color buffer every color write goes to it in linear light as f32 instead of
+    // to the 8 bit colors, which are only filled by `resolve_hdr`. Fragments are blended in
+    // linear light and the A-buffer is bypassed.
+    pub fn set_hdr(&mut self, enabled: bool) {
+        if enabled != self.hdr.is_some() {
+            self.hdr = enabled.then(|| vec![[0.0, 0.0, 0.0, 1.0]; self.color.len()]);
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        self.hdr.is_some()
+    }
+
+    // Tone maps the HDR colors into the 8 bit colors for display and export
+    pub fn resolve_hdr(&mut self, tone_mapping: &ToneMapping) {
+        let Some(hdr) = &self.hdr else {
+            return;
+        };
+        let width = self.width as usize;
+        for (i, (color, value)) in self.color.iter_mut().zip(hdr).enumerate() {
+            *color = tone_mapping.map(*value, (i % width) as u32, (i / width) as u32);
+        }
+    }
+
+    // Attaches an A-buffer holding up to `max_fragments` translucent fragments per frame,
+    // None detaches it. Without one, A-buffer fragments are blended right away.
+    pub fn set_a_buffer(&mut self, max_fragments: Option<usize>) {
+        let current = self.a_buffer.as_ref().map(|a_buffer| a_buffer.max_fragments());
+        if current != max_fragments {
+            self.a_buffer = max_fragments.map(|max| ABuffer::new(self.width, self.height, max));
+        }
+    }
+
+    // Composites the stored translucent fragments into the color buffer
+    pub fn resolve_a_buffer(&mut self) -> Option<ABufferStats> {
+        let a_buffer = self.a_buffer.as_mut()?;
+        Some(a_buffer.resolve(&mut self.color, &self.depth))
+    }
+
+    // Reduces the 8 bit colors to the palette, after any HDR resolve
+    pub fn quantize(&mut self, palette: &Palette, method: DitherMethod) {
+        quantize(&mut self.color, self.width as usize, palette, method);
+    }
+
+    // The 8 bit colors for post processing passes
+    pub fn post_image(&mut self) -> PostImage<'_> {
+        PostImage { width: self.width as usize, height: self.height as usize, pixels: &mut self.color }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        self.color.fill(color);
+        if let Some(hdr) = self.hdr.as_mut() {
+            hdr.fill(hdr_color(color, 1.0));
+        }
+        self.depth.fill(f32::INFINITY);
+        self.stencil.fill(0);
+        if let Some(a_buffer) = self.a_buffer.as_mut() {
+            a_buffer.clear();
+        }
+    }
+
+    pub fn clear_stencil(&mut self, value: u8) {
+        self.stencil.fill(value);
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            None
+        } else {
+            Some((y as u32 * self.width + x as u32) as usize)
+        }
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Color> {
+        self.index(x, y).map(|i| self.color[i])
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if let Some(i) = self.index(x, y) {
+            self.color[i] = color;
+        }
+    }
+
+    pub fn depth(&self, x: i32, y: i32) -> Option<f32> {
+        self.index(x, y).map(|i| self.depth[i])
+    }
+
+    pub fn stencil(&self, x: i32, y: i32) -> Option<u8> {
+        self.index(x, y).map(|i| self.stencil[i])
+    }
+
+    // Pixel rows of the cell or rectangle that lie inside the buffer, as index ranges
+    fn rows(&self, x: i32, y: i32, width: i32, height: i32) -> impl Iterator<Item = std::ops::Range<usize>> {
+        let (x0, x1) = (x.max(0), (x + width).min(self.width as i32));
+        let (y0, y1) = (y.max(0), (y + height).min(self.height as i32));
+        let stride = self.width as usize;
+        (y0..y1.max(y0)).filter(move |_| x0 < x1).map(move |row| {
+            let start = row as usize * stride;
+            start + x0 as usize..start + x1 as usize
+        })
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Color) {
+        for row in self.rows(x, y, width, height) {
+            match self.hdr.as_mut() {
+                Some(hdr) => hdr[row].fill(hdr_color(color, 1.0)),
+                None => self.color[row].fill(color),
+            }
+        }
+    }
+
+    // Writes a cell without depth, blended with what is already there
+    pub fn blend_cell(&mut self, x: i32, y: i32, resolution: i32, color: Color, blend: &BlendState) {
+        let value = hdr_color(color, 1.0);
+        for row in self.rows(x, y, resolution, resolution) {
+            match self.hdr.as_mut() {
+                Some(hdr) => hdr[row].iter_mut().for_each(|pixel| *pixel = blend.blend_hdr(value, *pixel)),
+                None => self.color[row].iter_mut().for_each(|pixel| *pixel = blend.blend(color, *pixel)),
+            }
+        }
+    }
+
+    // Writes a cell of a fragment at the given depth through the scissor, the stencil test,
+    // the depth test and blending. The stencil value is updated by the outcome of both tests.
+    pub fn write_fragment(&mut self, x: i32, y: i32, resolution: i32, color: Color, depth: f32, state: &RenderState) {
+        if self.hdr.is_some() {
+            self.write_hdr_fragment(x, y, resolution, hdr_color(color, 1.0), depth, state);
+            return;
+        }
+        for row in self.scissored_cell(x, y, resolution, state) {
+            for i in row {
+                if !self.test_pixel(i, depth, state) {
+                    continue;
+                }
+                if state.a_buffer {
+                    if let Some(a_buffer) = self.a_buffer.as_mut() {
+                        if a_buffer.insert(i, color, depth, state) {
+                            continue;
+                        }
+                    }
+                }
+                self.color[i] = state.blend.blend_in(color, self.color[i], state.color_space);
+            }
+        }
+    }
+
+    // Like write_fragment with a linear light color that may be brighter than 1, for the
+    // HDR color buffer. Without one it is clamped into the 8 bit colors.
+    pub fn write_hdr_fragment(&mut self, x: i32, y: i32, resolution: i32, color: HdrColor, depth: f32, state: &RenderState) {
+        if self.hdr.is_none() {
+            let clamped = ToneMapping::CLAMP.map(color, 0, 0);
+            self.write_fragment(x, y, resolution, clamped, depth, state);
+            return;
+        }
+        for row in self.scissored_cell(x, y, resolution, state) {
+            for i in row {
+                if self.test_pixel(i, depth, state) {
+                    if let Some(hdr) = self.hdr.as_mut() {
+                        hdr[i] = state.blend.blend_hdr(color, hdr[i]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn scissored_cell(&self, x: i32, y: i32, resolution: i32, state: &RenderState) -> impl Iterator<Item = std::ops::Range<usize>> {
+        let (mut x0, mut y0, mut x1, mut y1) = (x, y, x + resolution, y + resolution);
+        if let Some(scissor) = &state.scissor {
+            (x0, y0) = (x0.max(scissor.x_min), y0.max(scissor.y_min));
+            (x1, y1) = (x1.min(scissor.x_max + 1), y1.min(scissor.y_max + 1));
+        }
+        self.rows(x0, y0, x1 - x0, y1 - y0)
+    }
+
+    // Stencil and depth test of one pixel, updating both buffers.
+    // True when the color of the fragment is to be written.
+    fn test_pixel(&mut self, i: usize, depth: f32, state: &RenderState) -> bool {
+        if let Some(stencil) = &state.stencil {
+            let stored = self.stencil[i];
+            if !stencil.passes(stored) {
+                self.stencil[i] = stencil.update(stored, stencil.stencil_fail);
+                return false;
+            }
+            let depth_passes = !state.depth_test || depth <= self.depth[i];
+            let op = if depth_passes { stencil.pass } else { stencil.depth_fail };
+            self.stencil[i] = stencil.update(stored, op);
+        }
+        if state.depth_test && depth > self.depth[i] {
+            return false;
+        }
+        if state.depth_write {
+            self.depth[i] = depth;
+        }
+        state.color_write
+    }
+
+    // Packed RGB bytes for uploading to a streaming texture, `out` is reused between frames
+    pub fn write_rgb24(&self, out: &mut Vec<u8>) {
+        out.clear();
+        out.reserve(self.color.len() * 3);
+        for color in &self.color {
+            out.extend_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    // Saves the 8 bit colors as a binary PPM image
+    pub fn save_ppm(&self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut out = BufWriter::new(file);
+        let mut bytes = Vec::new();
+        self.write_rgb24(&mut bytes);
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)
+            .and_then(|_| out.write_all(&bytes))
+            .and_then(|_| out.flush())
+            .map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ppm_has_the_header_and_one_rgb_triple_per_pixel() {
+        let mut frame = Framebuffer::new(3, 2);
+        frame.write_fragment(2, 1, 1, Color::RGBA(10, 20, 30, 40), 0.5, &RenderState::OPAQUE);
+        let path = std::env::temp_dir().join(format!("rasterizer-test-{}.ppm", std::process::id()));
+        let path = path.to_str().unwrap();
+        frame.save_ppm(path).unwrap();
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 3 * 2 * 3);
+        assert_eq!(&bytes[bytes.len() - 6..], &[0, 0, 0, 10, 20, 30]);
+        assert!(frame.save_ppm("/nonexistent/directory/out.ppm").is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::post::{luma, PostImage, PostPass};
+use sdl2::pixels::Color;
+
+// Fast approximate anti-aliasing over the finished frame.
+//
+// Pixels whose local luma contrast is high enough are treated as lying on an edge. The edge
+// direction is taken from the luma gradients of the 3x3 neighbourhood, then the edge is walked
+// in both directions until its luma changes, which tells how far the pixel is from the end of
+// the stair step. The pixel is resampled with a bilinear fetch shifted across the edge by that
+// amount, or by the subpixel estimate when that is larger, which catches single pixel details.
+
+const SEARCH_STEPS: [f32; 10] = [1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 4.0, 8.0, 8.0];
+
+pub struct Fxaa {
+    // Minimum contrast relative to the brightest neighbour for a pixel to count as an edge
+    pub edge_threshold: f32,
+    // Minimum absolute contrast, keeps dark noise from being smoothed
+    pub edge_threshold_min: f32,
+    // Amount of subpixel smoothing, 0 turns it off
+    pub subpixel: f32,
+    values: Vec<[f32; 3]>,
+    lumas: Vec<f32>,
+}
+
+impl Fxaa {
+    pub fn new() -> Self {
+        Self { edge_threshold: 0.125, edge_threshold_min: 0.0312, subpixel: 0.75, values: Vec::new(), lumas: Vec::new() }
+    }
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Plane<'a, T> {
+    data: &'a [T],
+    width: usize,
+    height: usize,
+}
+
+impl<T: Copy> Plane<'_, T> {
+    fn at(&self, x: isize, y: isize) -> T {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.data[y * self.width + x]
+    }
+
+    // Bilinear fetch at a position in pixels, pixel centers are at +0.5
+    fn sample<F: Fn(T, T, f32) -> T>(&self, x: f32, y: f32, mix: F) -> T {
+        let (fx, fy) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (fx.floor(), fy.floor());
+        let (tx, ty) = (fx - x0, fy - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let top = mix(self.at(x0, y0), self.at(x0 + 1, y0), tx);
+        let bottom = mix(self.at(x0, y0 + 1), self.at(x0 + 1, y0 + 1), tx);
+        mix(top, bottom, ty)
+    }
+}
+
+fn mix_luma(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn mix_rgb(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * t)
+}
+
+impl PostPass for Fxaa {
+    fn name(&self) -> &str {
+        "FXAA"
+    }
+
+    fn apply(&mut self, image: &mut PostImage) {
+        let (width, height) = (image.width, image.height);
+        self.values.clear();
+        self.values.extend(image.pixels.iter().map(|p| [p.r as f32, p.g as f32, p.b as f32]));
+        self.lumas.clear();
+        self.lumas.extend(self.values.iter().map(|&value| luma(value) / 255.0));
+        let lumas = Plane { data: &self.lumas, width, height };
+        let values = Plane { data: &self.values, width, height };
+
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let (l, n, s, w, e) = (lumas.at(x, y), lumas.at(x, y - 1), lumas.at(x, y + 1), lumas.at(x - 1, y), lumas.at(x + 1, y));
+                let highest = l.max(n).max(s).max(w).max(e);
+                let lowest = l.min(n).min(s).min(w).min(e);
+                let range = highest - lowest;
+                if range < self.edge_threshold_min.max(highest * self.edge_threshold) {
+                    continue;
+                }
+                let (nw, ne, sw, se) = (lumas.at(x - 1, y - 1), lumas.at(x + 1, y - 1), lumas.at(x - 1, y + 1), lumas.at(x + 1, y + 1));
+
+                // How much the pixel stands out from its neighbourhood
+                let average = (2.0 * (n + s + w + e) + nw + ne + sw + se) / 12.0;
+                let t = ((average - l).abs() / range).clamp(0.0, 1.0);
+                let smooth = t * t * (3.0 - 2.0 * t);
+                let subpixel_blend = smooth * smooth * self.subpixel;
+
+                // A horizontal edge has its contrast across rows
+                let horizontal = 2.0 * (n + s - 2.0 * l).abs() + (ne + se - 2.0 * e).abs() + (nw + sw - 2.0 * w).abs()
+                    >= 2.0 * (e + w - 2.0 * l).abs() + (ne + nw - 2.0 * n).abs() + (se + sw - 2.0 * s).abs();
+                let (positive, negative) = if horizontal { (s, n) } else { (e, w) };
+                let (step, opposite) = if (positive - l).abs() >= (negative - l).abs() { (1.0, positive) } else { (-1.0, negative) };
+                let gradient = (opposite - l).abs();
+
+                // Walk along the edge, between the pixel and its opposite neighbour
+                let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+                let (ux, uy) = if horizontal { (cx, cy + step * 0.5) } else { (cx + step * 0.5, cy) };
+                let (dx, dy) = if horizontal { (1.0, 0.0) } else { (0.0, 1.0) };
+                let edge_luma = (l + opposite) * 0.5;
+                let threshold = gradient * 0.25;
+                let walk = |sign: f32| {
+                    let (mut px, mut py) = (ux + dx * sign, uy + dy * sign);
+                    let mut delta = lumas.sample(px, py, mix_luma) - edge_luma;
+                    for size in SEARCH_STEPS {
+                        if delta.abs() >= threshold {
+                            break;
+                        }
+                        px += dx * sign * size;
+                        py += dy * sign * size;
+                        delta = lumas.sample(px, py, mix_luma) - edge_luma;
+                    }
+                    let distance = if horizontal { (px - ux) * sign } else { (py - uy) * sign };
+                    (distance, delta)
+                };
+                let (positive_distance, positive_delta) = walk(1.0);
+                let (negative_distance, negative_delta) = walk(-1.0);
+
+                let (shortest, delta) = if positive_distance <= negative_distance {
+                    (positive_distance, positive_delta)
+                } else {
+                    (negative_distance, negative_delta)
+                };
+                // Only the side of the step the pixel is on gets blended
+                let edge_blend = if (delta >= 0.0) != (l - edge_luma >= 0.0) {
+                    0.5 - shortest / (positive_distance + negative_distance)
+                } else {
+                    0.0
+                };
+
+                let offset = edge_blend.max(subpixel_blend) * step;
+                let (sx, sy) = if horizontal { (cx, cy + offset) } else { (cx + offset, cy) };
+                let [r, g, b] = values.sample(sx, sy, mix_rgb).map(|c| c.round().clamp(0.0, 255.0) as u8);
+                let pixel = &mut image.pixels[y as usize * width + x as usize];
+                *pixel = Color::RGBA(r, g, b, pixel.a);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(fxaa: &mut Fxaa, pixels: &mut [Color], width: usize) {
+        let height = pixels.len() / width;
+        fxaa.apply(&mut PostImage { width, height, pixels });
+    }
+
+    #[test]
+    fn flat_images_are_unchanged() {
+        let mut fxaa = Fxaa::new();
+        for color in [Color::RGBA(0, 0, 0, 255), Color::RGBA(255, 255, 255, 255), Color::RGBA(40, 160, 90, 12)] {
+            let mut pixels = vec![color; 12 * 9];
+            run(&mut fxaa, &mut pixels, 12);
+            assert!(pixels.iter().all(|&pixel| pixel == color), "{:?}", color);
+        }
+        // A gentle ramp stays below the contrast threshold
+        let ramp: Vec<Color> = (0..12 * 9).map(|i| Color::RGB(100 + (i % 12) as u8, 100, 100)).collect();
+        let mut pixels = ramp.clone();
+        run(&mut fxaa, &mut pixels, 12);
+        assert_eq!(pixels, ramp);
+    }
+
+    #[test]
+    fn stair_steps_are_smoothed() {
+        // A shallow black over white slope
+        let (width, height) = (16, 8);
+        let mut pixels: Vec<Color> = (0..width * height)
+            .map(|i| if i / width < 2 + (i % width) / 4 { Color::RGB(0, 0, 0) } else { Color::RGB(255, 255, 255) })
+            .collect();
+        run(&mut Fxaa::new(), &mut pixels, width);
+        assert!(pixels.iter().any(|pixel| pixel.r > 0 && pixel.r < 255));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::color::{encode_srgb, srgb_to_linear};
+use sdl2::pixels::Color;
+
+// High dynamic range colors and tone mapping.
+//
+// An HDR color is RGBA in linear light as f32, the color channels are not limited to 1 so
+// bright lights and additive blending keep their energy instead of clipping. Before display
+// or export the frame is tone mapped back into [0, 1], encoded to sRGB and quantized to 8 bit,
+// optionally with dithering that trades the banding of smooth gradients for fine noise.
+
+pub type HdrColor = [f32; 4];
+
+// An 8 bit sRGB color in linear light, with the color channels scaled by `intensity`
+pub fn hdr_color(color: Color, intensity: f32) -> HdrColor {
+    [
+        srgb_to_linear(color.r) * intensity,
+        srgb_to_linear(color.g) * intensity,
+        srgb_to_linear(color.b) * intensity,
+        color.a as f32 / 255.0,
+    ]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    // Everything above 1 is cut off, what the 8 bit target does
+    Clamp,
+    // c / (1 + c)
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve
+    AcesFilmic,
+    // 1 - e^-c, like the response of film to exposure
+    Exposure,
+}
+
+impl ToneMapOperator {
+    pub fn apply(self, c: f32) -> f32 {
+        let c = c.max(0.0);
+        match self {
+            ToneMapOperator::Clamp => c.min(1.0),
+            ToneMapOperator::Reinhard => c / (1.0 + c),
+            ToneMapOperator::AcesFilmic => {
+                ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+            ToneMapOperator::Exposure => 1.0 - (-c).exp(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    // Scale applied to the linear color before the operator
+    pub exposure: f32,
+    pub dither: bool,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self { operator: ToneMapOperator::Reinhard, exposure: 1.0, dither: true }
+    }
+}
+
+impl ToneMapping {
+    pub const CLAMP: ToneMapping = ToneMapping { operator: ToneMapOperator::Clamp, exposure: 1.0, dither: false };
+
+    // The display color of the HDR color at pixel (x, y), the position only seeds the dither
+    pub fn map(&self, color: HdrColor, x: u32, y: u32) -> Color {
+        let offset = if self.dither { dither_offset(x, y) } else { 0.0 };
+        let byte = |v: f32| (v * 255.0 + offset).round().clamp(0.0, 255.0) as u8;
+        let channel = |c: f32| byte(encode_srgb(self.operator.apply(c * self.exposure)));
+        Color::RGBA(channel(color[0]), channel(color[1]), channel(color[2]), byte(color[3].clamp(0.0, 1.0)))
+    }
+}
+
+// Triangular noise in (-1, 1) of a quantization step, from two hashes of the pixel position
+fn dither_offset(x: u32, y: u32) -> f32 {
+    let hash = |seed: u32| {
+        let mut h = x.wrapping_mul(0x8da6_b343) ^ y.wrapping_mul(0xd816_3841) ^ seed;
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x2c1b_3c6d);
+        h ^= h >> 12;
+        (h & 0xffff) as f32 / 65536.0
+    };
+    hash(0x9e37_79b9) + hash(0x7f4a_7c15) - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_are_monotonic_and_bounded() {
+        for operator in [ToneMapOperator::Reinhard, ToneMapOperator::AcesFilmic] {
+            let values: Vec<f32> = (0..=4000).map(|i| operator.apply(i as f32 / 100.0)).collect();
+            assert_eq!(values[0], 0.0, "{:?}", operator);
+            assert!(values.windows(2).all(|pair| pair[1] >= pair[0]), "{:?} decreases", operator);
+            assert!(values.iter().all(|&v| (0.0..=1.0).contains(&v)), "{:?} leaves [0, 1]", operator);
+        }
+        // Reinhard never quite reaches white, so brighter input always shows
+        let reinhard: Vec<f32> = [0.5, 1.0, 4.0, 16.0].map(|c| ToneMapOperator::Reinhard.apply(c)).to_vec();
+        assert!(reinhard.windows(2).all(|pair| pair[1] > pair[0]) && reinhard[3] < 1.0);
+    }
+
+    #[test]
+    fn mapped_colors_keep_their_order() {
+        let mapping = ToneMapping { dither: false, ..ToneMapping::default() };
+        let mut previous = 0;
+        for i in 0..=200 {
+            let c = i as f32 / 20.0;
+            let red = mapping.map([c, 0.0, 0.0, 1.0], 0, 0).r;
+            assert!(red >= previous, "{} maps below a darker value", c);
+            previous = red;
+        }
+        assert_eq!(mapping.map([0.0, 0.0, 0.0, 0.5], 3, 4), Color::RGBA(0, 0, 0, 128));
+    }
+}
@@ -0,0 +1,33 @@
+pub mod abuffer;
+pub mod blend;
+pub mod camera;
+pub mod clip;
+pub mod color;
+pub mod cubemap;
+pub mod deferred;
+pub mod dither;
+pub mod environment;
+pub mod fog;
+pub mod framebuffer;
+pub mod fxaa;
+pub mod hdr;
+pub mod light;
+pub mod line;
+pub mod math;
+pub mod mesh;
+pub mod path;
+pub mod pbr;
+pub mod pipeline;
+pub mod post;
+pub mod raster;
+pub mod scanline;
+pub mod shapes;
+pub mod ssao;
+pub mod state;
+pub mod stencil;
+pub mod supersample;
+pub mod svg;
+pub mod texture;
+pub mod toon;
+pub mod triangulate;
+pub mod viewport;
@@ -0,0 +1,91 @@
+use crate::math::Vec3;
+
+// Lights and the Blinn-Phong lighting model, evaluated in linear light on world space data.
+// Physically based shading lives in pbr.
+
+// What shading needs to know about a point of a surface
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Surface {
+    pub position: Vec3,
+    // Unit length
+    pub normal: Vec3,
+    // Linear light color
+    pub albedo: Vec3,
+    // 0 for dielectrics, 1 for metals
+    pub metallic: f32,
+    // Perceptual roughness, 0 is a mirror
+    pub roughness: f32,
+    // Distance in front of the camera along its viewing direction
+    pub view_depth: f32,
+    // The tangent frame the normal was mapped in, along increasing u and v
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    // Linear light color, scaled by the intensity
+    pub color: Vec3,
+    pub intensity: f32,
+    // The light fades out smoothly to nothing at this distance
+    pub range: f32,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, color: Vec3, intensity: f32, range: f32) -> Self {
+        Self { position, color, intensity, range }
+    }
+
+    // Inverse square falloff, windowed so that it reaches 0 at the range
+    pub fn attenuation(&self, distance: f32) -> f32 {
+        let ratio = (distance / self.range).min(1.0);
+        let window = (1.0 - ratio.powi(4)).powi(2);
+        window / (distance * distance + 1.0)
+    }
+}
+
+// How light leaving a surface towards the eye is computed, shared by forward and deferred shading
+pub trait ShadingModel {
+    // `ambient_visibility` is the fraction of the ambient or environment light reaching the surface
+    fn shade(&self, surface: &Surface, lights: &[PointLight], eye: Vec3, ambient_visibility: f32) -> Vec3;
+}
+
+// The classic empirical model, which ignores metallic and roughness
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlinnPhong {
+    // Light reaching every point regardless of the lights
+    pub ambient: Vec3,
+    pub specular: f32,
+    pub shininess: f32,
+}
+
+impl Default for BlinnPhong {
+    fn default() -> Self {
+        Self { ambient: Vec3::splat(0.03), specular: 0.4, shininess: 48.0 }
+    }
+}
+
+impl ShadingModel for BlinnPhong {
+    fn shade(&self, surface: &Surface, lights: &[PointLight], eye: Vec3, ambient_visibility: f32) -> Vec3 {
+        let view = (eye - surface.position).normalized();
+        let mut result = self.ambient.mul_elements(surface.albedo) * ambient_visibility;
+        for light in lights {
+            let to_light = light.position - surface.position;
+            let distance = to_light.length();
+            if distance >= light.range {
+                continue;
+            }
+            let l = to_light / distance;
+            let n_dot_l = surface.normal.dot(l);
+            if n_dot_l <= 0.0 {
+                continue;
+            }
+            let half = (l + view).normalized();
+            let specular = self.specular * surface.normal.dot(half).max(0.0).powf(self.shininess);
+            let radiance = light.color * (light.intensity * light.attenuation(distance));
+            result += (surface.albedo * n_dot_l + Vec3::splat(specular * n_dot_l)).mul_elements(radiance);
+        }
+        result
+    }
+}
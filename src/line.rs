@@ -0,0 +1,38 @@
+use crate::color::interpolate_color;
+use sdl2::pixels::Color;
+
+// Drawing a line using Bresenham's line algorithm and interpolating colors based on the two vertices.
+// Every cell on the line is handed to `plot` with the position of its top left corner.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_bresenham_line<F: FnMut(i32, i32, Color)>(x1: i32, y1: i32, x2: i32, y2: i32, c1: Color, c2: Color, resolution: i32, mut plot: F) {
+    let dx = (x2 - x1).abs();
+    let dy = (y2 - y1).abs();
+    let sx = if x1 < x2 { resolution } else { -resolution };
+    let sy = if y1 < y2 { resolution } else { -resolution };
+    let mut err = dx - dy;
+
+    let mut x = x1;
+    let mut y = y1;
+
+    let total_distance = ((x2 - x1).pow(2) + (y2 - y1).pow(2)) as f32;
+
+    loop {
+        let t = if total_distance == 0.0 {0.0} else {((x - x1).pow(2) + (y - y1).pow(2)) as f32 / total_distance};
+        let color = interpolate_color(c1, c2, t);
+        plot(x - x % resolution, y - y % resolution, color);
+
+        if (x - x2).abs() < resolution && (y - y2).abs() < resolution {
+            break;
+        }
+
+        let e2 = 2 * err;
+        if e2 > -dy {
+            err -= dy;
+            x += sx;
+        }
+        if e2 < dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
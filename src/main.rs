@@ -1,11 +1,42 @@
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
 use sdl2::render::WindowCanvas;
 use std::time::{Duration, Instant};
 use std::f32::consts::PI;
-use std::collections::HashMap;
+use rasterizer::abuffer::ABufferStats;
+use rasterizer::blend::BlendState;
+use rasterizer::clip::{draw_clipped_line, ClipAlgorithm, ClipRect, LineClip};
+use rasterizer::camera::Camera;
+use rasterizer::color::{linear_rgb, premultiply, ColorSpace};
+use rasterizer::cubemap::CubeMap;
+use rasterizer::deferred::{GBuffer, GBufferChannel};
+use rasterizer::dither::{DitherMethod, Palette};
+use rasterizer::framebuffer::Framebuffer;
+use rasterizer::fog::{Fog, FogMode, Fogged, HeightFog};
+use rasterizer::fxaa::Fxaa;
+use rasterizer::hdr::{ToneMapOperator, ToneMapping};
+use rasterizer::environment::{EquirectMap, ImageBasedLight};
+use rasterizer::light::{BlinnPhong, PointLight, ShadingModel, Surface};
+use rasterizer::math::{Mat4, Vec3};
+use rasterizer::mesh::{load_obj, Mesh};
+use rasterizer::pbr::CookTorrance;
+use rasterizer::path::{fill_path, stroke_path, Path};
+use rasterizer::post::{Bloom, ColorGrade, GaussianBlur, Lut, PostChain, Sharpen, SobelEdges, Vignette};
+use rasterizer::pipeline::{Material, MeshRenderer};
+use rasterizer::raster::{sort_back_to_front, PolygonMode, Rasterizer, ScreenVertex};
+use rasterizer::scanline::{attrs_color, EdgeTable, FillRule, Span};
+use rasterizer::ssao::Ssao;
+use rasterizer::shapes::{draw_arc, draw_ellipse, fill_ellipse, fill_pie, Ellipse};
+use rasterizer::state::RenderState;
+use rasterizer::stencil::{CompareFunc, StencilState};
+use rasterizer::supersample::{DownsampleFilter, Supersampler};
+use rasterizer::svg::{load_svg, parse_svg, SvgDocument};
+use rasterizer::texture::Texture;
+use rasterizer::toon::{outline_shell, Toon};
+use rasterizer::triangulate::triangulate;
+use rasterizer::viewport::Viewport;
 
 // Custom UI : A slider for giving dynamic changes
 struct Slider {
@@ -65,38 +96,89 @@ impl Slider {
     }
 }
 
-// Custom UI : A structure to store interpolated points from Bresenham's line algorithm
-// Hashmap : { y: [(x, color)] }
-struct InterpolatedPoints {
-    points: HashMap<i32, Vec<(i32, Color)>>,
+// The demo scenes, cycled with Tab
+#[derive(Clone, Copy, PartialEq)]
+enum Scene {
+    Triangle,
+    Polygons,
+    Shapes,
+    Curves,
+    Svg,
+    Clipping,
+    Transparency,
+    Stencil,
+    Views,
+    Hdr,
+    Lighting,
 }
-impl InterpolatedPoints {
-    fn new() -> Self {
-        Self { points: HashMap::new() }
+impl Scene {
+    fn next(self) -> Self {
+        match self {
+            Scene::Triangle => Scene::Polygons,
+            Scene::Polygons => Scene::Shapes,
+            Scene::Shapes => Scene::Curves,
+            Scene::Curves => Scene::Svg,
+            Scene::Svg => Scene::Clipping,
+            Scene::Clipping => Scene::Transparency,
+            Scene::Transparency => Scene::Stencil,
+            Scene::Stencil => Scene::Views,
+            Scene::Views => Scene::Hdr,
+            Scene::Hdr => Scene::Lighting,
+            Scene::Lighting => Scene::Triangle,
+        }
     }
+}
 
-    fn add_point(&mut self, x: i32, y: i32, color: Color) {
-        self.points.entry(y).or_insert_with(Vec::new).push((x, color));
+// Blend modes for the transparency scene, cycled with B
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BlendMode {
+    Alpha,
+    Additive,
+    Multiply,
+    Premultiplied,
+}
+impl BlendMode {
+    fn next(self) -> Self {
+        match self {
+            BlendMode::Alpha => BlendMode::Additive,
+            BlendMode::Additive => BlendMode::Multiply,
+            BlendMode::Multiply => BlendMode::Premultiplied,
+            BlendMode::Premultiplied => BlendMode::Alpha,
+        }
     }
 
-    fn get_min_max_x(&self, y: i32) -> Option<((i32, Color), (i32, Color))> {
-        self.points.get(&y).map(|xs| {
-            let min_point = xs.iter().min_by_key(|(x, _)| x).unwrap();
-            let max_point = xs.iter().max_by_key(|(x, _)| x).unwrap();
-            (*min_point, *max_point)
-        })
+    fn state(self) -> BlendState {
+        match self {
+            BlendMode::Alpha => BlendState::ALPHA,
+            BlendMode::Additive => BlendState::ADDITIVE,
+            BlendMode::Multiply => BlendState::MULTIPLY,
+            BlendMode::Premultiplied => BlendState::PREMULTIPLIED,
+        }
     }
 }
 
+// 2D drawing is layered in drawing order, alpha blended and without depth
+const FLAT_2D: RenderState = RenderState { blend: BlendState::ALPHA, depth_test: false, depth_write: false, ..RenderState::OPAQUE };
+
+// Wireframe overlay lines are pulled this far towards the viewer, so they win the depth test
+// against the surfaces they lie on
+const WIREFRAME_DEPTH_BIAS: f32 = 1e-4;
+
+// Width of the toon outlines in world units
+const OUTLINE_WIDTH: f32 = 0.04;
+
+// Memory cap of the A-buffer, in fragments of 16 bytes
+const A_BUFFER_FRAGMENTS: usize = 1 << 20;
 
 // A background grid to help visualize the 2d space
-fn draw_grid(canvas: &mut WindowCanvas, width: u32, height: u32, resolution: i32) {
-    canvas.set_draw_color(Color::RGB(50, 50, 50));
-    for x in (0..width as i32).step_by(resolution as usize) {
-        canvas.draw_line((x, 0), (x, height as i32)).unwrap();
+fn draw_grid(frame: &mut Framebuffer, resolution: i32) {
+    let (width, height) = (frame.width() as i32, frame.height() as i32);
+    let color = Color::RGB(50, 50, 50);
+    for x in (0..width).step_by(resolution as usize) {
+        frame.fill_rect(x, 0, 1, height, color);
     }
-    for y in (0..height as i32).step_by(resolution as usize) {
-        canvas.draw_line((0, y), (width as i32, y)).unwrap();
+    for y in (0..height).step_by(resolution as usize) {
+        frame.fill_rect(0, y, width, 1, color);
     }
 }
 
@@ -123,126 +205,834 @@ fn rotate_y(point: &Vertex, angle: f32) -> Vertex {
     }
 }
 
-// Simple Linear Interpolation for color
-fn interpolate_color(c1: Color, c2: Color , t: f32) -> Color {
-    let r = (c1.r as f32 * (1.0 - t) + c2.r as f32 * t) as u8;
-    let g = (c1.g as f32 * (1.0 - t) + c2.g as f32 * t) as u8;
-    let b = (c1.b as f32 * (1.0 - t) + c2.b as f32 * t) as u8;
-    Color::RGB(r, g, b)
+// Drawing one span of a path fill, stepping the color from cell to cell
+fn draw_span(frame: &mut Framebuffer, span: &Span<4>, resolution: i32) {
+    let mut attrs = span.attrs;
+    for x in (span.x_start..span.x_end).step_by(resolution as usize) {
+        frame.blend_cell(x, span.y, resolution, attrs_color(&attrs), &BlendState::ALPHA);
+        for (value, step) in attrs.iter_mut().zip(span.step) {
+            *value += step;
+        }
+    }
+}
+
+// Orthographic projection of demo coordinates (pixels, y down) into a viewport. `extent` units
+// from the center reach its top and bottom border, depth covers -extent to extent.
+fn project(viewport: &Viewport, point: &Vertex, extent: f32) -> (f32, f32, f32) {
+    viewport.to_window(
+        point.x as f32 / (extent * viewport.aspect()),
+        -point.y as f32 / extent,
+        point.z as f32 / extent,
+    )
+}
+
+fn screen_vertex(point: &Point3D) -> ScreenVertex {
+    ScreenVertex::new(point.vertex.x as f32, point.vertex.y as f32, point.vertex.z as f32, point.color)
 }
 
-// Drawing a line using Bresenham's line algorithm and interpolating colors based on the two vertices
-fn draw_bresenham_line(interpolated_points: &mut InterpolatedPoints, canvas: &mut WindowCanvas, x1: i32, y1: i32, x2: i32, y2: i32, c1: Color, c2: Color, _z1: i32, _z2: i32, resolution: i32 ) {
-    
-    
-    let dx = (x2 - x1).abs();
-    let dy = (y2 - y1).abs();
-    let sx = if x1 < x2 { resolution } else { -resolution };
-    let sy = if y1 < y2 { resolution } else { -resolution };
-    let mut err = dx - dy;
+// Splitting the shape into triangles (first contour outer, the rest holes) and filling them one by one,
+// the triangle outlines are drawn on top to show the triangulation
+fn fill_triangulated(
+    frame: &mut Framebuffer,
+    contours: &[Vec<Point3D>],
+    clip: &LineClip,
+    resolution: i32,
+    rasterizer: &mut Rasterizer,
+) {
+    let Some((outer, holes)) = contours.split_first() else {
+        return;
+    };
+    let to_points = |contour: &Vec<Point3D>| -> Vec<(f32, f32)> {
+        contour.iter().map(|p| (p.vertex.x as f32, p.vertex.y as f32)).collect()
+    };
+    let hole_points: Vec<Vec<(f32, f32)>> = holes.iter().map(to_points).collect();
+    let hole_slices: Vec<&[(f32, f32)]> = hole_points.iter().map(|h| h.as_slice()).collect();
+    let triangles = triangulate(&to_points(outer), &hole_slices);
+
+    let points: Vec<&Point3D> = contours.iter().flatten().collect();
+    for [a, b, c] in triangles {
+        let triangle = [points[a], points[b], points[c]].map(screen_vertex);
+        rasterizer.fill_triangle(frame, &triangle, None, &FLAT_2D, resolution);
+        for (p1, p2) in [(a, b), (b, c), (c, a)] {
+            let (p1, p2) = (points[p1], points[p2]);
+            draw_clipped_line(clip, p1.vertex.x, p1.vertex.y, p2.vertex.x, p2.vertex.y,
+                Color::RGB(255, 255, 255), Color::RGB(255, 255, 255), resolution,
+                |x, y, color| plot_cell(frame, x, y, color, resolution));
+        }
+    }
+}
 
-    let mut x = x1;
-    let mut y = y1;
+// Filling a single grid cell, the callback used by the line and curve primitives
+fn plot_cell(frame: &mut Framebuffer, x: i32, y: i32, color: Color, resolution: i32) {
+    frame.blend_cell(x, y, resolution, color, &BlendState::ALPHA);
+}
 
-    let total_distance = ((x2 - x1).pow(2) + (y2 - y1).pow(2)) as f32;
+// Demo shapes for the curve scene : circle and ellipse outlines, a filled ellipse,
+// and an arc and a pie slice whose opening follows the rotation slider
+fn draw_shapes(frame: &mut Framebuffer, rotation_angle: f32, center_x: i32, center_y: i32, resolution: i32) {
+    let mut plot = |x: i32, y: i32, color: Color| plot_cell(frame, x, y, color, resolution);
+    let (start, end) = (rotation_angle, rotation_angle + 1.5 * PI);
 
-    loop {
-        
-        let t = ((x - x1).pow(2) + (y - y1).pow(2)) as f32 / total_distance;
-        let color = interpolate_color(c1, c2, t);
-        interpolated_points.add_point(x - x % resolution, y - y % resolution, color);
-        
-        // let z = z1 * (1.0 - t) + z2 * t;
-        // let shadow_factor = (1.0 + z).max(0.0).min(1.0);
-        // let shadowed_color = darken_color(color, shadow_factor);
-        canvas.set_draw_color(color);
-        canvas.fill_rect(Rect::new(x - x % resolution, y - y % resolution, resolution as u32, resolution as u32)).unwrap();
+    draw_ellipse(&Ellipse::circle(center_x - 250, center_y - 80, 110), resolution,
+        (Color::RGB(255, 0, 0), Color::RGB(0, 0, 255)), &mut plot);
+    fill_ellipse(&Ellipse::new(center_x, center_y - 80, 120, 70), resolution,
+        (Color::RGB(255, 255, 255), Color::RGB(120, 0, 200)), &mut plot);
+    draw_ellipse(&Ellipse::new(center_x + 250, center_y - 80, 90, 130), resolution,
+        (Color::RGB(0, 255, 0), Color::RGB(255, 255, 0)), &mut plot);
+    draw_arc(&Ellipse::circle(center_x - 150, center_y + 170, 110), start, end, resolution,
+        (Color::RGB(0, 255, 255), Color::RGB(255, 0, 255)), &mut plot);
+    fill_pie(&Ellipse::circle(center_x + 150, center_y + 170, 110), start, end, resolution,
+        (Color::RGB(255, 255, 0), Color::RGB(255, 80, 0)), &mut plot);
+}
 
+// Demo paths for the curve scene : a filled heart made of cubic curves, a stroked cubic S-curve
+// and a quadratic arch, with their control polygons. The curves are flattened within `tolerance` pixels.
+fn draw_curves(
+    frame: &mut Framebuffer,
+    center_x: f32,
+    center_y: f32,
+    tolerance: f32,
+    clip: &LineClip,
+    resolution: i32,
+    edge_table: &mut EdgeTable<4>,
+) {
+    let mut heart = Path::new();
+    heart.move_to(center_x + 180.0, center_y - 60.0)
+        .cubic_to(center_x + 180.0, center_y - 140.0, center_x + 60.0, center_y - 140.0, center_x + 60.0, center_y - 60.0)
+        .cubic_to(center_x + 60.0, center_y, center_x + 150.0, center_y + 60.0, center_x + 180.0, center_y + 120.0)
+        .cubic_to(center_x + 210.0, center_y + 60.0, center_x + 300.0, center_y, center_x + 300.0, center_y - 60.0)
+        .cubic_to(center_x + 300.0, center_y - 140.0, center_x + 180.0, center_y - 140.0, center_x + 180.0, center_y - 60.0)
+        .close();
+    fill_path(&heart, tolerance, FillRule::NonZero, Color::RGB(220, 30, 80), resolution, edge_table,
+        |span| draw_span(frame, span, resolution));
+    stroke_path(&heart, tolerance, clip, resolution, (Color::RGB(255, 255, 255), Color::RGB(255, 150, 150)),
+        |x, y, color| plot_cell(frame, x, y, color, resolution));
 
-        if (x - x2).abs() < resolution && (y - y2).abs() < resolution {
-            break;
-        }
+    let cubic = [(center_x - 320.0, center_y + 50.0), (center_x - 250.0, center_y - 250.0),
+        (center_x - 100.0, center_y + 250.0), (center_x - 30.0, center_y - 50.0)];
+    let quadratic = [(center_x - 300.0, center_y + 220.0), (center_x - 50.0, center_y - 20.0),
+        (center_x + 200.0, center_y + 220.0)];
 
-        let e2 = 2 * err;
-        if e2 > -dy {
-            err -= dy;
-            x += sx;
+    let mut control = Path::new();
+    control.move_to(cubic[0].0, cubic[0].1);
+    for point in &cubic[1..] {
+        control.line_to(point.0, point.1);
+    }
+    control.move_to(quadratic[0].0, quadratic[0].1);
+    for point in &quadratic[1..] {
+        control.line_to(point.0, point.1);
+    }
+    stroke_path(&control, tolerance, clip, resolution, (Color::RGB(70, 70, 70), Color::RGB(70, 70, 70)),
+        |x, y, color| plot_cell(frame, x, y, color, resolution));
+
+    let mut curves = Path::new();
+    curves.move_to(cubic[0].0, cubic[0].1)
+        .cubic_to(cubic[1].0, cubic[1].1, cubic[2].0, cubic[2].1, cubic[3].0, cubic[3].1)
+        .move_to(quadratic[0].0, quadratic[0].1)
+        .quad_to(quadratic[1].0, quadratic[1].1, quadratic[2].0, quadratic[2].1);
+    stroke_path(&curves, tolerance, clip, resolution, (Color::RGB(0, 255, 255), Color::RGB(255, 255, 0)),
+        |x, y, color| plot_cell(frame, x, y, color, resolution));
+}
+
+// Rendering the shapes of an SVG document, fills first and the stroke on top
+fn draw_svg(
+    frame: &mut Framebuffer,
+    document: &SvgDocument,
+    tolerance: f32,
+    clip: &LineClip,
+    resolution: i32,
+    edge_table: &mut EdgeTable<4>,
+) {
+    for shape in &document.shapes {
+        if let Some(fill) = shape.fill {
+            fill_path(&shape.path, tolerance, shape.fill_rule, fill, resolution, edge_table,
+                |span| draw_span(frame, span, resolution));
         }
-        if e2 < dx {
-            err += dx;
-            y += sy;
+        if let Some(stroke) = shape.stroke {
+            stroke_path(&shape.path, tolerance, clip, resolution, (stroke, stroke),
+                |x, y, color| plot_cell(frame, x, y, color, resolution));
         }
     }
 }
 
-// Drawing a horizontal line using Bresenham's line algorithm and interpolating colors based on the two vertices
-// It does not store the points in the hashmap, as it is only for filling the triangle
-fn draw_horizontal_line(canvas: &mut WindowCanvas, x1: i32, y1: i32, x2: i32, y2: i32, c1: Color, c2: Color, _z1: i32, _z2: i32, resolution: i32 ) {
-    
-    
-    let dx = (x2 - x1).abs();
-    let dy = (y2 - y1).abs();
-    let sx = if x1 < x2 { 1 } else { -1 };
-    let sy = if y1 < y2 { 1 } else { -1 };
-    let mut err = dx - dy;
-
-    let mut x = x1;
-    let mut y = y1;
-
-    let total_distance = ((x2 - x1).pow(2) + (y2 - y1).pow(2)) as f32;
-
-    loop {
-        
-        let t = if total_distance == 0.0 {0.0} else {((x - x1).pow(2) + (y - y1).pow(2)) as f32 / total_distance};
-        let color = interpolate_color(c1, c2, t);
-        
-        
-        // let z = z1 * (1.0 - t) + z2 * t;
-        // let shadow_factor = (1.0 + z).max(0.0).min(1.0);
-        // let shadowed_color = darken_color(color, shadow_factor);
-        canvas.set_draw_color(color);
-        canvas.fill_rect(Rect::new(x - x % resolution, y - y % resolution, resolution as u32, resolution as u32)).unwrap();
-
-        
-        if (x - x2).abs() < resolution && (y - y2).abs() < resolution {
-            break;
+// Demo for the clipping scene : a fan of lines a million pixels long, trimmed to a scissor
+// rectangle in the middle of the window before they are stepped
+fn draw_clipped_fan(frame: &mut Framebuffer, rotation_angle: f32, center_x: i32, center_y: i32, algorithm: ClipAlgorithm, resolution: i32) {
+    let clip = LineClip {
+        rect: ClipRect::new(center_x - 250, center_y - 150, 500, 300),
+        algorithm,
+    };
+    let (left, top, right, bottom) = (clip.rect.x_min - 1, clip.rect.y_min - 1, clip.rect.x_max + 1, clip.rect.y_max + 1);
+    let border = Color::RGB(120, 120, 120);
+    frame.fill_rect(left, top, right - left + 1, 1, border);
+    frame.fill_rect(left, bottom, right - left + 1, 1, border);
+    frame.fill_rect(left, top, 1, bottom - top + 1, border);
+    frame.fill_rect(right, top, 1, bottom - top + 1, border);
+
+    let length = 1_000_000.0;
+    for i in 0..24 {
+        let angle = rotation_angle + i as f32 * PI / 12.0;
+        let (x1, y1) = (center_x - 400 + i * 30, center_y);
+        let x2 = x1 + (angle.cos() * length) as i32;
+        let y2 = y1 + (angle.sin() * length) as i32;
+        draw_clipped_line(&clip, x1, y1, x2, y2, Color::RGB(255, 255, 0), Color::RGB(255, 0, 255), resolution,
+            |x, y, color| plot_cell(frame, x, y, color, resolution));
+    }
+}
+
+// Demo for the transparency scene : the gradient triangle as opaque geometry, with translucent
+// triangles in front of and behind it and a checkerboard quad with see-through cells, all
+// turning with the rotation slider. Translucent triangles are depth tested against the opaque
+// ones and blended back to front unless sorting is switched off. With the A-buffer they are
+// drawn unsorted and composited per pixel at the end, which returns the resolve statistics.
+// In the premultiplied blend mode the checkerboard has to be premultiplied like the vertex colors.
+#[allow(clippy::too_many_arguments)]
+fn draw_transparency(
+    frame: &mut Framebuffer,
+    opaque: &[Point3D],
+    rotation_angle: f32,
+    center_x: i32,
+    center_y: i32,
+    blend_mode: BlendMode,
+    sorted: bool,
+    order_independent: bool,
+    checkerboard: &Texture,
+    resolution: i32,
+    rasterizer: &mut Rasterizer,
+) -> Option<ABufferStats> {
+    let place = |x: i32, y: i32, z: i32, color: Color| {
+        let rotated = rotate_y(&Vertex { x, y, z }, rotation_angle);
+        let color = if blend_mode == BlendMode::Premultiplied { premultiply(color) } else { color };
+        ScreenVertex::new((rotated.x + center_x) as f32, (rotated.y + center_y) as f32, rotated.z as f32, color)
+    };
+
+    let opaque = [0, 1, 2].map(|i| {
+        let v = &opaque[i].vertex;
+        place(v.x, v.y, v.z, opaque[i].color)
+    });
+    rasterizer.fill_triangle(frame, &opaque, None, &RenderState::OPAQUE, resolution);
+
+    let tint = Color::RGBA(255, 255, 255, 255);
+    let quad = [
+        place(-150, -100, -250, tint).with_uv(0.0, 0.0),
+        place(150, -100, -250, tint).with_uv(2.0, 0.0),
+        place(150, 200, 50, tint).with_uv(2.0, 2.0),
+        place(-150, 200, 50, tint).with_uv(0.0, 2.0),
+    ];
+    let mut translucent = vec![
+        ([place(-250, -200, -150, Color::RGBA(255, 0, 0, 140)), place(50, 150, -150, Color::RGBA(255, 80, 0, 140)), place(-300, 180, -150, Color::RGBA(255, 0, 120, 140))], None),
+        ([place(-50, -220, 150, Color::RGBA(0, 255, 255, 110)), place(300, -50, 150, Color::RGBA(0, 120, 255, 110)), place(0, 220, 150, Color::RGBA(0, 255, 160, 110))], None),
+        ([place(100, -250, -300, Color::RGBA(255, 255, 0, 160)), place(250, 100, 100, Color::RGBA(255, 255, 0, 160)), place(-150, 50, -50, Color::RGBA(255, 200, 0, 160))], None),
+        ([quad[0], quad[1], quad[2]], Some(checkerboard)),
+        ([quad[0], quad[2], quad[3]], Some(checkerboard)),
+    ];
+    if sorted && !order_independent {
+        sort_back_to_front(&mut translucent, |(triangle, _)| triangle);
+    }
+
+    frame.set_a_buffer(order_independent.then_some(A_BUFFER_FRAGMENTS));
+    let state = RenderState { blend: blend_mode.state(), a_buffer: order_independent, ..RenderState::TRANSPARENT };
+    for (triangle, texture) in &translucent {
+        rasterizer.fill_triangle(frame, triangle, *texture, &state, resolution);
+    }
+    frame.resolve_a_buffer()
+}
+
+// Demo for the stencil scene : on the left an elliptic portal is marked in the stencil buffer
+// without writing color, and a backdrop with the gradient triangle is drawn only inside it.
+// On the right the triangle marks its cells, and a larger copy drawn only where nothing is
+// marked leaves an outline around it.
+fn draw_stencil(
+    frame: &mut Framebuffer,
+    opaque: &[Point3D],
+    rotation_angle: f32,
+    center_x: i32,
+    center_y: i32,
+    resolution: i32,
+    rasterizer: &mut Rasterizer,
+) {
+    let triangle = |cx: i32, cy: i32, scale: f32, color: Option<Color>| {
+        [0, 1, 2].map(|i| {
+            let rotated = rotate_y(&opaque[i].vertex, rotation_angle);
+            let (x, y) = (cx as f32 + rotated.x as f32 * scale, cy as f32 + rotated.y as f32 * scale);
+            ScreenVertex::new(x, y, rotated.z as f32, color.unwrap_or(opaque[i].color))
+        })
+    };
+
+    let portal = Ellipse::new(center_x - 190, center_y + 40, 150, 210);
+    let mark_portal = RenderState {
+        color_write: false,
+        depth_test: false,
+        depth_write: false,
+        stencil: Some(StencilState::mark(1)),
+        ..RenderState::OPAQUE
+    };
+    fill_ellipse(&portal, resolution, (Color::RGB(0, 0, 0), Color::RGB(0, 0, 0)), |x, y, color| {
+        frame.write_fragment(x, y, resolution, color, 0.0, &mark_portal)
+    });
+
+    let inside_portal = RenderState { stencil: Some(StencilState::test(CompareFunc::Equal, 1)), ..RenderState::OPAQUE };
+    let backdrop = [
+        ScreenVertex::new(0.0, 0.0, 1000.0, Color::RGB(20, 0, 60)),
+        ScreenVertex::new(center_x as f32, 0.0, 1000.0, Color::RGB(0, 60, 120)),
+        ScreenVertex::new(center_x as f32, frame.height() as f32, 1000.0, Color::RGB(0, 120, 80)),
+        ScreenVertex::new(0.0, frame.height() as f32, 1000.0, Color::RGB(60, 0, 60)),
+    ];
+    rasterizer.fill_polygon(frame, &[backdrop], FillRule::NonZero, None, &inside_portal, resolution);
+    rasterizer.fill_triangle(frame, &triangle(portal.cx, portal.cy, 0.9, None), None, &inside_portal, resolution);
+    draw_ellipse(&portal, resolution, (Color::RGB(200, 200, 200), Color::RGB(90, 90, 90)),
+        |x, y, color| plot_cell(frame, x, y, color, resolution));
+
+    let (outlined_x, outlined_y) = (center_x + 200, center_y + 60);
+    let mark_object = RenderState { stencil: Some(StencilState::mark(2)), ..RenderState::OPAQUE };
+    rasterizer.fill_triangle(frame, &triangle(outlined_x, outlined_y, 0.6, None), None, &mark_object, resolution);
+    let outside_object = RenderState {
+        depth_test: false,
+        depth_write: false,
+        stencil: Some(StencilState::test(CompareFunc::NotEqual, 2)),
+        ..RenderState::OPAQUE
+    };
+    let outline = triangle(outlined_x, outlined_y, 0.75, Some(Color::RGB(255, 220, 0)));
+    rasterizer.fill_triangle(frame, &outline, None, &outside_object, resolution);
+}
+
+// The views scene looks at a pyramid from the front, the top, the right side and tilted, one
+// quarter of the window each. Every view has its own viewport and a scissor rectangle that
+// keeps it out of its neighbours.
+fn draw_views(frame: &mut Framebuffer, rotation_angle: f32, resolution: i32, rasterizer: &mut Rasterizer) {
+    let corners = [
+        (Vertex { x: 0, y: -200, z: 0 }, Color::RGB(255, 255, 255)),
+        (Vertex { x: -150, y: 100, z: -150 }, Color::RGB(255, 0, 0)),
+        (Vertex { x: 150, y: 100, z: -150 }, Color::RGB(0, 255, 0)),
+        (Vertex { x: 150, y: 100, z: 150 }, Color::RGB(0, 0, 255)),
+        (Vertex { x: -150, y: 100, z: 150 }, Color::RGB(255, 255, 0)),
+    ];
+    let faces = [[0, 1, 2], [0, 2, 3], [0, 3, 4], [0, 4, 1], [1, 3, 2], [1, 4, 3]];
+
+    // Each view turns the world so that it looks along +z, with y down the screen
+    let views: [fn(&Vertex) -> Vertex; 4] = [
+        |v| Vertex { x: v.x, y: v.y, z: v.z },
+        |v| Vertex { x: v.x, y: -v.z, z: v.y },
+        |v| Vertex { x: v.z, y: v.y, z: -v.x },
+        |v| {
+            let (cos, sin) = (0.6f32.cos(), 0.6f32.sin());
+            Vertex {
+                x: v.x,
+                y: (v.y as f32 * cos - v.z as f32 * sin) as i32,
+                z: (v.y as f32 * sin + v.z as f32 * cos) as i32,
+            }
+        },
+    ];
+
+    let (half_width, half_height) = (frame.width() / 2, frame.height() / 2);
+    for (i, view) in views.iter().enumerate() {
+        let viewport = Viewport::new(
+            (i as u32 % 2 * half_width) as i32,
+            (i as u32 / 2 * half_height) as i32,
+            half_width,
+            half_height,
+        );
+        let state = RenderState { scissor: Some(viewport.rect()), ..RenderState::OPAQUE };
+        for face in &faces {
+            let triangle = face.map(|corner| {
+                let (vertex, color) = &corners[corner];
+                let (x, y, z) = project(&viewport, &view(&rotate_y(vertex, rotation_angle)), 300.0);
+                ScreenVertex::new(x, y, z, *color)
+            });
+            rasterizer.fill_triangle(frame, &triangle, None, &state, resolution);
         }
+    }
+
+    let border = Color::RGB(200, 200, 200);
+    frame.fill_rect(half_width as i32 - 1, 0, 2, frame.height() as i32, border);
+    frame.fill_rect(0, half_height as i32 - 1, frame.width() as i32, 2, border);
+}
 
-        let e2 = 2 * err;
-        if e2 > -dy {
-            err -= dy;
-            x += sx;
+// The triangle scene : the gradient triangle, projected into the whole frame, with its outline
+// on top as a wireframe overlay. `extent` units from the center reach its top and bottom border.
+#[allow(clippy::too_many_arguments)]
+fn draw_triangle(
+    frame: &mut Framebuffer,
+    opaque: &[Point3D],
+    rotation_angle: f32,
+    extent: f32,
+    clip_algorithm: ClipAlgorithm,
+    wireframe: bool,
+    resolution: i32,
+    rasterizer: &mut Rasterizer,
+) {
+    let viewport = Viewport::new(0, 0, frame.width(), frame.height());
+    let clip = LineClip { rect: viewport.rect(), algorithm: clip_algorithm };
+    let rotated_vertices: Vec<Point3D> = opaque.iter().map(|v| {
+        let rotated = rotate_y(&v.vertex, rotation_angle);
+        let (x, y, z) = project(&viewport, &rotated, extent);
+        Point3D {
+            vertex: Vertex { x: x.round() as i32, y: y.round() as i32, z: z.round() as i32 },
+            color: v.color,
         }
-        if e2 < dx {
-            err += dx;
-            y += sy;
+    }).collect();
+
+    let triangle = [0, 1, 2].map(|i| screen_vertex(&rotated_vertices[i]));
+    rasterizer.fill_triangle(frame, &triangle, None, &FLAT_2D, resolution);
+
+    if wireframe {
+        for i in 0..3 {
+            let point1 = &rotated_vertices[i];
+            let point2 = &rotated_vertices[(i + 1) % 3];
+            draw_clipped_line(&clip,
+                 point1.vertex.x,
+                 point1.vertex.y,
+                 point2.vertex.x,
+                 point2.vertex.y,
+                 Color::RGB(255, 255, 255), Color::RGB(255, 255, 255), resolution,
+                 |x, y, color| plot_cell(frame, x, y, color, resolution));
+        }
+    }
+}
+
+// Side by side comparison for the triangle scene : the gradient triangle and two color ramps
+// interpolated on sRGB values on the left and in linear light on the right
+fn draw_gamma_comparison(frame: &mut Framebuffer, opaque: &[Point3D], rotation_angle: f32, resolution: i32, rasterizer: &mut Rasterizer) {
+    let half_width = frame.width() / 2;
+    let ramp = |y: i32, from: Color, to: Color| [
+        (Vertex { x: -300, y, z: 0 }, from),
+        (Vertex { x: 300, y, z: 0 }, to),
+        (Vertex { x: 300, y: y + 60, z: 0 }, to),
+        (Vertex { x: -300, y: y + 60, z: 0 }, from),
+    ];
+    let ramps = [
+        ramp(300, Color::RGB(255, 0, 0), Color::RGB(0, 255, 0)),
+        ramp(380, Color::RGB(0, 255, 0), Color::RGB(0, 0, 255)),
+    ];
+
+    for (i, color_space) in [ColorSpace::Srgb, ColorSpace::Linear].into_iter().enumerate() {
+        let viewport = Viewport::new((i as u32 * half_width) as i32, 0, half_width, frame.height());
+        let state = RenderState { scissor: Some(viewport.rect()), color_space, ..FLAT_2D };
+        let to_screen = |vertex: &Vertex, color: Color| {
+            let (x, y, z) = project(&viewport, vertex, 550.0);
+            ScreenVertex::new(x, y, z, color)
+        };
+
+        let triangle = [0, 1, 2].map(|i| to_screen(&rotate_y(&opaque[i].vertex, rotation_angle), opaque[i].color));
+        rasterizer.fill_triangle(frame, &triangle, None, &state, resolution);
+        for ramp in &ramps {
+            let quad = ramp.each_ref().map(|(vertex, color)| to_screen(vertex, *color));
+            rasterizer.fill_polygon(frame, &[quad], FillRule::NonZero, None, &state, resolution);
         }
     }
+    frame.fill_rect(half_width as i32 - 1, 0, 2, frame.height() as i32, Color::RGB(200, 200, 200));
 }
 
-// Filling the triangle using the interpolated points
-fn fill_triangle(
-    canvas: &mut WindowCanvas,
-    v0: &Point3D,
-    v1: &Point3D,
-    v2: &Point3D,
+// Demo for the HDR scene : black to white ramps going up to 1, 4 and 16 times white, the
+// gradient triangle glowing at 3 times its color, and three colored lights added on top of
+// each other, all well beyond what the 8 bit colors can hold before tone mapping
+fn draw_hdr(
+    frame: &mut Framebuffer,
+    opaque: &[Point3D],
+    rotation_angle: f32,
+    center_x: i32,
+    center_y: i32,
     resolution: i32,
-    interpolated_points: &mut InterpolatedPoints,
+    rasterizer: &mut Rasterizer,
 ) {
-    let mut min_y = v0.vertex.y.min(v1.vertex.y).min(v2.vertex.y);
-    let mut max_y = v0.vertex.y.max(v1.vertex.y).max(v2.vertex.y);
-    min_y -= min_y % resolution;
-    max_y -= max_y % resolution;
-
-    for y in (min_y..=max_y).step_by(resolution as usize) {
-        if let Some(((x_min, color_min), (x_max, color_max))) = interpolated_points.get_min_max_x(y) {          
-            draw_horizontal_line(canvas, x_min, y, x_max, y , color_min, color_max, 0, 0, resolution);
+    let at = |x: i32, y: i32, color: Color| ScreenVertex::new((center_x + x) as f32, (center_y + y) as f32, 0.0, color);
+    for (i, intensity) in [1.0, 4.0, 16.0].into_iter().enumerate() {
+        let y = -270 + i as i32 * 70;
+        let (black, white) = (Color::RGB(0, 0, 0), Color::RGB(255, 255, 255));
+        let ramp = [at(-350, y, black), at(350, y, white), at(350, y + 50, white), at(-350, y + 50, black)]
+            .map(|vertex| vertex.with_intensity(intensity));
+        rasterizer.fill_polygon(frame, &[ramp], FillRule::NonZero, None, &FLAT_2D, resolution);
+    }
+
+    let triangle = [0, 1, 2].map(|i| {
+        let rotated = rotate_y(&opaque[i].vertex, rotation_angle);
+        at(rotated.x / 2 - 170, rotated.y / 2 + 150, opaque[i].color).with_intensity(3.0)
+    });
+    rasterizer.fill_triangle(frame, &triangle, None, &FLAT_2D, resolution);
+
+    let additive = RenderState { blend: BlendState::ADDITIVE, ..FLAT_2D };
+    for (i, color) in [Color::RGB(255, 40, 40), Color::RGB(40, 255, 40), Color::RGB(40, 40, 255)].into_iter().enumerate() {
+        let angle = rotation_angle + i as f32 * 2.0 * PI / 3.0;
+        let (x, y) = (200 + (angle.cos() * 50.0) as i32, 150 + (angle.sin() * 50.0) as i32);
+        let light = [at(x, y - 110, color), at(x + 100, y + 70, color), at(x - 100, y + 70, color)]
+            .map(|vertex| vertex.with_intensity(2.0));
+        rasterizer.fill_triangle(frame, &light, None, &additive, resolution);
+    }
+}
+
+// The lit scene : spheres and boxes standing on a checkered floor, with a loaded mesh in the
+// middle when one is given. For physically based shading the objects get more metallic from
+// left to right and rougher from back to front, and the floor is shiny on its dark squares.
+// Every object but the floor has an outline shell for toon shading, of the same width in the
+// world whatever the scale of its mesh.
+struct LitScene {
+    sphere: Mesh,
+    cube: Mesh,
+    sphere_shell: Mesh,
+    cube_shell: Mesh,
+    floor: Mesh,
+    floor_texture: Texture,
+    floor_metallic_roughness: Texture,
+    bumps: Texture,
+    centerpiece: Option<(Mesh, Mat4)>,
+    centerpiece_shell: Option<Mesh>,
+    environment: ImageBasedLight,
+    sky: CubeMap,
+}
+
+impl LitScene {
+    fn new(mesh_file: Option<&str>) -> Result<Self, String> {
+        // Rounded tiles, 4 by 4 over the texture
+        let heights = (0..64 * 64)
+            .map(|i| {
+                let (x, y) = ((i % 64) as f32 / 16.0, (i / 64) as f32 / 16.0);
+                let edge = (x.fract() - 0.5).abs().max((y.fract() - 0.5).abs());
+                let height = ((0.5 - edge) / 0.15).min(1.0);
+                let byte = (height * height * (3.0 - 2.0 * height) * 255.0) as u8;
+                Color::RGB(byte, byte, byte)
+            })
+            .collect();
+
+        // The loaded mesh is scaled to the size of the spheres and stood on the floor
+        let centerpiece = match mesh_file {
+            Some(file) => {
+                let mesh = load_obj(file)?;
+                let (mut low, mut high) = (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY));
+                for vertex in &mesh.vertices {
+                    let p = vertex.position;
+                    low = Vec3::new(low.x.min(p.x), low.y.min(p.y), low.z.min(p.z));
+                    high = Vec3::new(high.x.max(p.x), high.y.max(p.y), high.z.max(p.z));
+                }
+                let extent = high - low;
+                let scale = 1.8 / extent.x.max(extent.y).max(extent.z).max(1e-6);
+                let center = (low + high) * 0.5;
+                let model = Mat4::translation(Vec3::new(0.0, extent.y * scale / 2.0, 0.0)) * Mat4::scale(scale) * Mat4::translation(-center);
+                Some((mesh, model))
+            }
+            None => None,
+        };
+        let centerpiece_shell = centerpiece.as_ref().map(|(mesh, model)| {
+            outline_shell(mesh, OUTLINE_WIDTH / model.transform_vector(Vec3::new(1.0, 0.0, 0.0)).length())
+        });
+
+        let (sphere, cube) = (Mesh::sphere(0.8, 24, 16), Mesh::cube(1.2));
+        let sky = EquirectMap::from_fn(256, 128, sky);
+        Ok(Self {
+            sphere_shell: outline_shell(&sphere, OUTLINE_WIDTH),
+            cube_shell: outline_shell(&cube, OUTLINE_WIDTH),
+            sphere,
+            cube,
+            floor: Mesh::plane(12.0, 6.0),
+            floor_texture: Texture::checkerboard(64, 2, Color::RGB(200, 200, 200), Color::RGB(90, 90, 90)),
+            floor_metallic_roughness: Texture::checkerboard(64, 2, Color::RGB(0, 230, 0), Color::RGB(0, 40, 0)),
+            bumps: Texture::normal_map(&Texture::new(64, 64, heights)?, 3.0),
+            centerpiece,
+            centerpiece_shell,
+            environment: ImageBasedLight::new(&sky, 64),
+            sky: CubeMap::from_equirect(&sky, 128),
+        })
+    }
+
+    // The outline shell of an object's mesh, none for the floor
+    fn shell(&self, mesh: &Mesh) -> Option<&Mesh> {
+        if std::ptr::eq(mesh, &self.sphere) {
+            Some(&self.sphere_shell)
+        } else if std::ptr::eq(mesh, &self.cube) {
+            Some(&self.cube_shell)
+        } else if self.centerpiece.as_ref().is_some_and(|(centerpiece, _)| std::ptr::eq(mesh, centerpiece)) {
+            self.centerpiece_shell.as_ref()
         } else {
-            eprintln!("Warning: No min/max x found for y = {}", y);
+            None
         }
     }
+
+    // Every object with its model matrix and material
+    fn objects<'a>(&'a self, normal_mapping: bool) -> Vec<(&'a Mesh, Mat4, Material<'a>)> {
+        let bumpy = |material: Material<'a>| if normal_mapping { material.with_normal_map(&self.bumps) } else { material };
+        let colors = [Color::RGB(230, 80, 70), Color::RGB(240, 200, 90), Color::RGB(90, 170, 230)];
+        let floor = Material::new(Color::RGB(255, 255, 255))
+            .with_texture(&self.floor_texture)
+            .with_metallic_roughness(0.0, 1.0)
+            .with_metallic_roughness_map(&self.floor_metallic_roughness);
+        let mut objects = vec![(&self.floor, Mat4::IDENTITY, bumpy(floor))];
+        for row in 0..3 {
+            for column in 0..3 {
+                let (x, z) = ((column - 1) as f32 * 3.0, (row - 1) as f32 * 3.0);
+                let material = Material::new(colors[((row + column) % 3) as usize])
+                    .with_metallic_roughness(column as f32 / 2.0, 0.15 + row as f32 * 0.35);
+                let material = bumpy(material);
+                if let Some((mesh, model)) = self.centerpiece.as_ref().filter(|_| (row, column) == (1, 1)) {
+                    objects.push((mesh, *model, material));
+                } else if (row + column) % 2 == 0 {
+                    objects.push((&self.sphere, Mat4::translation(Vec3::new(x, 0.8, z)), material));
+                } else {
+                    let spin = Mat4::rotation_y((row * 3 + column) as f32 * 0.4);
+                    objects.push((&self.cube, Mat4::translation(Vec3::new(x, 0.6, z)) * spin, material));
+                }
+            }
+        }
+        objects
+    }
 }
 
+// Daylight for image based lighting : a blue sky getting paler towards the horizon, a bright
+// sun and a dark ground
+fn sky(direction: Vec3) -> Vec3 {
+    let sun = Vec3::new(0.5, 0.6, -0.62).normalized();
+    let up = direction.y.max(0.0);
+    let sky = Vec3::new(0.75, 0.82, 0.95).lerp(Vec3::new(0.2, 0.4, 0.85), up.sqrt());
+    let base = if direction.y >= 0.0 { sky } else { Vec3::new(0.15, 0.13, 0.11) };
+    let glow = direction.dot(sun).max(0.0);
+    base + Vec3::new(1.0, 0.9, 0.7) * (glow.powi(512) * 200.0 + glow.powi(16) * 0.5)
+}
+
+// The shading model of the lighting scene, cycled with E
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ShadingMode {
+    BlinnPhong,
+    CookTorrance,
+    Toon,
+}
+impl ShadingMode {
+    fn next(self) -> Self {
+        match self {
+            ShadingMode::BlinnPhong => ShadingMode::CookTorrance,
+            ShadingMode::CookTorrance => ShadingMode::Toon,
+            ShadingMode::Toon => ShadingMode::BlinnPhong,
+        }
+    }
+}
+
+// What the spheres of the lighting scene show of the environment in forward shading, cycled with Z
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EnvironmentMapping {
+    Off,
+    Chrome,
+    Glass,
+}
+impl EnvironmentMapping {
+    fn next(self) -> Self {
+        match self {
+            EnvironmentMapping::Off => EnvironmentMapping::Chrome,
+            EnvironmentMapping::Chrome => EnvironmentMapping::Glass,
+            EnvironmentMapping::Glass => EnvironmentMapping::Off,
+        }
+    }
+
+    // Chrome mirrors the sky tinted by the albedo, glass refracts it and reflects some at grazing angles
+    fn shade(self, sky: &CubeMap, surface: &Surface, eye: Vec3, lit: Vec3) -> Vec3 {
+        match self {
+            EnvironmentMapping::Off => lit,
+            EnvironmentMapping::Chrome => sky.reflection(surface, eye).mul_elements(surface.albedo),
+            EnvironmentMapping::Glass => {
+                let cos_theta = (eye - surface.position).normalized().dot(surface.normal).max(0.0);
+                let fresnel = 0.04 + 0.96 * (1.0 - cos_theta).powi(5);
+                let refracted = sky.refraction(surface, eye, 1.5).mul_elements(surface.albedo.lerp(Vec3::splat(1.0), 0.7));
+                refracted.lerp(sky.reflection(surface, eye), fresnel)
+            }
+        }
+    }
+}
+
+// Polygon mode of the triangle meshes, cycled with J : fill, line, point
+fn next_polygon_mode(mode: PolygonMode) -> PolygonMode {
+    match mode {
+        PolygonMode::Fill => PolygonMode::Line,
+        PolygonMode::Line => PolygonMode::Point,
+        PolygonMode::Point => PolygonMode::Fill,
+    }
+}
+
+// Fog of the lighting scene, cycled with X : off, linear, exponential and exponential squared
+fn next_fog_mode(mode: Option<FogMode>) -> Option<FogMode> {
+    match mode {
+        None => Some(FogMode::Linear { start: 6.0, end: 25.0 }),
+        Some(FogMode::Linear { .. }) => Some(FogMode::Exponential { density: 0.08 }),
+        Some(FogMode::Exponential { .. }) => Some(FogMode::ExponentialSquared { density: 0.06 }),
+        Some(FogMode::ExponentialSquared { .. }) => None,
+    }
+}
+
+// Colored point lights circling the scene at different heights and speeds
+fn orbiting_lights(time: f32) -> Vec<PointLight> {
+    (0..16)
+        .map(|i| {
+            let hue = i as f32 / 16.0 * 2.0 * PI;
+            let color = Vec3::new(hue.cos(), (hue - 2.0 * PI / 3.0).cos(), (hue + 2.0 * PI / 3.0).cos()) * 0.5 + Vec3::splat(0.5);
+            let radius = 1.5 + (i % 4) as f32 * 1.2;
+            let angle = hue + time * (0.3 + (i % 3) as f32 * 0.2);
+            let position = Vec3::new(angle.cos() * radius, 0.4 + (i % 5) as f32 * 0.4, angle.sin() * radius);
+            PointLight::new(position, color, 3.0, 4.0)
+        })
+        .collect()
+}
+
+// Demo for the lighting scene, seen by a camera circling with the rotation slider. Forward
+// shading lights every fragment while rasterizing, deferred shading fills the G-buffer and
+// lights the visible pixels afterwards, or shows one of its channels. Ambient occlusion needs
+// the G-buffer, so it only darkens the deferred shading. Fog takes the color of the horizon,
+// so distant objects fade into the sky. Outlines are drawn around toon shaded objects in
+// forward shading. The wireframe overlay draws the edges of every triangle on top of the
+// shaded scene, hidden where a surface in front covers them.
+#[allow(clippy::too_many_arguments)]
+fn draw_lighting(
+    frame: &mut Framebuffer,
+    scene: &LitScene,
+    time: f32,
+    rotation_angle: f32,
+    normal_mapping: bool,
+    shading_mode: ShadingMode,
+    outlines: bool,
+    skybox: bool,
+    environment_mapping: EnvironmentMapping,
+    fog_mode: Option<FogMode>,
+    height_fog: bool,
+    wireframe: bool,
+    deferred: Option<GBufferChannel>,
+    mut ssao: Option<&mut Ssao>,
+    resolution: i32,
+    renderer: &mut MeshRenderer,
+    gbuffer: &mut GBuffer,
+) {
+    let viewport = Viewport::new(0, 0, frame.width(), frame.height());
+    let camera = Camera::orbit(Vec3::new(0.0, 0.5, 0.0), 9.0, 4.5, rotation_angle);
+    let view_projection = camera.view_projection(viewport.aspect());
+    let lights = orbiting_lights(time);
+    // A strong ambient term, so its occlusion shows
+    let blinn_phong = BlinnPhong { ambient: Vec3::splat(0.12), ..BlinnPhong::default() };
+    let cook_torrance = CookTorrance::new(Some(&scene.environment));
+    let toon = Toon::default();
+    let lighting: &dyn ShadingModel = match shading_mode {
+        ShadingMode::BlinnPhong => &blinn_phong,
+        ShadingMode::CookTorrance => &cook_torrance,
+        ShadingMode::Toon => &toon,
+    };
+    let fog = Fog {
+        color: Vec3::new(0.75, 0.82, 0.95),
+        mode: fog_mode,
+        height: height_fog.then_some(HeightFog { base: 0.0, density: 0.6, falloff: 1.5 }),
+    };
+    let fogged = Fogged { shading: lighting, fog };
+    // Drawn first, the lighting pass of deferred shading writes no depth to test the sky against
+    if skybox {
+        scene.sky.draw_skybox(frame, &camera, &viewport, resolution);
+    }
+
+    match deferred {
+        Some(channel) => {
+            gbuffer.clear();
+            for (mesh, model, material) in scene.objects(normal_mapping) {
+                renderer.draw_mesh(mesh, &model, &view_projection, &viewport, &material, resolution, |x, y, depth, surface| {
+                    gbuffer.write(x, y, resolution, depth, surface);
+                });
+            }
+            if let Some(ssao) = ssao.as_deref_mut() {
+                ssao.compute(gbuffer, &view_projection, &viewport, resolution);
+            }
+            gbuffer.show(channel, frame, &fogged, &lights, camera.position, ssao.as_deref());
+        }
+        None => {
+            for (mesh, model, material) in scene.objects(normal_mapping) {
+                let mapping = if std::ptr::eq(mesh, &scene.sphere) { environment_mapping } else { EnvironmentMapping::Off };
+                renderer.draw_mesh(mesh, &model, &view_projection, &viewport, &material, resolution, |x, y, depth, surface| {
+                    let lit = lighting.shade(surface, &lights, camera.position, 1.0);
+                    let shaded = mapping.shade(&scene.sky, surface, camera.position, lit);
+                    let color = linear_rgb(fog.apply(shaded, surface, camera.position).to_array());
+                    frame.write_fragment(x, y, resolution, color, depth, &RenderState::OPAQUE);
+                });
+                let shell = scene.shell(mesh).filter(|_| shading_mode == ShadingMode::Toon && outlines && renderer.polygon_mode == PolygonMode::Fill);
+                if let Some(shell) = shell {
+                    renderer.draw_mesh(shell, &model, &view_projection, &viewport, &material, resolution, |x, y, depth, surface| {
+                        let color = linear_rgb(fog.apply(Vec3::splat(0.01), surface, camera.position).to_array());
+                        frame.write_fragment(x, y, resolution, color, depth, &RenderState::OPAQUE);
+                    });
+                }
+            }
+        }
+    }
+
+    if wireframe {
+        // Deferred shading leaves the depth of the surfaces in the G-buffer, not in the frame
+        let polygon_mode = std::mem::replace(&mut renderer.polygon_mode, PolygonMode::Line);
+        let state = RenderState { depth_write: false, ..RenderState::OPAQUE };
+        for (mesh, model, material) in scene.objects(normal_mapping) {
+            renderer.draw_mesh(mesh, &model, &view_projection, &viewport, &material, resolution, |x, y, depth, _| {
+                let depth = depth - WIREFRAME_DEPTH_BIAS;
+                if deferred.is_none() || gbuffer.depth(x, y).is_some_and(|stored| depth <= stored) {
+                    frame.write_fragment(x, y, resolution, Color::RGB(230, 230, 230), depth, &state);
+                }
+            });
+        }
+        renderer.polygon_mode = polygon_mode;
+    }
+
+    // A marker in the color of each light
+    for light in &lights {
+        let [x, y, z, w] = view_projection.transform(light.position);
+        if w > 1e-4 {
+            let (x, y, _) = viewport.to_window(x / w, y / w, z / w);
+            let cell = |v: f32| (v as i32).div_euclid(resolution) * resolution;
+            plot_cell(frame, cell(x), cell(y), linear_rgb(light.color.to_array()), resolution);
+        }
+    }
+}
+
+// Default grading of the post processing : warmer, with a gentle S curve for contrast
+fn warm_lut() -> Lut {
+    Lut::from_fn(17, |[r, g, b]| {
+        let contrast = |c: f32| c * c * (3.0 - 2.0 * c) * 0.4 + c * 0.6;
+        [contrast((r * 1.08 + 0.02).min(1.0)), contrast(g), contrast(b * 0.85)]
+    })
+}
+
+fn number_key(key: Keycode) -> Option<usize> {
+    [Keycode::Num1, Keycode::Num2, Keycode::Num3, Keycode::Num4, Keycode::Num5, Keycode::Num6, Keycode::Num7, Keycode::Num8, Keycode::Num9]
+        .iter()
+        .position(|&number| number == key)
+}
+
+fn print_post_chain(chain: &PostChain) {
+    let passes: Vec<String> = chain.passes()
+        .map(|(name, enabled)| format!("{} {}", name, if enabled { "on" } else { "off" }))
+        .collect();
+    println!("Post processing: {}", passes.join(", "));
+}
+
+// Demo shapes for the polygon scene : a self-intersecting star, a concave arrow and a square with a hole.
+// The hole runs in the same direction as its outer square, so only even-odd leaves it empty.
+fn polygon_contours(rotation_angle: f32, center_x: i32, center_y: i32) -> Vec<Vec<Vec<Point3D>>> {
+    let place = |x: i32, y: i32, color: Color| {
+        let rotated = rotate_y(&Vertex { x, y, z: 0 }, rotation_angle);
+        Point3D {
+            vertex: Vertex { x: rotated.x + center_x, y: rotated.y + center_y, z: rotated.z },
+            color,
+        }
+    };
+
+    let star: Vec<Point3D> = (0..5).map(|i| {
+        let angle = -PI / 2.0 + i as f32 * 4.0 * PI / 5.0;
+        let color = if i % 2 == 0 { Color::RGB(255, 200, 0) } else { Color::RGB(255, 50, 50) };
+        place(-200 + (angle.cos() * 150.0) as i32, -60 + (angle.sin() * 150.0) as i32, color)
+    }).collect();
+
+    let arrow = [(-120, 140), (0, 140), (0, 100), (100, 180), (0, 260), (0, 220), (-120, 220)]
+        .iter()
+        .map(|&(x, y)| place(x, y, Color::RGB(0, 200, 255)))
+        .collect();
+
+    let square = |half: i32, color: Color| -> Vec<Point3D> {
+        [(-half, -half), (half, -half), (half, half), (-half, half)]
+            .iter()
+            .map(|&(x, y)| place(200 + x, -60 + y, color))
+            .collect()
+    };
+
+    vec![
+        vec![star],
+        vec![arrow],
+        vec![square(130, Color::RGB(0, 255, 100)), square(60, Color::RGB(100, 0, 255))],
+    ]
+}
 
 fn main() -> Result<(), String> {
     let width = 800;
@@ -261,6 +1051,14 @@ fn main() -> Result<(), String> {
         .build()
         .expect("Could not make a canvas");
 
+    // Everything is rasterized into the framebuffer, which is uploaded to this texture every frame
+    let texture_creator = canvas.texture_creator();
+    let mut screen = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+        .map_err(|e| e.to_string())?;
+    let mut frame = Framebuffer::new(width, height);
+    let mut frame_bytes = Vec::new();
+
     let mut event_pump = sdl_context.event_pump()?;
     let mut resolution_slider = Slider::new(50, 100, 200, 10, 25.0, 1.0, 50.0);
     let mut rotation_slider = Slider::new(50, 50, 200, 10,0.0,  0.0, 2.0 * PI);
@@ -271,7 +1069,7 @@ fn main() -> Result<(), String> {
     let mut last_time = Instant::now();
     let mut frame_count = 0;
 
-    let original_vertices = vec![
+    let original_vertices = [
         Point3D {
             vertex: Vertex { x: 0, y: -250, z: 0 },
             color: Color::RGB(255, 0, 0),
@@ -286,39 +1084,157 @@ fn main() -> Result<(), String> {
         }
     ];
 
-    let mut interpolated_points = InterpolatedPoints::new();
+    // Command line : an SVG file for the SVG scene, otherwise it shows the bundled sample icon,
+    // `--palette "<colors>"` adding a custom palette for the dithered output
+    // `--lut <file.cube>` replacing the warm color grading of the post processing
+    // and `--mesh <file.obj>` placing a mesh in the middle of the lighting scene
+    let mut svg_file = None;
+    let mut mesh_file = None;
+    let mut palettes = vec![("1 bit", Palette::monochrome()), ("Game Boy", Palette::game_boy())];
+    let mut lut = warm_lut();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--palette" {
+            let spec = args.next().ok_or_else(|| "--palette needs a list of colors".to_string())?;
+            palettes.push(("custom", Palette::parse(&spec)?));
+        } else if arg == "--lut" {
+            let file = args.next().ok_or_else(|| "--lut needs a .cube file".to_string())?;
+            let text = std::fs::read_to_string(&file).map_err(|e| format!("{}: {}", file, e))?;
+            lut = Lut::parse_cube(&text)?;
+        } else if arg == "--mesh" {
+            mesh_file = Some(args.next().ok_or_else(|| "--mesh needs an .obj file".to_string())?);
+        } else {
+            svg_file = Some(arg);
+        }
+    }
+    let mut svg_document = match svg_file {
+        Some(file) => load_svg(&file)?,
+        None => parse_svg(include_str!("../assets/icon.svg"))?,
+    };
+    svg_document.fit(100.0, 120.0, width as f32 - 200.0, height as f32 - 160.0);
+
+    let mut post_chain = PostChain::new();
+    post_chain.push(Box::new(Bloom::new(170.0, 0.9, 6.0)), false);
+    post_chain.push(Box::new(GaussianBlur::new(2.0)), false);
+    post_chain.push(Box::new(Sharpen::new(1.0)), false);
+    post_chain.push(Box::new(ColorGrade::new(lut)), false);
+    post_chain.push(Box::new(Vignette::new(0.8, 0.35)), false);
+    post_chain.push(Box::new(SobelEdges::new()), false);
+    post_chain.push(Box::new(Fxaa::new()), false);
+
+    // A checkerboard of see-through and translucent cells for the transparency scene
+    let checkerboard = Texture::checkerboard(64, 4, Color::RGBA(255, 255, 255, 0), Color::RGBA(255, 140, 0, 200));
+    let premultiplied_checkerboard = checkerboard.premultiplied();
+
+    let lit_scene = LitScene::new(mesh_file.as_deref())?;
+    let mut gbuffer = GBuffer::new(width, height);
+    let mut ssao = Ssao::new(width, height, 16, 0.6);
+    let mut ambient_occlusion = false;
+    let mut normal_mapping = true;
+    let mut shading_mode = ShadingMode::BlinnPhong;
+    let mut outlines = true;
+    let mut skybox = true;
+    let mut environment_mapping = EnvironmentMapping::Off;
+    let mut fog_mode: Option<FogMode> = None;
+    let mut height_fog = false;
+    let mut mesh_renderer = MeshRenderer::new();
+    let start_time = Instant::now();
+
+    let mut edge_table = EdgeTable::new();
+    edge_table.set_clip(Some(ClipRect::new(0, 0, width, height)));
+    let mut rasterizer = Rasterizer::new();
+    let mut blend_mode = BlendMode::Alpha;
+    let mut sorted = true;
+    let mut order_independent = false;
+    let mut gamma_comparison = false;
+    let mut tone_mapping = ToneMapping::default();
+    let mut deferred = false;
+    let mut gbuffer_channel = GBufferChannel::Lit;
+    let mut selected_pass = 0;
+    let mut supersampler: Option<Supersampler> = None;
+    let mut downsample_filter = DownsampleFilter::Box;
+    let mut dither_method: Option<DitherMethod> = None;
+    let mut palette_index = 0;
+    let mut a_buffer_stats = None;
+    let mut clip_algorithm = ClipAlgorithm::CohenSutherland;
+    let mut scene = Scene::Triangle;
+    let mut fill_rule = FillRule::NonZero;
+    let mut triangulated = false;
+    let mut polygon_mode = PolygonMode::Fill;
+    // The triangle scene has always shown its outline, the lighting scene starts without one
+    let mut triangle_wireframe = true;
+    let mut lighting_wireframe = false;
+    let mut tolerance: f32 = 1.0;
     let center_x = width as i32 / 2;
     let center_y = height as i32 / 2;
 
     'running: loop {
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-        draw_grid(&mut canvas, width, height, resolution);
-
-        let rotated_vertices: Vec<Point3D> = original_vertices.iter().map(|v| {
-            let rotated = rotate_y(&v.vertex, rotation_angle);
-            Point3D {
-                vertex: Vertex {
-                    x: rotated.x + center_x,
-                    y: rotated.y + center_y,
-                    z: rotated.z,
-                },
-                color: v.color,
+        let window_viewport = Viewport::new(0, 0, width, height);
+        let window_clip = LineClip { rect: window_viewport.rect(), algorithm: clip_algorithm };
+        frame.set_hdr(scene == Scene::Hdr);
+
+        // The triangle and views scenes are projected through viewports, so they can be rendered
+        // into the larger framebuffer of the supersampler and filtered down
+        let supersampling = supersampler.as_mut().filter(|_| matches!(scene, Scene::Triangle | Scene::Views));
+        let (target, factor) = match supersampling {
+            Some(supersampler) => {
+                let factor = supersampler.factor() as i32;
+                (supersampler.frame(), factor)
             }
-        }).collect();
+            None => (&mut frame, 1),
+        };
+        target.clear(Color::RGB(0, 0, 0));
+        draw_grid(target, resolution * factor);
 
-        for i in 0..3 {
-            let point1 = &rotated_vertices[i];
-            let point2 = &rotated_vertices[(i + 1) % 3];
-            draw_bresenham_line(&mut interpolated_points, &mut canvas, 
-                 point1.vertex.x,
-                 point1.vertex.y,
-                 point2.vertex.x,
-                 point2.vertex.y, 
-                 point1.color, point2.color, point1.vertex.z, point2.vertex.z, resolution);
+        if scene == Scene::Lighting {
+            draw_lighting(&mut frame, &lit_scene, start_time.elapsed().as_secs_f32(), rotation_angle, normal_mapping, shading_mode, outlines, skybox, environment_mapping,
+                fog_mode, height_fog, lighting_wireframe, deferred.then_some(gbuffer_channel), ambient_occlusion.then_some(&mut ssao), resolution, &mut mesh_renderer, &mut gbuffer);
+        } else if scene == Scene::Hdr {
+            draw_hdr(&mut frame, &original_vertices, rotation_angle, center_x, center_y, resolution, &mut rasterizer);
+        } else if scene == Scene::Views {
+            draw_views(target, rotation_angle, resolution * factor, &mut rasterizer);
+        } else if scene == Scene::Stencil {
+            draw_stencil(&mut frame, &original_vertices, rotation_angle, center_x, center_y, resolution, &mut rasterizer);
+        } else if scene == Scene::Transparency {
+            let checkerboard = if blend_mode == BlendMode::Premultiplied { &premultiplied_checkerboard } else { &checkerboard };
+            a_buffer_stats = draw_transparency(&mut frame, &original_vertices, rotation_angle, center_x, center_y,
+                blend_mode, sorted, order_independent, checkerboard, resolution, &mut rasterizer);
+        } else if scene == Scene::Clipping {
+            draw_clipped_fan(&mut frame, rotation_angle, center_x, center_y, clip_algorithm, resolution);
+        } else if scene == Scene::Svg {
+            draw_svg(&mut frame, &svg_document, tolerance, &window_clip, resolution, &mut edge_table);
+        } else if scene == Scene::Curves {
+            draw_curves(&mut frame, center_x as f32, center_y as f32, tolerance, &window_clip, resolution, &mut edge_table);
+        } else if scene == Scene::Shapes {
+            draw_shapes(&mut frame, rotation_angle, center_x, center_y, resolution);
+        } else if scene == Scene::Polygons {
+            for contours in polygon_contours(rotation_angle, center_x, center_y) {
+                if triangulated {
+                    fill_triangulated(&mut frame, &contours, &window_clip, resolution, &mut rasterizer);
+                } else {
+                    let contours: Vec<Vec<ScreenVertex>> = contours.iter()
+                        .map(|contour| contour.iter().map(screen_vertex).collect())
+                        .collect();
+                    rasterizer.fill_polygon(&mut frame, &contours, fill_rule, None, &FLAT_2D, resolution);
+                }
+            }
+        } else if gamma_comparison {
+            draw_gamma_comparison(target, &original_vertices, rotation_angle, resolution * factor, &mut rasterizer);
+        } else {
+            draw_triangle(target, &original_vertices, rotation_angle, center_y as f32, clip_algorithm, triangle_wireframe, resolution * factor, &mut rasterizer);
+        }
+        if let Some(supersampler) = supersampler.as_mut().filter(|_| factor > 1) {
+            supersampler.resolve(&mut frame)?;
         }
 
-        fill_triangle(&mut canvas, &rotated_vertices[0], &rotated_vertices[1], &rotated_vertices[2], resolution, &mut interpolated_points);
+        frame.resolve_hdr(&tone_mapping);
+        post_chain.apply(&mut frame.post_image());
+        if let Some(method) = dither_method {
+            frame.quantize(&palettes[palette_index].1, method);
+        }
+        frame.write_rgb24(&mut frame_bytes);
+        screen.update(None, &frame_bytes, width as usize * 3).map_err(|e| e.to_string())?;
+        canvas.copy(&screen, None, None)?;
 
         resolution_slider.render(&mut canvas);
         rotation_slider.render(&mut canvas);
@@ -330,6 +1246,10 @@ fn main() -> Result<(), String> {
         if duration.as_secs_f32() >= 1.0 {
             let fps = frame_count as f32 / duration.as_secs_f32();
             println!("FPS: {:.2}", fps);
+            if let Some(stats) = a_buffer_stats.filter(|_| scene == Scene::Transparency) {
+                println!("A-buffer: {} fragments, {} over the cap, up to {} per pixel",
+                    stats.stored, stats.overflowed, stats.deepest_list);
+            }
             frame_count = 0;
             last_time = now;
         }
@@ -340,11 +1260,213 @@ fn main() -> Result<(), String> {
                 Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running;
                 }
+                // Tab cycles through the scenes, F toggles the fill rule
+                Event::KeyDown { keycode: Some(Keycode::Tab), .. } => {
+                    scene = scene.next();
+                }
+                Event::KeyDown { keycode: Some(Keycode::F), .. } => {
+                    fill_rule = match fill_rule {
+                        FillRule::NonZero => FillRule::EvenOdd,
+                        FillRule::EvenOdd => FillRule::NonZero,
+                    };
+                    println!("Fill rule: {:?}", fill_rule);
+                }
+                // T fills the polygons through triangulation instead of the scanline fill rules
+                Event::KeyDown { keycode: Some(Keycode::T), .. } => {
+                    triangulated = !triangulated;
+                }
+                // J cycles the polygon mode of the triangle and mesh rasterizers,
+                // I toggles the wireframe overlay of the triangle or the lighting scene, whichever is shown
+                Event::KeyDown { keycode: Some(Keycode::J), .. } => {
+                    polygon_mode = next_polygon_mode(polygon_mode);
+                    rasterizer.polygon_mode = polygon_mode;
+                    mesh_renderer.polygon_mode = polygon_mode;
+                    println!("Polygon mode: {:?}", polygon_mode);
+                }
+                Event::KeyDown { keycode: Some(Keycode::I), .. } => {
+                    let wireframe = if scene == Scene::Lighting { &mut lighting_wireframe } else { &mut triangle_wireframe };
+                    *wireframe = !*wireframe;
+                    println!("Wireframe overlay: {}", wireframe);
+                }
+                // C switches the line clipping algorithm
+                Event::KeyDown { keycode: Some(Keycode::C), .. } => {
+                    clip_algorithm = match clip_algorithm {
+                        ClipAlgorithm::CohenSutherland => ClipAlgorithm::LiangBarsky,
+                        ClipAlgorithm::LiangBarsky => ClipAlgorithm::CohenSutherland,
+                    };
+                    println!("Line clipping: {:?}", clip_algorithm);
+                }
+                // B cycles the blend mode of the transparency scene, S toggles back to front sorting
+                Event::KeyDown { keycode: Some(Keycode::B), .. } => {
+                    blend_mode = blend_mode.next();
+                    println!("Blend mode: {:?}", blend_mode);
+                }
+                Event::KeyDown { keycode: Some(Keycode::S), .. } => {
+                    sorted = !sorted;
+                    println!("Back to front sorting: {}", sorted);
+                }
+                // G splits the triangle scene into sRGB and linear light interpolation side by side
+                Event::KeyDown { keycode: Some(Keycode::G), .. } => {
+                    gamma_comparison = !gamma_comparison;
+                    println!("sRGB / linear light comparison: {}", gamma_comparison);
+                }
+                // M cycles the tone mapping operator of the HDR scene, Left / Right change the exposure,
+                // D toggles dithering and P saves the frame as it is shown
+                Event::KeyDown { keycode: Some(Keycode::M), .. } => {
+                    tone_mapping.operator = match tone_mapping.operator {
+                        ToneMapOperator::Clamp => ToneMapOperator::Reinhard,
+                        ToneMapOperator::Reinhard => ToneMapOperator::AcesFilmic,
+                        ToneMapOperator::AcesFilmic => ToneMapOperator::Exposure,
+                        ToneMapOperator::Exposure => ToneMapOperator::Clamp,
+                    };
+                    println!("Tone mapping: {:?}", tone_mapping.operator);
+                }
+                Event::KeyDown { keycode: Some(Keycode::Right), .. } => {
+                    tone_mapping.exposure = (tone_mapping.exposure * 2.0).min(64.0);
+                    println!("Exposure: {}", tone_mapping.exposure);
+                }
+                Event::KeyDown { keycode: Some(Keycode::Left), .. } => {
+                    tone_mapping.exposure = (tone_mapping.exposure / 2.0).max(1.0 / 64.0);
+                    println!("Exposure: {}", tone_mapping.exposure);
+                }
+                Event::KeyDown { keycode: Some(Keycode::D), .. } => {
+                    tone_mapping.dither = !tone_mapping.dither;
+                    println!("Dithering: {}", tone_mapping.dither);
+                }
+                // N cycles the supersampling factor of the triangle and views scenes, L the downsampling filter
+                Event::KeyDown { keycode: Some(Keycode::N), .. } => {
+                    let factor = match supersampler.as_ref().map(|supersampler| supersampler.factor()) {
+                        None => 2,
+                        Some(factor) if factor < 4 => factor + 1,
+                        Some(_) => 1,
+                    };
+                    supersampler = (factor > 1).then(|| Supersampler::new(width, height, factor, downsample_filter));
+                    println!("Supersampling: {}x{}", factor, factor);
+                }
+                Event::KeyDown { keycode: Some(Keycode::L), .. } => {
+                    downsample_filter = match downsample_filter {
+                        DownsampleFilter::Box => DownsampleFilter::Tent,
+                        DownsampleFilter::Tent => DownsampleFilter::Mitchell,
+                        DownsampleFilter::Mitchell => DownsampleFilter::Lanczos,
+                        DownsampleFilter::Lanczos => DownsampleFilter::Box,
+                    };
+                    if let Some(supersampler) = supersampler.as_mut() {
+                        supersampler.set_filter(downsample_filter);
+                    }
+                    println!("Downsampling filter: {:?}", downsample_filter);
+                }
+                // Q cycles the output dithering, K the palette it quantizes to
+                Event::KeyDown { keycode: Some(Keycode::Q), .. } => {
+                    dither_method = match dither_method {
+                        None => Some(DitherMethod::Nearest),
+                        Some(DitherMethod::Nearest) => Some(DitherMethod::Bayer),
+                        Some(DitherMethod::Bayer) => Some(DitherMethod::FloydSteinberg),
+                        Some(DitherMethod::FloydSteinberg) => Some(DitherMethod::Atkinson),
+                        Some(DitherMethod::Atkinson) => None,
+                    };
+                    println!("Output dithering: {:?}", dither_method);
+                }
+                Event::KeyDown { keycode: Some(Keycode::K), .. } => {
+                    palette_index = (palette_index + 1) % palettes.len();
+                    println!("Palette: {}", palettes[palette_index].0);
+                }
+                Event::KeyDown { keycode: Some(Keycode::P), .. } => {
+                    match frame.save_ppm("screenshot.ppm") {
+                        Ok(()) => println!("Saved screenshot.ppm"),
+                        Err(e) => println!("Could not save the screenshot : {}", e),
+                    }
+                }
+                // O switches the transparency scene to order independent transparency with the A-buffer
+                Event::KeyDown { keycode: Some(Keycode::O), .. } => {
+                    order_independent = !order_independent;
+                    println!("A-buffer: {}", order_independent);
+                }
+                // R switches the lighting scene between forward and deferred shading,
+                // V cycles the G-buffer channel shown by deferred shading
+                Event::KeyDown { keycode: Some(Keycode::R), .. } => {
+                    deferred = !deferred;
+                    println!("Shading: {}", if deferred { "deferred" } else { "forward" });
+                }
+                Event::KeyDown { keycode: Some(Keycode::V), .. } => {
+                    gbuffer_channel = match gbuffer_channel {
+                        GBufferChannel::Lit => GBufferChannel::Position,
+                        GBufferChannel::Position => GBufferChannel::Normal,
+                        GBufferChannel::Normal => GBufferChannel::Tangent,
+                        GBufferChannel::Tangent => GBufferChannel::Bitangent,
+                        GBufferChannel::Bitangent => GBufferChannel::Albedo,
+                        GBufferChannel::Albedo => GBufferChannel::MetallicRoughness,
+                        GBufferChannel::MetallicRoughness => GBufferChannel::Depth,
+                        GBufferChannel::Depth => GBufferChannel::Occlusion,
+                        GBufferChannel::Occlusion => GBufferChannel::Lit,
+                    };
+                    println!("G-buffer channel: {:?}", gbuffer_channel);
+                }
+                // E cycles the shading model of the lighting scene, U toggles the outlines of toon shading
+                Event::KeyDown { keycode: Some(Keycode::E), .. } => {
+                    shading_mode = shading_mode.next();
+                    println!("Shading model: {:?}", shading_mode);
+                }
+                Event::KeyDown { keycode: Some(Keycode::U), .. } => {
+                    outlines = !outlines;
+                    println!("Outlines: {}", outlines);
+                }
+                // Y toggles the skybox of the lighting scene, Z cycles environment mapping of its spheres
+                Event::KeyDown { keycode: Some(Keycode::Y), .. } => {
+                    skybox = !skybox;
+                    println!("Skybox: {}", skybox);
+                }
+                Event::KeyDown { keycode: Some(Keycode::Z), .. } => {
+                    environment_mapping = environment_mapping.next();
+                    println!("Environment mapping: {:?}", environment_mapping);
+                }
+                // X cycles the distance fog of the lighting scene, W toggles its height fog
+                Event::KeyDown { keycode: Some(Keycode::X), .. } => {
+                    fog_mode = next_fog_mode(fog_mode);
+                    println!("Fog: {:?}", fog_mode);
+                }
+                Event::KeyDown { keycode: Some(Keycode::W), .. } => {
+                    height_fog = !height_fog;
+                    println!("Height fog: {}", height_fog);
+                }
+                // H toggles the normal maps of the lighting scene
+                Event::KeyDown { keycode: Some(Keycode::H), .. } => {
+                    normal_mapping = !normal_mapping;
+                    println!("Normal mapping: {}", normal_mapping);
+                }
+                // A toggles screen space ambient occlusion in deferred shading
+                Event::KeyDown { keycode: Some(Keycode::A), .. } => {
+                    ambient_occlusion = !ambient_occlusion;
+                    println!("Ambient occlusion: {}", ambient_occlusion);
+                }
+                // Up and Down double or halve the curve flattening tolerance
+                Event::KeyDown { keycode: Some(Keycode::Up), .. } => {
+                    tolerance = (tolerance * 2.0).min(64.0);
+                    println!("Flattening tolerance: {}", tolerance);
+                }
+                Event::KeyDown { keycode: Some(Keycode::Down), .. } => {
+                    tolerance = (tolerance / 2.0).max(0.125);
+                    println!("Flattening tolerance: {}", tolerance);
+                }
+                // The number keys select and toggle a post processing pass,
+                // Page Up / Page Down move the selected pass earlier or later in the chain
+                Event::KeyDown { keycode: Some(key), .. } if number_key(key).is_some_and(|i| i < post_chain.len()) => {
+                    selected_pass = number_key(key).unwrap_or(0);
+                    post_chain.set_enabled(selected_pass, !post_chain.is_enabled(selected_pass));
+                    print_post_chain(&post_chain);
+                }
+                Event::KeyDown { keycode: Some(Keycode::PageUp), .. } if selected_pass > 0 => {
+                    post_chain.move_pass(selected_pass, selected_pass - 1);
+                    selected_pass -= 1;
+                    print_post_chain(&post_chain);
+                }
+                Event::KeyDown { keycode: Some(Keycode::PageDown), .. } if selected_pass + 1 < post_chain.len() => {
+                    post_chain.move_pass(selected_pass, selected_pass + 1);
+                    selected_pass += 1;
+                    print_post_chain(&post_chain);
+                }
                 _ => {
                     resolution_slider.handle_event(&event);
                     rotation_slider.handle_event(&event);
-                    interpolated_points = InterpolatedPoints::new();
-                    
                 }
             }
         }
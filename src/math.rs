@@ -0,0 +1,220 @@
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+
+// Vectors and matrices for the 3D pipeline.
+//
+// Matrices are row major and multiply column vectors, so `a * b` applies b first. The
+// projection follows the OpenGL conventions : the camera looks down -z in view space and
+// clip space z / w runs from -1 at the near plane to 1 at the far plane.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const ZERO: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+    pub const UP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn splat(v: f32) -> Self {
+        Self::new(v, v, v)
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    // Unit length, the zero vector stays zero
+    pub fn normalized(self) -> Vec3 {
+        let length = self.length();
+        if length > 0.0 {
+            self / length
+        } else {
+            self
+        }
+    }
+
+    // Component wise product
+    pub fn mul_elements(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x * other.x, self.y * other.y, self.z * other.z)
+    }
+
+    pub fn lerp(self, other: Vec3, t: f32) -> Vec3 {
+        self + (other - self) * t
+    }
+
+    // Mirrors the vector, pointing at a surface, about the unit normal
+    pub fn reflect(self, normal: Vec3) -> Vec3 {
+        self - normal * (2.0 * self.dot(normal))
+    }
+
+    // Bends the unit vector, pointing at a surface, through it by the ratio of refractive indices
+    // (outside / inside), None on total internal reflection
+    pub fn refract(self, normal: Vec3, eta: f32) -> Option<Vec3> {
+        let cos_incident = -self.dot(normal);
+        let k = 1.0 - eta * eta * (1.0 - cos_incident * cos_incident);
+        if k < 0.0 {
+            None
+        } else {
+            Some(self * eta + normal * (eta * cos_incident - k.sqrt()))
+        }
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, other: Vec3) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+    fn mul(self, s: f32) -> Vec3 {
+        Vec3::new(self.x * s, self.y * s, self.z * s)
+    }
+}
+
+impl Div<f32> for Vec3 {
+    type Output = Vec3;
+    fn div(self, s: f32) -> Vec3 {
+        Vec3::new(self.x / s, self.y / s, self.z / s)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        m: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]],
+    };
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut matrix = Self::IDENTITY;
+        matrix.m[0][3] = offset.x;
+        matrix.m[1][3] = offset.y;
+        matrix.m[2][3] = offset.z;
+        matrix
+    }
+
+    pub fn scale(factor: f32) -> Self {
+        let mut matrix = Self::IDENTITY;
+        for i in 0..3 {
+            matrix.m[i][i] = factor;
+        }
+        matrix
+    }
+
+    pub fn rotation_x(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Mat4 { m: [[1.0, 0.0, 0.0, 0.0], [0.0, cos, -sin, 0.0], [0.0, sin, cos, 0.0], [0.0, 0.0, 0.0, 1.0]] }
+    }
+
+    pub fn rotation_y(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Mat4 { m: [[cos, 0.0, sin, 0.0], [0.0, 1.0, 0.0, 0.0], [-sin, 0.0, cos, 0.0], [0.0, 0.0, 0.0, 1.0]] }
+    }
+
+    // View matrix of a camera at `eye` looking at `target`
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        let forward = (target - eye).normalized();
+        let right = forward.cross(up).normalized();
+        let up = right.cross(forward);
+        Mat4 {
+            m: [
+                [right.x, right.y, right.z, -right.dot(eye)],
+                [up.x, up.y, up.z, -up.dot(eye)],
+                [-forward.x, -forward.y, -forward.z, forward.dot(eye)],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    // Perspective projection with a vertical field of view in radians
+    pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+        let f = 1.0 / (fov_y / 2.0).tan();
+        Mat4 {
+            m: [
+                [f / aspect, 0.0, 0.0, 0.0],
+                [0.0, f, 0.0, 0.0],
+                [0.0, 0.0, (far + near) / (near - far), 2.0 * far * near / (near - far)],
+                [0.0, 0.0, -1.0, 0.0],
+            ],
+        }
+    }
+
+    // The point with w = 1, returning the homogeneous result
+    pub fn transform(&self, p: Vec3) -> [f32; 4] {
+        let row = |r: &[f32; 4]| r[0] * p.x + r[1] * p.y + r[2] * p.z + r[3];
+        [row(&self.m[0]), row(&self.m[1]), row(&self.m[2]), row(&self.m[3])]
+    }
+
+    // The point with w = 1, for affine matrices
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let [x, y, z, _] = self.transform(p);
+        Vec3::new(x, y, z)
+    }
+
+    // The direction with w = 0. Normals stay correct as long as the scale is uniform.
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let row = |r: &[f32; 4]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+        Vec3::new(row(&self.m[0]), row(&self.m[1]), row(&self.m[2]))
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (r, row) in m.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[r][k] * other.m[k][c]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+}
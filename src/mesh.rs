@@ -0,0 +1,330 @@
+use crate::math::Vec3;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// Indexed triangle meshes. Front faces wind counter-clockwise seen from outside.
+//
+// Vertices carry a tangent frame for normal mapping: the tangent points along increasing u,
+// and the bitangent, rebuilt as normal x tangent times the handedness, along increasing v.
+// Textures have v going down, so tangent space normal maps follow the DirectX convention
+// with green pointing down the image.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: (f32, f32),
+    // Unit length and perpendicular to the normal
+    pub tangent: Vec3,
+    // +1 or -1, -1 where the texture is mirrored
+    pub handedness: f32,
+}
+
+impl MeshVertex {
+    // The tangent frame is left for Mesh::generate_tangents
+    pub fn new(position: Vec3, normal: Vec3, uv: (f32, f32)) -> Self {
+        Self { position, normal, uv, tangent: Vec3::ZERO, handedness: 1.0 }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<MeshVertex>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl Mesh {
+    // Axis aligned cube around the origin, with its own vertices per face for flat normals
+    pub fn cube(size: f32) -> Self {
+        let h = size / 2.0;
+        let mut mesh = Mesh::default();
+        let faces = [
+            (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+            (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+            (Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            (Vec3::new(0.0, -1.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+            (Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0)),
+            (Vec3::new(0.0, 0.0, -1.0), Vec3::new(-1.0, 0.0, 0.0)),
+        ];
+        for (normal, right) in faces {
+            let up = normal.cross(right);
+            let base = mesh.vertices.len() as u32;
+            for (u, v) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                let position = (normal + right * (u * 2.0 - 1.0) + up * (1.0 - v * 2.0)) * h;
+                mesh.vertices.push(MeshVertex::new(position, normal, (u, v)));
+            }
+            mesh.triangles.push([base, base + 2, base + 1]);
+            mesh.triangles.push([base, base + 3, base + 2]);
+        }
+        mesh.generate_tangents();
+        mesh
+    }
+
+    // Sphere around the origin made of `segments` slices around the y axis and `rings` stacks
+    pub fn sphere(radius: f32, segments: u32, rings: u32) -> Self {
+        let (segments, rings) = (segments.max(3), rings.max(2));
+        let mut mesh = Mesh::default();
+        for ring in 0..=rings {
+            let v = ring as f32 / rings as f32;
+            let (sin_theta, cos_theta) = (v * PI).sin_cos();
+            for segment in 0..=segments {
+                let u = segment as f32 / segments as f32;
+                let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+                let normal = Vec3::new(sin_theta * sin_phi, cos_theta, sin_theta * cos_phi);
+                mesh.vertices.push(MeshVertex::new(normal * radius, normal, (u, v)));
+            }
+        }
+        let stride = segments + 1;
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let a = ring * stride + segment;
+                let b = a + stride;
+                if ring != 0 {
+                    mesh.triangles.push([a, b, a + 1]);
+                }
+                if ring != rings - 1 {
+                    mesh.triangles.push([a + 1, b, b + 1]);
+                }
+            }
+        }
+        mesh.generate_tangents();
+        mesh
+    }
+
+    // Square in the xz plane facing up, its texture repeated `tiles` times
+    pub fn plane(size: f32, tiles: f32) -> Self {
+        let h = size / 2.0;
+        let corners = [(-h, -h, 0.0, 0.0), (h, -h, tiles, 0.0), (h, h, tiles, tiles), (-h, h, 0.0, tiles)];
+        let mut mesh = Mesh {
+            vertices: corners.iter().map(|&(x, z, u, v)| MeshVertex::new(Vec3::new(x, 0.0, z), Vec3::UP, (u, v))).collect(),
+            triangles: vec![[0, 3, 2], [0, 2, 1]],
+        };
+        mesh.generate_tangents();
+        mesh
+    }
+
+    // Tangent frames from the positions, normals and texture coordinates, in the spirit of
+    // MikkTSpace : the tangent and bitangent of every triangle are projected onto the tangent
+    // plane of each corner and summed weighted by the corner angle, then the tangent is made
+    // perpendicular to the normal and the bitangent kept only as the handedness. Vertices
+    // without usable texture coordinates get an arbitrary tangent.
+    pub fn generate_tangents(&mut self) {
+        let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+        let mut bitangents = vec![Vec3::ZERO; self.vertices.len()];
+        for triangle in &self.triangles {
+            let [a, b, c] = triangle.map(|i| self.vertices[i as usize]);
+            let (e1, e2) = (b.position - a.position, c.position - a.position);
+            let (du1, dv1, du2, dv2) = (b.uv.0 - a.uv.0, b.uv.1 - a.uv.1, c.uv.0 - a.uv.0, c.uv.1 - a.uv.1);
+            let determinant = du1 * dv2 - du2 * dv1;
+            if determinant.abs() < 1e-12 {
+                continue;
+            }
+            let tangent = (e1 * dv2 - e2 * dv1) / determinant;
+            let bitangent = (e2 * du1 - e1 * du2) / determinant;
+
+            for corner in 0..3 {
+                let vertex = self.vertices[triangle[corner] as usize];
+                let to_next = self.vertices[triangle[(corner + 1) % 3] as usize].position - vertex.position;
+                let to_previous = self.vertices[triangle[(corner + 2) % 3] as usize].position - vertex.position;
+                let angle = to_next.normalized().dot(to_previous.normalized()).clamp(-1.0, 1.0).acos();
+                let n = vertex.normal;
+                tangents[triangle[corner] as usize] += (tangent - n * n.dot(tangent)).normalized() * angle;
+                bitangents[triangle[corner] as usize] += (bitangent - n * n.dot(bitangent)).normalized() * angle;
+            }
+        }
+
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents) {
+            let n = vertex.normal;
+            let mut t = tangent - n * n.dot(tangent);
+            if t.length() < 1e-6 {
+                let helper = if n.x.abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::UP };
+                t = helper - n * n.dot(helper);
+            }
+            vertex.tangent = t.normalized();
+            vertex.handedness = if n.cross(vertex.tangent).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+        }
+    }
+
+    // Smooth normals from the triangles around each vertex, weighted by their area
+    pub fn generate_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+        for triangle in &self.triangles {
+            let [a, b, c] = triangle.map(|i| self.vertices[i as usize].position);
+            let normal = (b - a).cross(c - a);
+            for &i in triangle {
+                normals[i as usize] += normal;
+            }
+        }
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = if normal.length() > 0.0 { normal.normalized() } else { Vec3::UP };
+        }
+    }
+}
+
+pub fn load_obj(file: &str) -> Result<Mesh, String> {
+    let source = std::fs::read_to_string(file).map_err(|e| format!("Could not read {}: {}", file, e))?;
+    parse_obj(&source)
+}
+
+// Wavefront OBJ geometry : v, vt and vn lines and faces of any size, which are split into
+// fans. Indices may be negative, counting back from the last element. Normals are generated
+// when the file has none, tangents always.
+pub fn parse_obj(source: &str) -> Result<Mesh, String> {
+    let (mut positions, mut uvs, mut normals) = (Vec::new(), Vec::new(), Vec::new());
+    let mut mesh = Mesh::default();
+    let mut corners: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+    let mut has_normals = true;
+
+    for (number, line) in source.lines().enumerate() {
+        let mut fields = line.split_whitespace();
+        let invalid = || format!("Invalid OBJ line {}: \"{}\"", number + 1, line.trim());
+        match fields.next() {
+            Some("v") => {
+                let [x, y, z] = floats(fields).ok_or_else(invalid)?;
+                positions.push(Vec3::new(x, y, z));
+            }
+            Some("vt") => {
+                let [u, v] = floats(fields).ok_or_else(invalid)?;
+                // OBJ counts v up from the bottom of the image
+                uvs.push((u, 1.0 - v));
+            }
+            Some("vn") => {
+                let [x, y, z] = floats(fields).ok_or_else(invalid)?;
+                normals.push(Vec3::new(x, y, z).normalized());
+            }
+            Some("f") => {
+                let mut face = Vec::new();
+                for corner in fields {
+                    let mut parts = corner.split('/');
+                    let resolve = |part: Option<&str>, count: usize| -> Result<Option<usize>, String> {
+                        match part.filter(|part| !part.is_empty()) {
+                            None => Ok(None),
+                            Some(part) => {
+                                let index: i64 = part.parse().map_err(|_| invalid())?;
+                                let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+                                if resolved < 0 || resolved >= count as i64 {
+                                    return Err(format!("OBJ line {}: index {} out of range", number + 1, index));
+                                }
+                                Ok(Some(resolved as usize))
+                            }
+                        }
+                    };
+                    let position = resolve(parts.next(), positions.len())?.ok_or_else(invalid)?;
+                    let uv = resolve(parts.next(), uvs.len())?;
+                    let normal = resolve(parts.next(), normals.len())?;
+                    has_normals &= normal.is_some();
+
+                    let key = (position, uv, normal);
+                    let index = *corners.entry(key).or_insert_with(|| {
+                        let uv = uv.map_or((0.0, 0.0), |i| uvs[i]);
+                        let normal = normal.map_or(Vec3::UP, |i| normals[i]);
+                        mesh.vertices.push(MeshVertex::new(positions[position], normal, uv));
+                        mesh.vertices.len() as u32 - 1
+                    });
+                    face.push(index);
+                }
+                if face.len() < 3 {
+                    return Err(invalid());
+                }
+                for i in 1..face.len() - 1 {
+                    mesh.triangles.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if mesh.triangles.is_empty() {
+        return Err("OBJ file has no faces".to_string());
+    }
+    if !has_normals {
+        mesh.generate_normals();
+    }
+    mesh.generate_tangents();
+    Ok(mesh)
+}
+
+// The first N fields as numbers, None when there are fewer or one is not a number
+fn floats<'a, const N: usize>(mut fields: impl Iterator<Item = &'a str>) -> Option<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = fields.next()?.parse().ok()?;
+    }
+    Some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn quad_face_is_split_into_a_fan() {
+        let mesh = parse_obj("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1 4//1\n").unwrap();
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!(mesh.vertices.iter().all(|v| close(v.normal, Vec3::new(0.0, 0.0, 1.0))));
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_end() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nf -3/-3 -2/-2 -1/-1\n";
+        let mesh = parse_obj(source).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        assert!(close(mesh.vertices[1].position, Vec3::new(1.0, 0.0, 0.0)));
+        // v is flipped to count down from the top of the image
+        assert_eq!(mesh.vertices[2].uv, (0.0, 0.0));
+        assert_eq!(mesh.vertices[0].uv, (0.0, 1.0));
+    }
+
+    #[test]
+    fn missing_normals_are_generated() {
+        let mesh = parse_obj("v 0 0 0\nv 0 0 1\nv 1 0 0\nf 1 2 3\n").unwrap();
+        assert!(mesh.vertices.iter().all(|v| close(v.normal, Vec3::UP)));
+    }
+
+    #[test]
+    fn invalid_indices_are_errors() {
+        let triangle = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+        assert!(parse_obj(&format!("{}f 0 1 2\n", triangle)).is_err());
+        assert!(parse_obj(&format!("{}f 1 2 4\n", triangle)).is_err());
+        assert!(parse_obj(&format!("{}f 1 2 -4\n", triangle)).is_err());
+        assert!(parse_obj(&format!("{}f 1/1 2 3\n", triangle)).is_err());
+        assert!(parse_obj(&format!("{}f 1 2\n", triangle)).is_err());
+        assert!(parse_obj(triangle).is_err());
+    }
+
+    // Unit tangents perpendicular to the normals, with the frame following increasing u and v
+    fn assert_tangent_frames(mesh: &Mesh) {
+        for vertex in &mesh.vertices {
+            assert!((vertex.tangent.length() - 1.0).abs() < 1e-4, "{:?}", vertex);
+            assert!(vertex.tangent.dot(vertex.normal).abs() < 1e-4, "{:?}", vertex);
+            assert!(vertex.handedness == 1.0 || vertex.handedness == -1.0, "{:?}", vertex);
+        }
+        for triangle in &mesh.triangles {
+            let [a, b, c] = triangle.map(|i| mesh.vertices[i as usize]);
+            let (e1, e2) = (b.position - a.position, c.position - a.position);
+            let (du1, dv1, du2, dv2) = (b.uv.0 - a.uv.0, b.uv.1 - a.uv.1, c.uv.0 - a.uv.0, c.uv.1 - a.uv.1);
+            let determinant = du1 * dv2 - du2 * dv1;
+            let along_u = (e1 * dv2 - e2 * dv1) / determinant;
+            let along_v = (e2 * du1 - e1 * du2) / determinant;
+            for vertex in [a, b, c] {
+                let bitangent = vertex.normal.cross(vertex.tangent) * vertex.handedness;
+                assert!(vertex.tangent.dot(along_u) > 0.0 && bitangent.dot(along_v) > 0.0, "{:?}", vertex);
+            }
+        }
+    }
+
+    #[test]
+    fn plane_and_cube_tangents_follow_the_texture() {
+        assert_tangent_frames(&Mesh::plane(4.0, 3.0));
+        assert_tangent_frames(&Mesh::cube(2.0));
+        // The poles have no usable u direction, so the sphere only gets the frame checks
+        for v in Mesh::sphere(1.0, 12, 8).vertices {
+            assert!(v.tangent.dot(v.normal).abs() < 1e-4 && (v.tangent.length() - 1.0).abs() < 1e-4, "{:?}", v);
+        }
+    }
+}
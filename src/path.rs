@@ -0,0 +1,424 @@
+use crate::clip::{draw_clipped_line, LineClip};
+use crate::color::interpolate_color;
+use crate::scanline::{color_attrs, EdgeTable, EdgeVertex, FillRule, Span};
+use sdl2::pixels::Color;
+
+// Paths made of lines and quadratic / cubic Bézier curves.
+//
+// Curves are flattened into polylines by adaptive subdivision: a segment is split in half
+// with de Casteljau until its control points lie within `tolerance` pixels of the chord,
+// so flat parts become a single line and tight bends get as many as they need. Strokes
+// are then drawn with draw_clipped_line and fills go through the scanline edge table.
+
+pub type Point = (f32, f32);
+
+// Deep enough for any curve that fits on screen, guards against NaN or huge coordinates
+const MAX_SUBDIVISION_DEPTH: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathCommand {
+    MoveTo(Point),
+    LineTo(Point),
+    QuadTo(Point, Point),
+    CubicTo(Point, Point, Point),
+    Close,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Path {
+    pub commands: Vec<PathCommand>,
+}
+
+// 2D affine transform mapping (x, y) to (a x + c y + e, b x + d y + f), the SVG matrix layout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self { a: 1.0, b: 0.0, c: 0.0, d: 1.0, e: 0.0, f: 0.0 }
+    }
+
+    pub fn translate(x: f32, y: f32) -> Self {
+        Self { e: x, f: y, ..Self::identity() }
+    }
+
+    pub fn scale(x: f32, y: f32) -> Self {
+        Self { a: x, d: y, ..Self::identity() }
+    }
+
+    // Rotation by `angle` radians, clockwise on screen since y points down
+    pub fn rotate(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self { a: cos, b: sin, c: -sin, d: cos, e: 0.0, f: 0.0 }
+    }
+
+    pub fn skew(x_angle: f32, y_angle: f32) -> Self {
+        Self { b: y_angle.tan(), c: x_angle.tan(), ..Self::identity() }
+    }
+
+    // The transform applying `other` first and then `self`
+    pub fn then(&self, other: &Transform) -> Transform {
+        Transform {
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f,
+        }
+    }
+
+    pub fn apply(&self, p: Point) -> Point {
+        (self.a * p.0 + self.c * p.1 + self.e, self.b * p.0 + self.d * p.1 + self.f)
+    }
+}
+
+// A flattened subpath
+#[derive(Clone, Debug, Default)]
+pub struct Polyline {
+    pub points: Vec<Point>,
+    pub closed: bool,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self { commands: Vec::new() }
+    }
+
+    pub fn move_to(&mut self, x: f32, y: f32) -> &mut Self {
+        self.commands.push(PathCommand::MoveTo((x, y)));
+        self
+    }
+
+    pub fn line_to(&mut self, x: f32, y: f32) -> &mut Self {
+        self.commands.push(PathCommand::LineTo((x, y)));
+        self
+    }
+
+    pub fn quad_to(&mut self, cx: f32, cy: f32, x: f32, y: f32) -> &mut Self {
+        self.commands.push(PathCommand::QuadTo((cx, cy), (x, y)));
+        self
+    }
+
+    pub fn cubic_to(&mut self, c1x: f32, c1y: f32, c2x: f32, c2y: f32, x: f32, y: f32) -> &mut Self {
+        self.commands.push(PathCommand::CubicTo((c1x, c1y), (c2x, c2y), (x, y)));
+        self
+    }
+
+    pub fn close(&mut self) -> &mut Self {
+        self.commands.push(PathCommand::Close);
+        self
+    }
+
+    // Affine transforms keep Bézier curves Bézier curves, so transforming the points is enough
+    pub fn transformed(&self, transform: &Transform) -> Path {
+        let t = |p: Point| transform.apply(p);
+        let commands = self
+            .commands
+            .iter()
+            .map(|command| match *command {
+                PathCommand::MoveTo(p) => PathCommand::MoveTo(t(p)),
+                PathCommand::LineTo(p) => PathCommand::LineTo(t(p)),
+                PathCommand::QuadTo(c, p) => PathCommand::QuadTo(t(c), t(p)),
+                PathCommand::CubicTo(c1, c2, p) => PathCommand::CubicTo(t(c1), t(c2), t(p)),
+                PathCommand::Close => PathCommand::Close,
+            })
+            .collect();
+        Path { commands }
+    }
+
+    // Turns every subpath into a polyline, curves are subdivided until flat within `tolerance`
+    pub fn flatten(&self, tolerance: f32) -> Vec<Polyline> {
+        let tolerance = tolerance.max(0.01);
+        let mut polylines = Vec::new();
+        let mut current = Polyline::default();
+        let mut start = (0.0, 0.0);
+        let mut pen = (0.0, 0.0);
+
+        for command in &self.commands {
+            match *command {
+                PathCommand::MoveTo(p) => {
+                    if current.points.len() > 1 {
+                        polylines.push(std::mem::take(&mut current));
+                    }
+                    current.points.clear();
+                    current.closed = false;
+                    current.points.push(p);
+                    start = p;
+                    pen = p;
+                }
+                PathCommand::LineTo(p) => {
+                    begin(&mut current, pen);
+                    current.points.push(p);
+                    pen = p;
+                }
+                PathCommand::QuadTo(c, p) => {
+                    begin(&mut current, pen);
+                    flatten_quadratic(pen, c, p, tolerance, &mut current.points);
+                    pen = p;
+                }
+                PathCommand::CubicTo(c1, c2, p) => {
+                    begin(&mut current, pen);
+                    flatten_cubic(pen, c1, c2, p, tolerance, &mut current.points);
+                    pen = p;
+                }
+                PathCommand::Close => {
+                    // A subpath with a segment is finished, a lone point is dropped. Either way
+                    // drawing continues from the subpath start.
+                    if current.points.len() > 1 {
+                        current.closed = true;
+                        polylines.push(std::mem::take(&mut current));
+                    } else {
+                        current.points.clear();
+                    }
+                    current.points.push(start);
+                    pen = start;
+                }
+            }
+        }
+        if current.points.len() > 1 {
+            polylines.push(current);
+        }
+        polylines
+    }
+}
+
+// Drawing without a move first starts at the current pen position
+fn begin(polyline: &mut Polyline, pen: Point) {
+    if polyline.points.is_empty() {
+        polyline.points.push(pen);
+    }
+}
+
+fn distance_to_chord(p: Point, a: Point, b: Point) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        return ((p.0 - a.0).powi(2) + (p.1 - a.1).powi(2)).sqrt();
+    }
+    ((p.0 - a.0) * dy - (p.1 - a.1) * dx).abs() / length
+}
+
+fn midpoint(a: Point, b: Point) -> Point {
+    ((a.0 + b.0) * 0.5, (a.1 + b.1) * 0.5)
+}
+
+// Appends the points after p0 of the flattened quadratic curve
+pub fn flatten_quadratic(p0: Point, p1: Point, p2: Point, tolerance: f32, out: &mut Vec<Point>) {
+    subdivide_quadratic(p0, p1, p2, tolerance, 0, out);
+}
+
+fn subdivide_quadratic(p0: Point, p1: Point, p2: Point, tolerance: f32, depth: u32, out: &mut Vec<Point>) {
+    if depth >= MAX_SUBDIVISION_DEPTH || distance_to_chord(p1, p0, p2) <= tolerance {
+        out.push(p2);
+        return;
+    }
+    let a = midpoint(p0, p1);
+    let b = midpoint(p1, p2);
+    let mid = midpoint(a, b);
+    subdivide_quadratic(p0, a, mid, tolerance, depth + 1, out);
+    subdivide_quadratic(mid, b, p2, tolerance, depth + 1, out);
+}
+
+// Appends the points after p0 of the flattened cubic curve
+pub fn flatten_cubic(p0: Point, p1: Point, p2: Point, p3: Point, tolerance: f32, out: &mut Vec<Point>) {
+    subdivide_cubic([p0, p1, p2, p3], tolerance, 0, out);
+}
+
+fn subdivide_cubic(p: [Point; 4], tolerance: f32, depth: u32, out: &mut Vec<Point>) {
+    let flat = distance_to_chord(p[1], p[0], p[3]).max(distance_to_chord(p[2], p[0], p[3])) <= tolerance;
+    if depth >= MAX_SUBDIVISION_DEPTH || flat {
+        out.push(p[3]);
+        return;
+    }
+    let ab = midpoint(p[0], p[1]);
+    let bc = midpoint(p[1], p[2]);
+    let cd = midpoint(p[2], p[3]);
+    let abc = midpoint(ab, bc);
+    let bcd = midpoint(bc, cd);
+    let mid = midpoint(abc, bcd);
+    subdivide_cubic([p[0], ab, abc, mid], tolerance, depth + 1, out);
+    subdivide_cubic([mid, bcd, cd, p[3]], tolerance, depth + 1, out);
+}
+
+// Strokes every subpath, the colors blend from start to end along each subpath's length
+pub fn stroke_path<F: FnMut(i32, i32, Color)>(
+    path: &Path,
+    tolerance: f32,
+    clip: &LineClip,
+    resolution: i32,
+    colors: (Color, Color),
+    mut plot: F,
+) {
+    for polyline in path.flatten(tolerance) {
+        let mut points = polyline.points;
+        if polyline.closed {
+            points.push(points[0]);
+        }
+        let total: f32 = points.windows(2).map(|w| segment_length(w[0], w[1])).sum();
+        let mut travelled = 0.0;
+
+        for w in points.windows(2) {
+            let length = segment_length(w[0], w[1]);
+            let t0 = if total > 0.0 { travelled / total } else { 0.0 };
+            let t1 = if total > 0.0 { (travelled + length) / total } else { 0.0 };
+            travelled += length;
+            draw_clipped_line(
+                clip,
+                w[0].0.round() as i32,
+                w[0].1.round() as i32,
+                w[1].0.round() as i32,
+                w[1].1.round() as i32,
+                interpolate_color(colors.0, colors.1, t0),
+                interpolate_color(colors.0, colors.1, t1),
+                resolution,
+                &mut plot,
+            );
+        }
+    }
+}
+
+fn segment_length(a: Point, b: Point) -> f32 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+// Fills the area enclosed by the path with a flat color, open subpaths are closed implicitly
+pub fn fill_path<F: FnMut(&Span<4>)>(
+    path: &Path,
+    tolerance: f32,
+    rule: FillRule,
+    color: Color,
+    resolution: i32,
+    edge_table: &mut EdgeTable<4>,
+    emit: F,
+) {
+    edge_table.reset(resolution);
+    let attrs = color_attrs(color);
+    for polyline in path.flatten(tolerance) {
+        let points = &polyline.points;
+        for i in 0..points.len() {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            edge_table.add_edge(
+                &EdgeVertex { x: a.0, y: a.1, attrs },
+                &EdgeVertex { x: b.0, y: b.1, attrs },
+            );
+        }
+    }
+    edge_table.fill_spans(rule, emit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quadratic_at(p: [Point; 3], t: f32) -> Point {
+        let u = 1.0 - t;
+        let along = |a: f32, b: f32, c: f32| u * u * a + 2.0 * u * t * b + t * t * c;
+        (along(p[0].0, p[1].0, p[2].0), along(p[0].1, p[1].1, p[2].1))
+    }
+
+    fn cubic_at(p: [Point; 4], t: f32) -> Point {
+        let u = 1.0 - t;
+        let along = |a: f32, b: f32, c: f32, d: f32| u * u * u * a + 3.0 * u * u * t * b + 3.0 * u * t * t * c + t * t * t * d;
+        (along(p[0].0, p[1].0, p[2].0, p[3].0), along(p[0].1, p[1].1, p[2].1, p[3].1))
+    }
+
+    fn distance_to_segment(p: Point, a: Point, b: Point) -> f32 {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let length2 = dx * dx + dy * dy;
+        let t = if length2 == 0.0 { 0.0 } else { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length2).clamp(0.0, 1.0) };
+        ((p.0 - a.0 - t * dx).powi(2) + (p.1 - a.1 - t * dy).powi(2)).sqrt()
+    }
+
+    // The largest distance from sampled points of the curve to the polyline
+    fn deviation<C: Fn(f32) -> Point>(curve: C, polyline: &[Point]) -> f32 {
+        (0..=1000)
+            .map(|i| {
+                let p = curve(i as f32 / 1000.0);
+                polyline.windows(2).map(|w| distance_to_segment(p, w[0], w[1])).fold(f32::MAX, f32::min)
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn quadratic_stays_within_tolerance() {
+        let p = [(0.0, 0.0), (50.0, 120.0), (100.0, 0.0)];
+        let mut counts = Vec::new();
+        for tolerance in [4.0, 1.0, 0.25] {
+            let mut points = vec![p[0]];
+            flatten_quadratic(p[0], p[1], p[2], tolerance, &mut points);
+            assert_eq!(*points.last().unwrap(), p[2]);
+            assert!(deviation(|t| quadratic_at(p, t), &points) <= tolerance);
+            counts.push(points.len());
+        }
+        assert!(counts[0] < counts[1] && counts[1] < counts[2], "{:?}", counts);
+        // The error of a split quadratic drops by four, so four times the precision costs twice the points
+        assert!(counts[2] <= 2 * counts[1] + 1, "{:?}", counts);
+    }
+
+    #[test]
+    fn cubic_stays_within_tolerance() {
+        let p = [(0.0, 0.0), (0.0, 150.0), (200.0, -150.0), (200.0, 0.0)];
+        let mut counts = Vec::new();
+        for tolerance in [4.0, 1.0, 0.25] {
+            let mut points = vec![p[0]];
+            flatten_cubic(p[0], p[1], p[2], p[3], tolerance, &mut points);
+            assert_eq!(*points.last().unwrap(), p[3]);
+            assert!(deviation(|t| cubic_at(p, t), &points) <= tolerance);
+            counts.push(points.len());
+        }
+        assert!(counts[0] < counts[1] && counts[1] < counts[2], "{:?}", counts);
+    }
+
+    #[test]
+    fn flat_curves_become_a_single_segment() {
+        let mut points = Vec::new();
+        flatten_quadratic((0.0, 0.0), (5.0, 5.0), (10.0, 10.0), 0.1, &mut points);
+        flatten_cubic((10.0, 10.0), (12.0, 10.0), (18.0, 10.0), (20.0, 10.0), 0.1, &mut points);
+        assert_eq!(points, vec![(10.0, 10.0), (20.0, 10.0)]);
+    }
+
+    #[test]
+    fn then_applies_the_argument_first() {
+        let translate = Transform::translate(10.0, 0.0);
+        let scale = Transform::scale(2.0, 3.0);
+        assert_eq!(translate.then(&scale).apply((1.0, 1.0)), (12.0, 3.0));
+        assert_eq!(scale.then(&translate).apply((1.0, 1.0)), (22.0, 3.0));
+
+        let rotate = Transform::rotate(0.7);
+        let combined = rotate.then(&scale).then(&translate);
+        let (x, y) = combined.apply((4.0, -2.0));
+        let (ex, ey) = rotate.apply(scale.apply(translate.apply((4.0, -2.0))));
+        assert!((x - ex).abs() < 1e-4 && (y - ey).abs() < 1e-4);
+    }
+
+    #[test]
+    fn close_restarts_at_the_subpath_start() {
+        let mut path = Path::new();
+        path.move_to(1.0, 1.0).close().line_to(5.0, 1.0);
+        let polylines = path.flatten(0.5);
+        assert_eq!(polylines.len(), 1);
+        assert_eq!(polylines[0].points, vec![(1.0, 1.0), (5.0, 1.0)]);
+        assert!(!polylines[0].closed);
+
+        let mut path = Path::new();
+        path.move_to(0.0, 0.0).line_to(4.0, 0.0).line_to(4.0, 4.0).close().line_to(0.0, 4.0);
+        let polylines = path.flatten(0.5);
+        assert_eq!(polylines.len(), 2);
+        assert!(polylines[0].closed);
+        assert_eq!(polylines[1].points, vec![(0.0, 0.0), (0.0, 4.0)]);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::environment::ImageBasedLight;
+use crate::light::{PointLight, ShadingModel, Surface};
+use crate::math::Vec3;
+use std::f32::consts::PI;
+
+// Physically based shading with the metallic-roughness model.
+//
+// The specular BRDF is Cook–Torrance with the GGX distribution, the height correlated Smith
+// visibility term and Schlick's Fresnel, the diffuse BRDF is Lambert. Metals have no diffuse
+// part and tint their reflections with the albedo, dielectrics reflect 4% at normal incidence.
+// The diffuse part only gets what the surface does not reflect toward the eye, so the two
+// together never return more light than arrives, even at grazing angles.
+// Point lights are punctual, with the light intensity as the irradiance they deliver, and the
+// environment adds image based lighting, or a flat ambient color without an environment.
+
+// Roughness is clamped to this, a perfect mirror would make point light highlights vanish
+const MIN_ROUGHNESS: f32 = 0.045;
+
+#[derive(Clone, Copy)]
+pub struct CookTorrance<'a> {
+    pub environment: Option<&'a ImageBasedLight>,
+    // Scales the environment light
+    pub environment_intensity: f32,
+    // Used instead of the environment when there is none
+    pub ambient: Vec3,
+}
+
+impl<'a> CookTorrance<'a> {
+    pub fn new(environment: Option<&'a ImageBasedLight>) -> Self {
+        Self { environment, environment_intensity: 1.0, ambient: Vec3::splat(0.03) }
+    }
+}
+
+// Reflectance at normal incidence
+fn base_reflectance(surface: &Surface) -> Vec3 {
+    Vec3::splat(0.04).lerp(surface.albedo, surface.metallic)
+}
+
+fn fresnel_schlick(f0: Vec3, cos_theta: f32) -> Vec3 {
+    f0 + (Vec3::splat(1.0) - f0) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+// Fresnel for rough surfaces lit by the environment, which do not reach full reflection at grazing angles
+fn fresnel_schlick_roughness(f0: Vec3, cos_theta: f32, roughness: f32) -> Vec3 {
+    let f90 = Vec3::new(f0.x.max(1.0 - roughness), f0.y.max(1.0 - roughness), f0.z.max(1.0 - roughness));
+    f0 + (f90 - f0) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+// Smith visibility, the geometry term divided by 4 n.l n.v
+fn visibility_smith_ggx(n_dot_v: f32, n_dot_l: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    let view = n_dot_l * (n_dot_v * n_dot_v * (1.0 - a2) + a2).sqrt();
+    let light = n_dot_v * (n_dot_l * n_dot_l * (1.0 - a2) + a2).sqrt();
+    0.5 / (view + light).max(1e-6)
+}
+
+impl ShadingModel for CookTorrance<'_> {
+    fn shade(&self, surface: &Surface, lights: &[PointLight], eye: Vec3, ambient_visibility: f32) -> Vec3 {
+        let normal = surface.normal;
+        let view = (eye - surface.position).normalized();
+        let n_dot_v = normal.dot(view).max(1e-4);
+        let roughness = surface.roughness.clamp(MIN_ROUGHNESS, 1.0);
+        let alpha = roughness * roughness;
+        let f0 = base_reflectance(surface);
+        let diffuse_color = surface.albedo * (1.0 - surface.metallic);
+        let diffuse = (Vec3::splat(1.0) - fresnel_schlick(f0, n_dot_v)).mul_elements(diffuse_color) / PI;
+
+        let mut result = Vec3::ZERO;
+        for light in lights {
+            let to_light = light.position - surface.position;
+            let distance = to_light.length();
+            if distance >= light.range {
+                continue;
+            }
+            let l = to_light / distance;
+            let n_dot_l = normal.dot(l);
+            if n_dot_l <= 0.0 {
+                continue;
+            }
+            let half = (l + view).normalized();
+            let fresnel = fresnel_schlick(f0, view.dot(half));
+            let specular = fresnel * (distribution_ggx(normal.dot(half).max(0.0), alpha) * visibility_smith_ggx(n_dot_v, n_dot_l, alpha));
+            let irradiance = light.color * (light.intensity * light.attenuation(distance) * n_dot_l);
+            result += (diffuse + specular).mul_elements(irradiance);
+        }
+
+        let ambient = match self.environment {
+            Some(environment) => {
+                let fresnel = fresnel_schlick_roughness(f0, n_dot_v, roughness);
+                let diffuse = (Vec3::splat(1.0) - fresnel).mul_elements(diffuse_color).mul_elements(environment.irradiance(normal));
+                let (scale, bias) = environment.brdf(n_dot_v, roughness);
+                let reflected = (-view).reflect(normal);
+                let specular = environment.prefiltered(reflected, roughness).mul_elements(f0 * scale + Vec3::splat(bias));
+                (diffuse + specular) * self.environment_intensity
+            }
+            None => self.ambient.mul_elements(surface.albedo),
+        };
+        result + ambient * ambient_visibility
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAR: f32 = 1.0e4;
+
+    fn surface(metallic: f32, roughness: f32) -> Surface {
+        Surface { normal: Vec3::UP, albedo: Vec3::splat(1.0), metallic, roughness, ..Surface::default() }
+    }
+
+    // The BRDF times n.l for a light in direction `l` and the eye in direction `v`
+    fn reflected(shading: &CookTorrance, surface: &Surface, l: Vec3, v: Vec3) -> Vec3 {
+        let light = PointLight::new(l * 10.0, Vec3::splat(1.0), 1.0, FAR);
+        shading.shade(surface, &[light], v * 10.0, 1.0) / light.attenuation(10.0)
+    }
+
+    fn direction(theta: f32, phi: f32) -> Vec3 {
+        Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+    }
+
+    #[test]
+    fn no_more_light_leaves_than_arrives() {
+        let shading = CookTorrance { ambient: Vec3::ZERO, ..CookTorrance::new(None) };
+        for metallic in [0.0, 1.0] {
+            for roughness in [0.3, 0.6, 1.0] {
+                for view_angle in [0.0f32, 45.0, 80.0] {
+                    let v = direction(view_angle.to_radians(), 0.0);
+                    // Reflected light summed over the hemisphere of light directions, in equal solid angle cells
+                    let (rings, segments) = (256, 128);
+                    let mut albedo = 0.0;
+                    for ring in 0..rings {
+                        let theta = ((ring as f32 + 0.5) / rings as f32).acos();
+                        for segment in 0..segments {
+                            let phi = (segment as f32 + 0.5) / segments as f32 * 2.0 * PI;
+                            albedo += reflected(&shading, &surface(metallic, roughness), direction(theta, phi), v).x;
+                        }
+                    }
+                    albedo *= 2.0 * PI / (rings * segments) as f32;
+                    let case = format!("metallic {} roughness {} view {}", metallic, roughness, view_angle);
+                    assert!(albedo <= 1.01, "{}: {}", case, albedo);
+                    // A white dielectric seen head on loses next to nothing
+                    if metallic == 0.0 && view_angle == 0.0 {
+                        assert!(albedo > 0.95, "roughness {}: {}", roughness, albedo);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn extreme_inputs_stay_finite() {
+        let shading = CookTorrance::new(None);
+        let grazing = Vec3::new(1.0, 0.0, 0.0);
+        let cases = [
+            (Vec3::UP, Vec3::UP),
+            (direction(0.3, 0.0), direction(0.3, PI)),
+            (grazing, Vec3::UP),
+            (Vec3::UP, grazing),
+            (grazing, grazing),
+            (grazing, -grazing),
+            (direction(0.5, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+        ];
+        for metallic in [0.0, 1.0] {
+            for roughness in [0.0, 0.045, 1.0] {
+                for (l, v) in cases {
+                    let color = reflected(&shading, &surface(metallic, roughness), l, v);
+                    let finite = color.to_array().iter().all(|c| c.is_finite() && *c >= 0.0);
+                    assert!(finite, "metallic {} roughness {} l {:?} v {:?}: {:?}", metallic, roughness, l, v, color);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use crate::color::srgb_to_linear;
+use crate::light::Surface;
+use crate::math::{Mat4, Vec3};
+use crate::mesh::Mesh;
+use crate::raster::PolygonMode;
+use crate::scanline::{EdgeTable, EdgeVertex, FillRule};
+use crate::texture::Texture;
+use crate::viewport::Viewport;
+use sdl2::pixels::Color;
+
+// Rasterizing 3D meshes.
+//
+// Vertices are moved to world space by the model matrix and projected by the view projection
+// matrix, then mapped into the viewport. World position, normal and texture coordinates are
+// interpolated perspective correctly : the edge table steps them divided by w together with
+// 1 / w, and every fragment divides them back. What to do with a fragment is up to the
+// caller, which gets the cell, its depth and the surface there, so the same rasterization
+// feeds both forward shading and the G-buffer.
+//
+// Triangles crossing the near plane are clipped against it in clip space, before the divide by
+// w, which would flip whatever lies behind the camera. The corners it adds get their varyings
+// interpolated there, where they are still linear along the edges.
+//
+// With a normal map bound, the interpolated tangent is made perpendicular to the interpolated
+// normal again, the bitangent rebuilt from both and the handedness of the triangle, and the
+// texel taken from tangent space into world space through that frame.
+//
+// In line and point polygon modes only the edges or the vertices of the triangles left after
+// culling produce fragments. Their varyings are interpolated linearly in window space, which
+// is perspective correct as they are divided by w.
+
+// Interpolated per fragment : 1 / w, world position, normal, uv and tangent divided by w, depth
+const VARYINGS: usize = 13;
+const INV_W: usize = 0;
+const POSITION: usize = 1;
+const NORMAL: usize = 4;
+const UV: usize = 7;
+const TANGENT: usize = 9;
+const DEPTH: usize = 12;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Material<'a> {
+    // Multiplied with the texel when a texture is bound
+    pub albedo: Color,
+    pub texture: Option<&'a Texture>,
+    // Tangent space normals, see Mesh for the convention
+    pub normal_map: Option<&'a Texture>,
+    pub metallic: f32,
+    pub roughness: f32,
+    // Multiplies roughness by green and metallic by blue, stored linearly as in glTF
+    pub metallic_roughness_map: Option<&'a Texture>,
+}
+
+impl<'a> Material<'a> {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo, texture: None, normal_map: None, metallic: 0.0, roughness: 0.5, metallic_roughness_map: None }
+    }
+
+    pub fn with_texture(self, texture: &'a Texture) -> Self {
+        Self { texture: Some(texture), ..self }
+    }
+
+    pub fn with_normal_map(self, normal_map: &'a Texture) -> Self {
+        Self { normal_map: Some(normal_map), ..self }
+    }
+
+    pub fn with_metallic_roughness(self, metallic: f32, roughness: f32) -> Self {
+        Self { metallic, roughness, ..self }
+    }
+
+    pub fn with_metallic_roughness_map(self, map: &'a Texture) -> Self {
+        Self { metallic_roughness_map: Some(map), ..self }
+    }
+
+    fn metallic_roughness_at(&self, u: f32, v: f32) -> (f32, f32) {
+        match self.metallic_roughness_map {
+            Some(map) => {
+                let texel = map.sample(u, v);
+                (self.metallic * texel.b as f32 / 255.0, self.roughness * texel.g as f32 / 255.0)
+            }
+            None => (self.metallic, self.roughness),
+        }
+    }
+
+    // Linear light albedo at the texture coordinates
+    fn albedo_at(&self, u: f32, v: f32) -> Vec3 {
+        let linear = |c: Color| Vec3::new(srgb_to_linear(c.r), srgb_to_linear(c.g), srgb_to_linear(c.b));
+        let albedo = linear(self.albedo);
+        match self.texture {
+            Some(texture) => albedo.mul_elements(linear(texture.sample(u, v))),
+            None => albedo,
+        }
+    }
+
+    // The normal at the texture coordinates, in the frame of the interpolated normal and tangent
+    fn normal_at(&self, u: f32, v: f32, normal: Vec3, tangent: Vec3, bitangent: Vec3) -> Vec3 {
+        match self.normal_map {
+            Some(normal_map) => {
+                let texel = normal_map.sample(u, v);
+                let decode = |c: u8| c as f32 / 255.0 * 2.0 - 1.0;
+                (tangent * decode(texel.r) + bitangent * decode(texel.g) + normal * decode(texel.b)).normalized()
+            }
+            None => normal,
+        }
+    }
+}
+
+// A vertex before the perspective divide, its varyings laid out like the interpolated ones
+// but not yet divided by w, with 1 in place of 1 / w and the depth still to come
+#[derive(Clone, Copy, Debug)]
+struct ClipVertex {
+    position: [f32; 4],
+    varyings: [f32; VARYINGS],
+}
+
+impl ClipVertex {
+    // Signed distance to the near plane z = -w, where w is the near distance of the projection
+    fn near_distance(&self) -> f32 {
+        self.position[2] + self.position[3]
+    }
+
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        ClipVertex {
+            position: std::array::from_fn(|i| self.position[i] + (other.position[i] - self.position[i]) * t),
+            varyings: std::array::from_fn(|i| self.varyings[i] + (other.varyings[i] - self.varyings[i]) * t),
+        }
+    }
+
+    fn project(&self, viewport: &Viewport) -> EdgeVertex<VARYINGS> {
+        let [cx, cy, cz, w] = self.position;
+        let inv_w = 1.0 / w;
+        let (x, y, depth) = viewport.to_window(cx * inv_w, cy * inv_w, cz * inv_w);
+        let mut attrs = self.varyings.map(|v| v * inv_w);
+        attrs[DEPTH] = depth;
+        EdgeVertex { x, y, attrs }
+    }
+}
+
+pub struct MeshRenderer {
+    // Skips triangles facing away from the camera
+    pub cull_back_faces: bool,
+    pub polygon_mode: PolygonMode,
+    edge_table: EdgeTable<VARYINGS>,
+    transformed: Vec<ClipVertex>,
+    // None for vertices behind the near plane
+    projected: Vec<Option<EdgeVertex<VARYINGS>>>,
+    clipped: Vec<ClipVertex>,
+    polygon: Vec<EdgeVertex<VARYINGS>>,
+}
+
+impl Default for MeshRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MeshRenderer {
+    pub fn new() -> Self {
+        Self {
+            cull_back_faces: true,
+            polygon_mode: PolygonMode::Fill,
+            edge_table: EdgeTable::new(),
+            transformed: Vec::new(),
+            projected: Vec::new(),
+            clipped: Vec::new(),
+            polygon: Vec::new(),
+        }
+    }
+
+    // Rasterizes the mesh, calling `fragment` with the top left corner, depth and surface of every covered cell
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_mesh<F: FnMut(i32, i32, f32, &Surface)>(
+        &mut self,
+        mesh: &Mesh,
+        model: &Mat4,
+        view_projection: &Mat4,
+        viewport: &Viewport,
+        material: &Material,
+        resolution: i32,
+        mut fragment: F,
+    ) {
+        let model_view_projection = *view_projection * *model;
+        self.transformed.clear();
+        self.transformed.extend(mesh.vertices.iter().map(|vertex| {
+            let world = model.transform_point(vertex.position);
+            let normal = model.transform_vector(vertex.normal).normalized();
+            let tangent = model.transform_vector(vertex.tangent).normalized();
+            ClipVertex {
+                position: model_view_projection.transform(vertex.position),
+                varyings: [
+                    1.0,
+                    world.x,
+                    world.y,
+                    world.z,
+                    normal.x,
+                    normal.y,
+                    normal.z,
+                    vertex.uv.0,
+                    vertex.uv.1,
+                    tangent.x,
+                    tangent.y,
+                    tangent.z,
+                    0.0,
+                ],
+            }
+        }));
+        self.projected.clear();
+        self.projected.extend(self.transformed.iter().map(|vertex| {
+            (vertex.near_distance() >= 0.0).then(|| vertex.project(viewport))
+        }));
+
+        for triangle in &mesh.triangles {
+            self.polygon.clear();
+            if let [Some(a), Some(b), Some(c)] = triangle.map(|i| self.projected[i as usize]) {
+                self.polygon.extend([a, b, c]);
+            } else {
+                clip_near(&triangle.map(|i| self.transformed[i as usize]), &mut self.clipped);
+                self.polygon.extend(self.clipped.iter().map(|vertex| vertex.project(viewport)));
+                if self.polygon.len() < 3 {
+                    continue;
+                }
+            }
+            // Counter-clockwise in normalized device coordinates is clockwise with y down
+            let area: f32 = (0..self.polygon.len())
+                .map(|i| {
+                    let (a, b) = (&self.polygon[i], &self.polygon[(i + 1) % self.polygon.len()]);
+                    a.x * b.y - b.x * a.y
+                })
+                .sum();
+            if self.cull_back_faces && area >= 0.0 {
+                continue;
+            }
+            let handedness = mesh.vertices[triangle[0] as usize].handedness;
+            if self.polygon_mode != PolygonMode::Fill {
+                self.polygon_mode.for_each_cell(&self.polygon, viewport.rect(), resolution, |x, y, attrs| {
+                    fragment(x, y, attrs[DEPTH], &surface(&attrs, material, handedness));
+                });
+                continue;
+            }
+
+            self.edge_table.reset(resolution);
+            self.edge_table.set_clip(Some(viewport.rect()));
+            self.edge_table.add_polygon(&self.polygon);
+            self.edge_table.fill_spans(FillRule::NonZero, |span| {
+                let mut attrs = span.attrs;
+                for x in (span.x_start..span.x_end).step_by(resolution as usize) {
+                    fragment(x, span.y, attrs[DEPTH], &surface(&attrs, material, handedness));
+                    for (value, step) in attrs.iter_mut().zip(span.step) {
+                        *value += step;
+                    }
+                }
+            });
+        }
+    }
+}
+
+// Sutherland–Hodgman clipping of a triangle against the near plane, keeping what is in front.
+// The result is empty or a convex polygon of 3 or 4 corners.
+fn clip_near(triangle: &[ClipVertex; 3], out: &mut Vec<ClipVertex>) {
+    out.clear();
+    for (i, current) in triangle.iter().enumerate() {
+        let next = &triangle[(i + 1) % 3];
+        let (d0, d1) = (current.near_distance(), next.near_distance());
+        if d0 >= 0.0 {
+            out.push(*current);
+        }
+        if (d0 >= 0.0) != (d1 >= 0.0) {
+            out.push(current.lerp(next, d0 / (d0 - d1)));
+        }
+    }
+}
+
+// The surface at a fragment from its interpolated varyings
+fn surface(attrs: &[f32; VARYINGS], material: &Material, handedness: f32) -> Surface {
+    let w = 1.0 / attrs[INV_W];
+    let at = |i: usize| Vec3::new(attrs[i], attrs[i + 1], attrs[i + 2]) * w;
+    let (u, v) = (attrs[UV] * w, attrs[UV + 1] * w);
+    let normal = at(NORMAL).normalized();
+    let tangent = at(TANGENT);
+    let tangent = (tangent - normal * normal.dot(tangent)).normalized();
+    let bitangent = normal.cross(tangent) * handedness;
+    let (metallic, roughness) = material.metallic_roughness_at(u, v);
+    Surface {
+        position: at(POSITION),
+        normal: material.normal_at(u, v, normal, tangent, bitangent),
+        albedo: material.albedo_at(u, v),
+        metallic,
+        roughness,
+        view_depth: w,
+        tangent,
+        bitangent,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    #[test]
+    fn triangles_crossing_the_near_plane_are_clipped() {
+        // Standing on a large floor looking at the horizon, so most of its corners are behind the camera
+        let (width, height) = (40, 30);
+        let viewport = Viewport::new(0, 0, width, height);
+        let camera = Camera::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, -10.0));
+        let view_projection = camera.view_projection(viewport.aspect());
+        let floor = Mesh::plane(200.0, 1.0);
+
+        let mut covered = vec![false; (width * height) as usize];
+        let mut renderer = MeshRenderer::new();
+        let material = Material::new(Color::RGB(255, 255, 255));
+        renderer.draw_mesh(&floor, &Mat4::IDENTITY, &view_projection, &viewport, &material, 1, |x, y, depth, surface| {
+            covered[(y * width as i32 + x) as usize] = true;
+            assert!((0.0..=1.0).contains(&depth), "depth {}", depth);
+            // The varyings still describe the floor point seen through the cell
+            assert!(surface.position.y.abs() < 1e-3, "{:?}", surface.position);
+            let [cx, cy, _, w] = view_projection.transform(surface.position);
+            let (sx, sy, _) = viewport.to_window(cx / w, cy / w, 0.0);
+            let (dx, dy) = (sx - (x as f32 + 0.5), sy - (y as f32 + 0.5));
+            assert!(dx.abs() < 0.6 && dy.abs() < 0.6, "cell {} {} shows {} {}", x, y, sx, sy);
+            assert!((surface.normal - Vec3::UP).length() < 1e-3);
+        });
+
+        // Everything below the horizon, in the middle row, is floor and nothing above it is
+        for y in 0..height as usize {
+            let row = &covered[y * width as usize..(y + 1) * width as usize];
+            if y > height as usize / 2 {
+                assert!(row.iter().all(|&c| c), "row {} has gaps", y);
+            } else if y < height as usize / 2 - 1 {
+                assert!(row.iter().all(|&c| !c), "row {} is covered", y);
+            }
+        }
+    }
+
+    #[test]
+    fn triangles_behind_the_camera_are_dropped() {
+        let viewport = Viewport::new(0, 0, 20, 20);
+        let camera = Camera::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 10.0));
+        let view_projection = camera.view_projection(viewport.aspect());
+        let model = Mat4::translation(Vec3::new(0.0, 0.0, -20.0));
+        let mut drawn = 0;
+        let mut renderer = MeshRenderer::new();
+        renderer.cull_back_faces = false;
+        let material = Material::new(Color::RGB(255, 255, 255));
+        let floor = Mesh::plane(10.0, 1.0);
+        renderer.draw_mesh(&floor, &model, &view_projection, &viewport, &material, 1, |_, _, _, _| drawn += 1);
+        assert_eq!(drawn, 0);
+    }
+}
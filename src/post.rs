@@ -0,0 +1,478 @@
+use sdl2::pixels::Color;
+
+// Full screen passes run over the finished frame.
+//
+// A pass gets the 8 bit colors of the whole frame and rewrites them in place, keeping any
+// scratch buffers it needs between frames. Passes are chained in a PostChain, which runs the
+// enabled ones in order and lets them be switched and reordered at runtime. Filters work on
+// the sRGB values as floats, alpha is left alone.
+
+pub struct PostImage<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'a mut [Color],
+}
+
+pub trait PostPass {
+    fn name(&self) -> &str;
+    fn apply(&mut self, image: &mut PostImage);
+}
+
+type Rgb = [f32; 3];
+
+fn rgb(color: Color) -> Rgb {
+    [color.r as f32, color.g as f32, color.b as f32]
+}
+
+fn to_color(value: Rgb, alpha: u8) -> Color {
+    let byte = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+    Color::RGBA(byte(value[0]), byte(value[1]), byte(value[2]), alpha)
+}
+
+// Rec. 709 luma of sRGB values
+pub fn luma(value: Rgb) -> f32 {
+    0.2126 * value[0] + 0.7152 * value[1] + 0.0722 * value[2]
+}
+
+fn load(image: &PostImage, out: &mut Vec<Rgb>) {
+    out.clear();
+    out.extend(image.pixels.iter().map(|&pixel| rgb(pixel)));
+}
+
+fn store(image: &mut PostImage, values: &[Rgb]) {
+    for (pixel, value) in image.pixels.iter_mut().zip(values) {
+        *pixel = to_color(*value, pixel.a);
+    }
+}
+
+// Clamped neighbour lookup
+fn at(values: &[Rgb], width: usize, height: usize, x: isize, y: isize) -> Rgb {
+    let x = x.clamp(0, width as isize - 1) as usize;
+    let y = y.clamp(0, height as isize - 1) as usize;
+    values[y * width + x]
+}
+
+// Separable gaussian blur of `values` in place, `tmp` is scratch space
+fn blur(values: &mut [Rgb], tmp: &mut Vec<Rgb>, width: usize, height: usize, sigma: f32) {
+    // No spread is no blur, and would divide by zero below
+    if sigma.is_nan() || sigma <= 0.0 {
+        return;
+    }
+    let radius = (sigma * 3.0).ceil().max(1.0) as isize;
+    let mut kernel: Vec<f32> = (-radius..=radius).map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|k| *k /= sum);
+
+    tmp.clear();
+    tmp.resize(values.len(), [0.0; 3]);
+    for (pass, (dx, dy)) in [(1, 0), (0, 1)].into_iter().enumerate() {
+        let (src, dst): (&[Rgb], &mut [Rgb]) = if pass == 0 { (values, tmp) } else { (tmp, values) };
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0.0; 3];
+                for (k, weight) in kernel.iter().enumerate() {
+                    let offset = k as isize - radius;
+                    let sample = at(src, width, height, x as isize + offset * dx, y as isize + offset * dy);
+                    for c in 0..3 {
+                        sum[c] += sample[c] * weight;
+                    }
+                }
+                dst[y * width + x] = sum;
+            }
+        }
+    }
+}
+
+pub struct GaussianBlur {
+    pub sigma: f32,
+    values: Vec<Rgb>,
+    tmp: Vec<Rgb>,
+}
+
+impl GaussianBlur {
+    pub fn new(sigma: f32) -> Self {
+        Self { sigma, values: Vec::new(), tmp: Vec::new() }
+    }
+}
+
+impl PostPass for GaussianBlur {
+    fn name(&self) -> &str {
+        "gaussian blur"
+    }
+
+    fn apply(&mut self, image: &mut PostImage) {
+        load(image, &mut self.values);
+        blur(&mut self.values, &mut self.tmp, image.width, image.height, self.sigma);
+        store(image, &self.values);
+    }
+}
+
+// Blurs the parts brighter than `threshold` and adds them back, so bright areas glow
+pub struct Bloom {
+    pub threshold: f32,
+    pub intensity: f32,
+    pub sigma: f32,
+    bright: Vec<Rgb>,
+    tmp: Vec<Rgb>,
+}
+
+impl Bloom {
+    pub fn new(threshold: f32, intensity: f32, sigma: f32) -> Self {
+        Self { threshold, intensity, sigma, bright: Vec::new(), tmp: Vec::new() }
+    }
+}
+
+impl PostPass for Bloom {
+    fn name(&self) -> &str {
+        "bloom"
+    }
+
+    fn apply(&mut self, image: &mut PostImage) {
+        load(image, &mut self.bright);
+        for value in self.bright.iter_mut() {
+            let l = luma(*value);
+            let keep = if l > self.threshold { (l - self.threshold) / l } else { 0.0 };
+            *value = value.map(|c| c * keep);
+        }
+        blur(&mut self.bright, &mut self.tmp, image.width, image.height, self.sigma);
+        for (pixel, glow) in image.pixels.iter_mut().zip(&self.bright) {
+            let base = rgb(*pixel);
+            *pixel = to_color([0, 1, 2].map(|c| base[c] + glow[c] * self.intensity), pixel.a);
+        }
+    }
+}
+
+// Unsharp masking with the 4 direct neighbours
+pub struct Sharpen {
+    pub amount: f32,
+    values: Vec<Rgb>,
+}
+
+impl Sharpen {
+    pub fn new(amount: f32) -> Self {
+        Self { amount, values: Vec::new() }
+    }
+}
+
+impl PostPass for Sharpen {
+    fn name(&self) -> &str {
+        "sharpen"
+    }
+
+    fn apply(&mut self, image: &mut PostImage) {
+        load(image, &mut self.values);
+        let (width, height) = (image.width, image.height);
+        for y in 0..height {
+            for x in 0..width {
+                let (xi, yi) = (x as isize, y as isize);
+                let center = self.values[y * width + x];
+                let neighbours = [(-1, 0), (1, 0), (0, -1), (0, 1)].map(|(dx, dy)| at(&self.values, width, height, xi + dx, yi + dy));
+                let sharpened = [0, 1, 2].map(|c| {
+                    let average = neighbours.iter().map(|n| n[c]).sum::<f32>() / 4.0;
+                    center[c] + (center[c] - average) * self.amount
+                });
+                let pixel = &mut image.pixels[y * width + x];
+                *pixel = to_color(sharpened, pixel.a);
+            }
+        }
+    }
+}
+
+// A 3D color lookup table with `size` entries per channel, red changing fastest
+#[derive(Clone, Debug)]
+pub struct Lut {
+    size: usize,
+    entries: Vec<Rgb>,
+}
+
+impl Lut {
+    // Table of `grade` sampled on the lattice, colors in [0, 1]
+    pub fn from_fn<F: Fn(Rgb) -> Rgb>(size: usize, grade: F) -> Self {
+        let size = size.max(2);
+        let step = 1.0 / (size - 1) as f32;
+        let mut entries = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    entries.push(grade([r as f32 * step, g as f32 * step, b as f32 * step]));
+                }
+            }
+        }
+        Self { size, entries }
+    }
+
+    // A table in the .cube text format, only LUT_3D_SIZE and the entries are used
+    pub fn parse_cube(text: &str) -> Result<Self, String> {
+        let mut size = None;
+        let mut entries = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(value) = line.strip_prefix("LUT_3D_SIZE") {
+                size = Some(value.trim().parse::<usize>().map_err(|_| format!("Invalid LUT size \"{}\"", line))?);
+            } else if line.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') {
+                let numbers = line.split_whitespace().map(str::parse::<f32>).collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("Invalid LUT entry \"{}\"", line))?;
+                if numbers.len() != 3 {
+                    return Err(format!("Invalid LUT entry \"{}\"", line));
+                }
+                entries.push([numbers[0], numbers[1], numbers[2]]);
+            }
+        }
+        let size = size.ok_or_else(|| "Missing LUT_3D_SIZE".to_string())?;
+        if size < 2 || entries.len() != size * size * size {
+            return Err(format!("Expected {} LUT entries, found {}", size * size * size, entries.len()));
+        }
+        Ok(Self { size, entries })
+    }
+
+    // Trilinear lookup of a color in [0, 1]
+    pub fn lookup(&self, color: Rgb) -> Rgb {
+        let scale = (self.size - 1) as f32;
+        let position = color.map(|c| c.clamp(0.0, 1.0) * scale);
+        let base = position.map(|p| (p.floor() as usize).min(self.size - 2));
+        let t = [0, 1, 2].map(|c| position[c] - base[c] as f32);
+
+        let mut out = [0.0; 3];
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let weight: f32 = (0..3).map(|c| if offset[c] == 1 { t[c] } else { 1.0 - t[c] }).product();
+            let index = |c: usize| base[c] + offset[c];
+            let entry = self.entries[(index(2) * self.size + index(1)) * self.size + index(0)];
+            for c in 0..3 {
+                out[c] += entry[c] * weight;
+            }
+        }
+        out
+    }
+}
+
+pub struct ColorGrade {
+    pub lut: Lut,
+}
+
+impl ColorGrade {
+    pub fn new(lut: Lut) -> Self {
+        Self { lut }
+    }
+}
+
+impl PostPass for ColorGrade {
+    fn name(&self) -> &str {
+        "color grading"
+    }
+
+    fn apply(&mut self, image: &mut PostImage) {
+        for pixel in image.pixels.iter_mut() {
+            let graded = self.lut.lookup(rgb(*pixel).map(|c| c / 255.0));
+            *pixel = to_color(graded.map(|c| c * 255.0), pixel.a);
+        }
+    }
+}
+
+// Darkens towards the corners, starting at `radius` (1 reaches the corners) from the center
+pub struct Vignette {
+    pub strength: f32,
+    pub radius: f32,
+}
+
+impl Vignette {
+    pub fn new(strength: f32, radius: f32) -> Self {
+        Self { strength, radius }
+    }
+}
+
+impl PostPass for Vignette {
+    fn name(&self) -> &str {
+        "vignette"
+    }
+
+    fn apply(&mut self, image: &mut PostImage) {
+        let (cx, cy) = (image.width as f32 / 2.0, image.height as f32 / 2.0);
+        let corner = (cx * cx + cy * cy).sqrt();
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            let (dx, dy) = ((i % image.width) as f32 + 0.5 - cx, (i / image.width) as f32 + 0.5 - cy);
+            let distance = (dx * dx + dy * dy).sqrt() / corner;
+            let t = ((distance - self.radius) / (1.0 - self.radius).max(1e-3)).clamp(0.0, 1.0);
+            let factor = 1.0 - self.strength * t * t * (3.0 - 2.0 * t);
+            *pixel = to_color(rgb(*pixel).map(|c| c * factor), pixel.a);
+        }
+    }
+}
+
+// Replaces the frame by the Sobel gradient magnitude of its luma, white on black
+pub struct SobelEdges {
+    lumas: Vec<f32>,
+}
+
+impl SobelEdges {
+    pub fn new() -> Self {
+        Self { lumas: Vec::new() }
+    }
+}
+
+impl Default for SobelEdges {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PostPass for SobelEdges {
+    fn name(&self) -> &str {
+        "Sobel edges"
+    }
+
+    fn apply(&mut self, image: &mut PostImage) {
+        let (width, height) = (image.width, image.height);
+        self.lumas.clear();
+        self.lumas.extend(image.pixels.iter().map(|&pixel| luma(rgb(pixel))));
+        let l = |x: isize, y: isize| {
+            self.lumas[y.clamp(0, height as isize - 1) as usize * width + x.clamp(0, width as isize - 1) as usize]
+        };
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let gx = l(x + 1, y - 1) + 2.0 * l(x + 1, y) + l(x + 1, y + 1)
+                    - l(x - 1, y - 1) - 2.0 * l(x - 1, y) - l(x - 1, y + 1);
+                let gy = l(x - 1, y + 1) + 2.0 * l(x, y + 1) + l(x + 1, y + 1)
+                    - l(x - 1, y - 1) - 2.0 * l(x, y - 1) - l(x + 1, y - 1);
+                let magnitude = (gx * gx + gy * gy).sqrt() / 4.0;
+                let pixel = &mut image.pixels[y as usize * width + x as usize];
+                *pixel = to_color([magnitude; 3], pixel.a);
+            }
+        }
+    }
+}
+
+// Passes run in order, each can be switched off without losing its place
+#[derive(Default)]
+pub struct PostChain {
+    passes: Vec<(bool, Box<dyn PostPass>)>,
+}
+
+impl PostChain {
+    pub fn new() -> Self {
+        Self { passes: Vec::new() }
+    }
+
+    pub fn push(&mut self, pass: Box<dyn PostPass>, enabled: bool) {
+        self.passes.push((enabled, pass));
+    }
+
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    // Names and states in chain order
+    pub fn passes(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.passes.iter().map(|(enabled, pass)| (pass.name(), *enabled))
+    }
+
+    pub fn is_enabled(&self, index: usize) -> bool {
+        self.passes.get(index).is_some_and(|(enabled, _)| *enabled)
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(pass) = self.passes.get_mut(index) {
+            pass.0 = enabled;
+        }
+    }
+
+    // Moves a pass to another place in the chain
+    pub fn move_pass(&mut self, from: usize, to: usize) {
+        if from < self.passes.len() && to < self.passes.len() {
+            let pass = self.passes.remove(from);
+            self.passes.insert(to, pass);
+        }
+    }
+
+    pub fn apply(&mut self, image: &mut PostImage) {
+        for (enabled, pass) in self.passes.iter_mut() {
+            if *enabled {
+                pass.apply(image);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Maps every red channel through `r * scale + offset`
+    struct Affine {
+        name: &'static str,
+        scale: f32,
+        offset: f32,
+    }
+
+    impl PostPass for Affine {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn apply(&mut self, image: &mut PostImage) {
+            for pixel in image.pixels.iter_mut() {
+                pixel.r = (pixel.r as f32 * self.scale + self.offset) as u8;
+            }
+        }
+    }
+
+    fn run(chain: &mut PostChain, red: u8) -> u8 {
+        let mut pixels = [Color::RGB(red, 0, 0)];
+        chain.apply(&mut PostImage { width: 1, height: 1, pixels: &mut pixels });
+        pixels[0].r
+    }
+
+    #[test]
+    fn chain_runs_the_enabled_passes_in_order() {
+        let mut chain = PostChain::new();
+        chain.push(Box::new(Affine { name: "double", scale: 2.0, offset: 0.0 }), true);
+        chain.push(Box::new(Affine { name: "add", scale: 1.0, offset: 10.0 }), true);
+        assert_eq!(run(&mut chain, 5), 20);
+
+        chain.move_pass(1, 0);
+        assert_eq!(chain.passes().collect::<Vec<_>>(), vec![("add", true), ("double", true)]);
+        assert_eq!(run(&mut chain, 5), 30);
+
+        chain.set_enabled(0, false);
+        assert!(!chain.is_enabled(0) && chain.is_enabled(1));
+        assert_eq!(run(&mut chain, 5), 10);
+
+        // Out of range indices are ignored
+        chain.move_pass(0, 5);
+        chain.set_enabled(7, false);
+        assert_eq!(chain.passes().map(|(name, _)| name).collect::<Vec<_>>(), vec!["add", "double"]);
+    }
+
+    #[test]
+    fn cube_files_are_parsed() {
+        let text = "# identity\nTITLE \"test\"\nLUT_3D_SIZE 2\n\n\
+                    0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1.0 1.0 1.0\n";
+        let lut = Lut::parse_cube(text).unwrap();
+        for color in [[0.0, 0.0, 0.0], [0.25, 0.5, 0.75], [1.0, 1.0, 1.0]] {
+            let looked_up = lut.lookup(color);
+            assert!((0..3).all(|c| (looked_up[c] - color[c]).abs() < 1e-6), "{:?}", looked_up);
+        }
+
+        assert!(Lut::parse_cube("0 0 0\n").is_err());
+        assert!(Lut::parse_cube("LUT_3D_SIZE two\n").is_err());
+        assert!(Lut::parse_cube("LUT_3D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+        assert!(Lut::parse_cube(&text.replace("0 1 1", "0 1")).is_err());
+        assert!(Lut::parse_cube(&text.replace("0 1 1", "0 x 1")).is_err());
+        assert!(Lut::parse_cube("LUT_3D_SIZE 1\n0 0 0\n").is_err());
+    }
+
+    #[test]
+    fn blur_keeps_a_constant_image() {
+        for sigma in [0.0, -1.0, f32::NAN, 0.5, 2.0, 6.0] {
+            let mut pixels = vec![Color::RGBA(90, 140, 200, 77); 7 * 5];
+            GaussianBlur::new(sigma).apply(&mut PostImage { width: 7, height: 5, pixels: &mut pixels });
+            assert!(pixels.iter().all(|&pixel| pixel == Color::RGBA(90, 140, 200, 77)), "sigma {}", sigma);
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use crate::clip::{clip_line, ClipAlgorithm, ClipRect, LineClip};
+use crate::color::{linear_to_srgb, modulate, srgb_to_linear, ColorSpace};
+use crate::framebuffer::Framebuffer;
+use crate::hdr::hdr_color;
+use crate::line::draw_bresenham_line;
+use crate::scanline::{attrs_color, EdgeTable, EdgeVertex, FillRule, Span};
+use crate::state::RenderState;
+use crate::texture::Texture;
+use sdl2::pixels::Color;
+
+// Filling triangles and polygons into the framebuffer.
+//
+// Vertices are already in window coordinates. Color, depth and texture coordinates are
+// interpolated by the scanline edge table, every covered cell becomes a fragment whose
+// color is the vertex color, tinted texel when a texture is bound, and is written through
+// the depth test and blending of the render state. In linear light the color channels are
+// decoded from sRGB at the vertices and encoded again per fragment. Vertices with an
+// intensity other than 1 produce HDR fragments.
+//
+// The polygon mode can rasterize only the edges of the contours instead, as Bresenham lines
+// with every varying interpolated along them, or only their vertices. Edges are clipped to a
+// rectangle first, so edges reaching far off screen are not stepped cell by cell. Those
+// fragments go through the same texturing, depth test and blending as filled ones.
+
+#[derive(Clone, Copy, Debug)]
+pub struct ScreenVertex {
+    pub x: f32,
+    pub y: f32,
+    // Larger is further away
+    pub z: f32,
+    pub color: Color,
+    pub uv: (f32, f32),
+    // Scale of the color in linear light, above 1 for lights on an HDR target
+    pub intensity: f32,
+}
+
+impl ScreenVertex {
+    pub fn new(x: f32, y: f32, z: f32, color: Color) -> Self {
+        Self { x, y, z, color, uv: (0.0, 0.0), intensity: 1.0 }
+    }
+
+    pub fn with_uv(self, u: f32, v: f32) -> Self {
+        Self { uv: (u, v), ..self }
+    }
+
+    pub fn with_intensity(self, intensity: f32) -> Self {
+        Self { intensity, ..self }
+    }
+
+    fn edge_vertex(&self, space: ColorSpace) -> EdgeVertex<VARYINGS> {
+        let c = self.color;
+        let channel = |v: u8| match space {
+            ColorSpace::Srgb => v as f32,
+            ColorSpace::Linear => srgb_to_linear(v) * 255.0,
+        };
+        EdgeVertex {
+            x: self.x,
+            y: self.y,
+            attrs: [channel(c.r), channel(c.g), channel(c.b), c.a as f32, self.z, self.uv.0, self.uv.1, self.intensity],
+        }
+    }
+}
+
+// Interpolated per fragment : RGBA, depth, u, v, intensity
+const VARYINGS: usize = 8;
+const DEPTH: usize = 4;
+const U: usize = 5;
+const V: usize = 6;
+const INTENSITY: usize = 7;
+
+// How triangles and polygons are rasterized, as in OpenGL's glPolygonMode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    // The edges
+    Line,
+    // The vertices, one cell each
+    Point,
+}
+
+impl PolygonMode {
+    // Every cell inside `rect` covered in the mode by the polygon with the given corners, and
+    // the position between the corners in the attributes there, or one attribute set per
+    // vertex for points
+    pub fn for_each_cell<const N: usize, F: FnMut(i32, i32, [f32; N])>(self, corners: &[EdgeVertex<N>], rect: ClipRect, resolution: i32, mut cell: F) {
+        let snap = |v: f32| (v as i32).div_euclid(resolution) * resolution;
+        let clip = LineClip { rect, algorithm: ClipAlgorithm::LiangBarsky };
+        match self {
+            PolygonMode::Fill => {}
+            PolygonMode::Line => {
+                for (i, a) in corners.iter().enumerate() {
+                    let b = &corners[(i + 1) % corners.len()];
+                    let (dx, dy) = (b.x - a.x, b.y - a.y);
+                    let length = (dx * dx + dy * dy).max(1e-6);
+                    let Some(line) = clip_line(&clip, a.x.round() as i32, a.y.round() as i32, b.x.round() as i32, b.y.round() as i32) else {
+                        continue;
+                    };
+                    let white = Color::RGB(255, 255, 255);
+                    // The varyings are taken along the whole edge, which the clipped part lies on
+                    draw_bresenham_line(line.x1, line.y1, line.x2, line.y2, white, white, resolution, |x, y, _| {
+                        // The cell center projected onto the edge
+                        let center = (x as f32 + resolution as f32 / 2.0, y as f32 + resolution as f32 / 2.0);
+                        let t = (((center.0 - a.x) * dx + (center.1 - a.y) * dy) / length).clamp(0.0, 1.0);
+                        let attrs = std::array::from_fn(|k| a.attrs[k] + (b.attrs[k] - a.attrs[k]) * t);
+                        cell(x, y, attrs);
+                    });
+                }
+            }
+            PolygonMode::Point => {
+                for corner in corners {
+                    let (x, y) = (corner.x as i32, corner.y as i32);
+                    if (rect.x_min..=rect.x_max).contains(&x) && (rect.y_min..=rect.y_max).contains(&y) {
+                        cell(snap(corner.x), snap(corner.y), corner.attrs);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Rasterizer {
+    pub polygon_mode: PolygonMode,
+    edge_table: EdgeTable<VARYINGS>,
+}
+
+impl Rasterizer {
+    pub fn new() -> Self {
+        Self { polygon_mode: PolygonMode::Fill, edge_table: EdgeTable::new() }
+    }
+
+    pub fn fill_triangle(
+        &mut self,
+        frame: &mut Framebuffer,
+        vertices: &[ScreenVertex; 3],
+        texture: Option<&Texture>,
+        state: &RenderState,
+        resolution: i32,
+    ) {
+        self.fill_polygon(frame, &[vertices], FillRule::NonZero, texture, state, resolution);
+    }
+
+    // Fills a shape made of one or more contours, holes are contours inside the outer one
+    pub fn fill_polygon<C: AsRef<[ScreenVertex]>>(
+        &mut self,
+        frame: &mut Framebuffer,
+        contours: &[C],
+        rule: FillRule,
+        texture: Option<&Texture>,
+        state: &RenderState,
+        resolution: i32,
+    ) {
+        // Nothing outside the frame or the scissor can be written
+        let frame_rect = ClipRect::new(0, 0, frame.width(), frame.height());
+        let rect = state.scissor.map_or(frame_rect, |scissor| ClipRect {
+            x_min: scissor.x_min.max(frame_rect.x_min),
+            y_min: scissor.y_min.max(frame_rect.y_min),
+            x_max: scissor.x_max.min(frame_rect.x_max),
+            y_max: scissor.y_max.min(frame_rect.y_max),
+        });
+        if self.polygon_mode != PolygonMode::Fill {
+            for contour in contours {
+                let corners: Vec<_> = contour.as_ref().iter().map(|v| v.edge_vertex(state.color_space)).collect();
+                self.polygon_mode.for_each_cell(&corners, rect, resolution, |x, y, attrs| shade_fragment(frame, x, y, &attrs, texture, state, resolution));
+            }
+            return;
+        }
+        self.edge_table.reset(resolution);
+        self.edge_table.set_clip(Some(rect));
+        for contour in contours {
+            let contour = contour.as_ref();
+            for i in 0..contour.len() {
+                let next = (i + 1) % contour.len();
+                self.edge_table.add_edge(
+                    &contour[i].edge_vertex(state.color_space),
+                    &contour[next].edge_vertex(state.color_space),
+                );
+            }
+        }
+        self.edge_table.fill_spans(rule, |span| shade_span(frame, span, texture, state, resolution));
+    }
+}
+
+fn shade_span(frame: &mut Framebuffer, span: &Span<VARYINGS>, texture: Option<&Texture>, state: &RenderState, resolution: i32) {
+    let mut attrs = span.attrs;
+    for x in (span.x_start..span.x_end).step_by(resolution as usize) {
+        shade_fragment(frame, x, span.y, &attrs, texture, state, resolution);
+        for (value, step) in attrs.iter_mut().zip(span.step) {
+            *value += step;
+        }
+    }
+}
+
+fn shade_fragment(frame: &mut Framebuffer, x: i32, y: i32, attrs: &[f32; VARYINGS], texture: Option<&Texture>, state: &RenderState, resolution: i32) {
+    let mut color = match state.color_space {
+        ColorSpace::Srgb => attrs_color(attrs),
+        ColorSpace::Linear => {
+            let channel = |v: f32| linear_to_srgb(v / 255.0);
+            Color::RGBA(channel(attrs[0]), channel(attrs[1]), channel(attrs[2]), attrs[3].clamp(0.0, 255.0) as u8)
+        }
+    };
+    if let Some(texture) = texture {
+        color = modulate(color, texture.sample(attrs[U], attrs[V]));
+    }
+    if attrs[INTENSITY] != 1.0 {
+        frame.write_hdr_fragment(x, y, resolution, hdr_color(color, attrs[INTENSITY]), attrs[DEPTH], state);
+    } else {
+        frame.write_fragment(x, y, resolution, color, attrs[DEPTH], state);
+    }
+}
+
+// Orders items from the furthest to the nearest by the average depth of their triangle,
+// the order translucent triangles have to be blended in
+pub fn sort_back_to_front<T, F: Fn(&T) -> &[ScreenVertex; 3]>(items: &mut [T], triangle: F) {
+    let depth = |item: &T| triangle(item).iter().map(|v| v.z).sum::<f32>();
+    items.sort_by(|a, b| depth(b).total_cmp(&depth(a)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corner(x: f32, y: f32) -> EdgeVertex<1> {
+        EdgeVertex { x, y, attrs: [x] }
+    }
+
+    #[test]
+    fn line_edges_are_clipped_to_the_rectangle() {
+        let rect = ClipRect::new(0, 0, 100, 100);
+        let corners = [corner(10.0, 10.0), corner(1e7, 10.0), corner(10.0, 50.0)];
+        let mut cells = Vec::new();
+        PolygonMode::Line.for_each_cell(&corners, rect, 1, |x, y, attrs| cells.push((x, y, attrs[0])));
+        assert!(!cells.is_empty());
+        // Two edges run off to x = 1e7, only their visible parts are stepped
+        assert!(cells.len() < 400, "{} cells", cells.len());
+        assert!(cells.iter().all(|&(x, y, _)| (0..100).contains(&x) && (0..100).contains(&y)));
+        // The varyings follow the whole edge, the attribute is the x coordinate along the top one
+        for &(x, _, value) in cells.iter().filter(|&&(_, y, _)| y == 10) {
+            assert!((value - (x as f32 + 0.5)).abs() < 1.0, "{} at {}", value, x);
+        }
+    }
+
+    #[test]
+    fn edges_entirely_outside_produce_nothing() {
+        let rect = ClipRect::new(0, 0, 100, 100);
+        let corners = [corner(-500.0, -10.0), corner(1e7, -20.0), corner(300.0, -1e6)];
+        let mut count = 0;
+        PolygonMode::Line.for_each_cell(&corners, rect, 1, |_, _, _| count += 1);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn points_outside_the_rectangle_are_skipped() {
+        let rect = ClipRect::new(0, 0, 100, 100);
+        let corners = [corner(-5.0, 10.0), corner(42.5, 17.0), corner(100.0, 50.0), corner(50.0, 1e9)];
+        let mut cells = Vec::new();
+        PolygonMode::Point.for_each_cell(&corners, rect, 4, |x, y, attrs| cells.push((x, y, attrs[0])));
+        assert_eq!(cells, vec![(40, 16, 42.5)]);
+    }
+
+    #[test]
+    fn fill_mode_leaves_cells_to_the_scanline_filler() {
+        let mut count = 0;
+        PolygonMode::Fill.for_each_cell(&[corner(0.0, 0.0), corner(10.0, 0.0), corner(0.0, 10.0)], ClipRect::new(0, 0, 100, 100), 1, |_, _, _| count += 1);
+        assert_eq!(count, 0);
+    }
+}
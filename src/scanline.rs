@@ -0,0 +1,353 @@
+use crate::clip::ClipRect;
+use sdl2::pixels::Color;
+
+// Scanline polygon filling with an edge table and an active edge list.
+//
+// Every cell row of height `resolution` is sampled at its vertical center. Edges store
+// their x intercept and N interpolated attributes (color channels, depth, ...) for the
+// current row together with the per-row increments, so stepping to the next row is a
+// handful of additions. Both tables keep their capacity between frames, so filling does
+// not allocate once they have grown to the size of the scene.
+//
+// Any number of closed contours can be added before filling, which covers concave and
+// self-intersecting outlines as well as shapes with holes. Which regions count as inside
+// is decided by the fill rule from the crossings to the left of each span.
+//
+// With a clip rectangle, edges start at its first row and end after its last one, and spans
+// are cut to its columns, so geometry reaching far off screen costs no more than the part
+// that is visible. Attributes are evaluated where the clipped rows and spans begin.
+
+// Even-odd fills regions crossed an odd number of times,
+// non-zero fills regions whose signed winding number is not zero
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillRule {
+    EvenOdd,
+    NonZero,
+}
+
+impl FillRule {
+    fn is_inside(self, winding: i32) -> bool {
+        match self {
+            FillRule::EvenOdd => winding % 2 != 0,
+            FillRule::NonZero => winding != 0,
+        }
+    }
+}
+
+// A polygon corner with the attributes interpolated along its edges
+#[derive(Clone, Copy, Debug)]
+pub struct EdgeVertex<const N: usize> {
+    pub x: f32,
+    pub y: f32,
+    pub attrs: [f32; N],
+}
+
+// A horizontal run of cells inside the polygon.
+// `attrs` holds the attribute values at the center of the first cell, `step` the increment per cell.
+#[derive(Clone, Copy, Debug)]
+pub struct Span<const N: usize> {
+    pub y: i32,
+    pub x_start: i32,
+    pub x_end: i32,
+    pub attrs: [f32; N],
+    pub step: [f32; N],
+}
+
+// An edge prepared for stepping, rows are indices of cell rows (y / resolution).
+// `winding` is +1 for edges going down the screen and -1 for edges going up.
+#[derive(Clone, Copy, Debug)]
+struct Edge<const N: usize> {
+    row_start: i32,
+    row_end: i32,
+    winding: i32,
+    x: f32,
+    dx: f32,
+    attrs: [f32; N],
+    dattrs: [f32; N],
+}
+
+pub struct EdgeTable<const N: usize> {
+    resolution: i32,
+    clip: Option<ClipRect>,
+    edges: Vec<Edge<N>>,
+    active: Vec<Edge<N>>,
+}
+
+impl<const N: usize> Default for EdgeTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> EdgeTable<N> {
+    pub fn new() -> Self {
+        Self { resolution: 1, clip: None, edges: Vec::new(), active: Vec::new() }
+    }
+
+    // Removes all edges and sets the cell size used by the following fill
+    pub fn reset(&mut self, resolution: i32) {
+        self.resolution = resolution.max(1);
+        self.edges.clear();
+        self.active.clear();
+    }
+
+    // Restricts the following fills to the cells overlapping the rectangle, kept across resets
+    pub fn set_clip(&mut self, clip: Option<ClipRect>) {
+        self.clip = clip;
+    }
+
+    pub fn add_edge(&mut self, v0: &EdgeVertex<N>, v1: &EdgeVertex<N>) {
+        let (top, bottom, winding) = if v0.y <= v1.y { (v0, v1, 1) } else { (v1, v0, -1) };
+        let res = self.resolution as f32;
+
+        // Rows whose center lies in [top.y, bottom.y), within the clip rectangle
+        let mut row_start = (top.y / res - 0.5).ceil() as i32;
+        let mut row_end = (bottom.y / res - 0.5).ceil() as i32;
+        if let Some(clip) = self.clip {
+            row_start = row_start.max(clip.y_min.div_euclid(self.resolution));
+            row_end = row_end.min(clip.y_max.div_euclid(self.resolution) + 1);
+        }
+        if row_start >= row_end {
+            return;
+        }
+
+        let height = bottom.y - top.y;
+        let first_y = (row_start as f32 + 0.5) * res;
+        let t = (first_y - top.y) / height;
+
+        let mut attrs = [0.0; N];
+        let mut dattrs = [0.0; N];
+        for i in 0..N {
+            let slope = (bottom.attrs[i] - top.attrs[i]) / height;
+            attrs[i] = top.attrs[i] + slope * (first_y - top.y);
+            dattrs[i] = slope * res;
+        }
+
+        self.edges.push(Edge {
+            row_start,
+            row_end,
+            winding,
+            x: top.x + (bottom.x - top.x) * t,
+            dx: (bottom.x - top.x) / height * res,
+            attrs,
+            dattrs,
+        });
+    }
+
+    // Adds the closed outline through all the given vertices, call once per contour
+    pub fn add_polygon(&mut self, vertices: &[EdgeVertex<N>]) {
+        for i in 0..vertices.len() {
+            self.add_edge(&vertices[i], &vertices[(i + 1) % vertices.len()]);
+        }
+    }
+
+    // Walks the rows top to bottom and emits the spans between neighbouring active edges
+    // that lie inside the shape according to the fill rule
+    pub fn fill_spans<F: FnMut(&Span<N>)>(&mut self, rule: FillRule, mut emit: F) {
+        self.edges.sort_unstable_by_key(|edge| edge.row_start);
+        self.active.clear();
+
+        let res = self.resolution as f32;
+        let columns = self.clip.map_or((i32::MIN, i32::MAX), |clip| {
+            (clip.x_min.div_euclid(self.resolution), clip.x_max.div_euclid(self.resolution) + 1)
+        });
+        let mut next = 0;
+        let mut row = match self.edges.first() {
+            Some(edge) => edge.row_start,
+            None => return,
+        };
+
+        loop {
+            while next < self.edges.len() && self.edges[next].row_start <= row {
+                self.active.push(self.edges[next]);
+                next += 1;
+            }
+            self.active.retain(|edge| edge.row_end > row);
+
+            if self.active.is_empty() {
+                match self.edges.get(next) {
+                    Some(edge) => {
+                        row = edge.row_start;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+
+            // Edges only swap order where they cross, so insertion sort is close to linear here
+            for i in 1..self.active.len() {
+                let mut j = i;
+                while j > 0 && self.active[j - 1].x > self.active[j].x {
+                    self.active.swap(j - 1, j);
+                    j -= 1;
+                }
+            }
+
+            let mut winding = 0;
+            for pair in self.active.windows(2) {
+                winding += pair[0].winding;
+                if rule.is_inside(winding) {
+                    if let Some(span) = make_span(&pair[0], &pair[1], row, res, columns) {
+                        emit(&span);
+                    }
+                }
+            }
+
+            for edge in self.active.iter_mut() {
+                edge.x += edge.dx;
+                for i in 0..N {
+                    edge.attrs[i] += edge.dattrs[i];
+                }
+            }
+            row += 1;
+        }
+    }
+}
+
+// Cells whose centers lie in [left.x, right.x) and whose column is in the clipped range,
+// with attributes stepped from the left edge
+fn make_span<const N: usize>(left: &Edge<N>, right: &Edge<N>, row: i32, res: f32, columns: (i32, i32)) -> Option<Span<N>> {
+    let x_start = ((left.x / res - 0.5).ceil() as i32).max(columns.0);
+    let x_end = ((right.x / res - 0.5).ceil() as i32).min(columns.1);
+    if x_start >= x_end {
+        return None;
+    }
+
+    let width = right.x - left.x;
+    let first_x = (x_start as f32 + 0.5) * res;
+    let mut attrs = [0.0; N];
+    let mut step = [0.0; N];
+    for i in 0..N {
+        let slope = (right.attrs[i] - left.attrs[i]) / width;
+        attrs[i] = left.attrs[i] + slope * (first_x - left.x);
+        step[i] = slope * res;
+    }
+
+    Some(Span {
+        y: row * res as i32,
+        x_start: x_start * res as i32,
+        x_end: x_end * res as i32,
+        attrs,
+        step,
+    })
+}
+
+// RGBA channels as span attributes and back
+pub fn color_attrs(color: Color) -> [f32; 4] {
+    [color.r as f32, color.g as f32, color.b as f32, color.a as f32]
+}
+
+pub fn attrs_color(attrs: &[f32]) -> Color {
+    Color::RGBA(
+        attrs[0].clamp(0.0, 255.0) as u8,
+        attrs[1].clamp(0.0, 255.0) as u8,
+        attrs[2].clamp(0.0, 255.0) as u8,
+        attrs[3].clamp(0.0, 255.0) as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(x: f32, y: f32) -> EdgeVertex<2> {
+        EdgeVertex { x, y, attrs: [x, y] }
+    }
+
+    fn spans(table: &mut EdgeTable<2>, polygon: &[EdgeVertex<2>]) -> Vec<Span<2>> {
+        table.add_polygon(polygon);
+        let mut spans = Vec::new();
+        table.fill_spans(FillRule::NonZero, |span| spans.push(*span));
+        spans
+    }
+
+    #[test]
+    fn clipping_keeps_only_the_rows_and_columns_inside() {
+        let mut table = EdgeTable::new();
+        table.set_clip(Some(ClipRect::new(10, 20, 100, 50)));
+        let polygon = [vertex(-1.0e6, -1.0e6), vertex(1.0e6, -1.0e6), vertex(0.0, 1.0e6)];
+        let spans = spans(&mut table, &polygon);
+
+        assert_eq!(spans.len(), 50);
+        for (row, span) in spans.iter().enumerate() {
+            assert_eq!(span.y, 20 + row as i32);
+            assert_eq!((span.x_start, span.x_end), (10, 110));
+            // Attributes still match the plane through the vertices at the first cell center
+            assert!((span.attrs[0] - (span.x_start as f32 + 0.5)).abs() < 0.5, "{:?}", span);
+            assert!((span.attrs[1] - (span.y as f32 + 0.5)).abs() < 0.5, "{:?}", span);
+        }
+    }
+
+    #[test]
+    fn clipping_matches_the_unclipped_cells_inside_the_rectangle() {
+        let polygon = [vertex(-7.3, 3.1), vertex(21.6, -5.2), vertex(13.4, 27.9), vertex(2.2, 11.0)];
+        for resolution in [1, 2, 3] {
+            let rect = ClipRect::new(0, 0, 15, 12);
+            let mut table = EdgeTable::new();
+            table.reset(resolution);
+            let mut expected = Vec::new();
+            for span in spans(&mut table, &polygon) {
+                let inside = span.y >= rect.y_min.div_euclid(resolution) * resolution && span.y <= rect.y_max;
+                let x_start = span.x_start.max(rect.x_min.div_euclid(resolution) * resolution);
+                let x_end = span.x_end.min((rect.x_max.div_euclid(resolution) + 1) * resolution);
+                if inside && x_start < x_end {
+                    let skipped = ((x_start - span.x_start) / resolution) as f32;
+                    let attrs = [span.attrs[0] + span.step[0] * skipped, span.attrs[1] + span.step[1] * skipped];
+                    expected.push((span.y, x_start, x_end, attrs));
+                }
+            }
+
+            table.reset(resolution);
+            table.set_clip(Some(rect));
+            let clipped = spans(&mut table, &polygon);
+            assert_eq!(clipped.len(), expected.len(), "resolution {}", resolution);
+            for (span, &(y, x_start, x_end, attrs)) in clipped.iter().zip(&expected) {
+                assert_eq!((span.y, span.x_start, span.x_end), (y, x_start, x_end));
+                for i in 0..2 {
+                    assert!((span.attrs[i] - attrs[i]).abs() < 1e-3, "{:?} {:?}", span, attrs);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn polygons_outside_the_clip_produce_no_spans() {
+        let mut table = EdgeTable::new();
+        table.set_clip(Some(ClipRect::new(0, 0, 10, 10)));
+        assert!(spans(&mut table, &[vertex(20.0, 0.0), vertex(30.0, 0.0), vertex(25.0, 8.0)]).is_empty());
+        table.reset(1);
+        assert!(spans(&mut table, &[vertex(0.0, -20.0), vertex(8.0, -20.0), vertex(4.0, -5.0)]).is_empty());
+    }
+
+    fn covered_cells(contours: &[Vec<EdgeVertex<2>>], rule: FillRule) -> i32 {
+        let mut table = EdgeTable::new();
+        for contour in contours {
+            table.add_polygon(contour);
+        }
+        let mut cells = 0;
+        table.fill_spans(rule, |span| cells += span.x_end - span.x_start);
+        cells
+    }
+
+    fn square(min: f32, max: f32, clockwise: bool) -> Vec<EdgeVertex<2>> {
+        let mut corners = vec![vertex(min, min), vertex(max, min), vertex(max, max), vertex(min, max)];
+        if !clockwise {
+            corners.reverse();
+        }
+        corners
+    }
+
+    #[test]
+    fn same_winding_inner_square_is_a_hole_only_for_even_odd() {
+        let contours = [square(0.0, 20.0, true), square(5.0, 15.0, true)];
+        assert_eq!(covered_cells(&contours, FillRule::NonZero), 400);
+        assert_eq!(covered_cells(&contours, FillRule::EvenOdd), 300);
+    }
+
+    #[test]
+    fn opposite_winding_inner_square_is_a_hole_for_both_rules() {
+        let contours = [square(0.0, 20.0, true), square(5.0, 15.0, false)];
+        assert_eq!(covered_cells(&contours, FillRule::NonZero), 300);
+        assert_eq!(covered_cells(&contours, FillRule::EvenOdd), 300);
+    }
+}
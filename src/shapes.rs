@@ -0,0 +1,265 @@
+use crate::color::interpolate_color;
+use sdl2::pixels::Color;
+use std::f32::consts::PI;
+
+// Circles, ellipses and arcs with the midpoint algorithms.
+//
+// All shapes are walked on the grid of `resolution` sized cells, like draw_bresenham_line,
+// and every touched cell is handed to `plot` with the position of its top left corner.
+// Outlines blend from the first to the second color going around the shape (clockwise on
+// screen, starting at the +x axis), fills blend from the center color to the rim color.
+// Angles are in radians and measured the same way.
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ellipse {
+    pub cx: i32,
+    pub cy: i32,
+    pub rx: i32,
+    pub ry: i32,
+}
+
+impl Ellipse {
+    pub fn new(cx: i32, cy: i32, rx: i32, ry: i32) -> Self {
+        Self { cx, cy, rx, ry }
+    }
+
+    pub fn circle(cx: i32, cy: i32, radius: i32) -> Self {
+        Self::new(cx, cy, radius, radius)
+    }
+
+    // Center and radii in cell units
+    fn cells(&self, resolution: i32) -> (i32, i32, i32, i32) {
+        (
+            self.cx.div_euclid(resolution),
+            self.cy.div_euclid(resolution),
+            (self.rx / resolution).max(0),
+            (self.ry / resolution).max(0),
+        )
+    }
+}
+
+pub fn draw_ellipse<F: FnMut(i32, i32, Color)>(ellipse: &Ellipse, resolution: i32, colors: (Color, Color), plot: F) {
+    draw_arc(ellipse, 0.0, 2.0 * PI, resolution, colors, plot);
+}
+
+// Outline between the start and end angle, colors blend along the swept angle
+pub fn draw_arc<F: FnMut(i32, i32, Color)>(
+    ellipse: &Ellipse,
+    start: f32,
+    end: f32,
+    resolution: i32,
+    colors: (Color, Color),
+    mut plot: F,
+) {
+    let (ccx, ccy, rx, ry) = ellipse.cells(resolution);
+    let sweep = (end - start).clamp(0.0, 2.0 * PI);
+    let mut plot_offset = |dx: i32, dy: i32| {
+        let angle = (dy as f32).atan2(dx as f32);
+        let along = (angle - start).rem_euclid(2.0 * PI);
+        if along <= sweep {
+            let t = if sweep > 0.0 { along / sweep } else { 0.0 };
+            plot((ccx + dx) * resolution, (ccy + dy) * resolution, interpolate_color(colors.0, colors.1, t));
+        }
+    };
+
+    if rx == ry {
+        midpoint_circle(rx, |x, y| mirror(x, y, &mut plot_offset));
+    } else {
+        midpoint_ellipse(rx, ry, |x, y| mirror(x, y, &mut plot_offset));
+    }
+}
+
+pub fn fill_ellipse<F: FnMut(i32, i32, Color)>(ellipse: &Ellipse, resolution: i32, colors: (Color, Color), plot: F) {
+    fill_pie(ellipse, 0.0, 2.0 * PI, resolution, colors, plot);
+}
+
+// Filled slice between the start and end angle. Each cell row is one span whose half width
+// comes from the midpoint walk of the outline.
+pub fn fill_pie<F: FnMut(i32, i32, Color)>(
+    ellipse: &Ellipse,
+    start: f32,
+    end: f32,
+    resolution: i32,
+    colors: (Color, Color),
+    mut plot: F,
+) {
+    let (ccx, ccy, rx, ry) = ellipse.cells(resolution);
+    let sweep = (end - start).clamp(0.0, 2.0 * PI);
+    let full = sweep >= 2.0 * PI;
+
+    let mut half_widths = vec![0; ry as usize + 1];
+    let mut record = |x: i32, y: i32| {
+        let width = &mut half_widths[y as usize];
+        *width = (*width).max(x);
+    };
+    if rx == ry {
+        midpoint_circle(rx, |x, y| {
+            record(x, y);
+            record(y, x);
+        });
+    } else {
+        midpoint_ellipse(rx, ry, &mut record);
+    }
+
+    let (rx_f, ry_f) = (rx.max(1) as f32, ry.max(1) as f32);
+    for dy in -ry..=ry {
+        let half = half_widths[dy.unsigned_abs() as usize];
+        for dx in -half..=half {
+            if !full {
+                let angle = (dy as f32).atan2(dx as f32);
+                if (dx, dy) != (0, 0) && (angle - start).rem_euclid(2.0 * PI) > sweep {
+                    continue;
+                }
+            }
+            let t = ((dx as f32 / rx_f).powi(2) + (dy as f32 / ry_f).powi(2)).sqrt().min(1.0);
+            plot((ccx + dx) * resolution, (ccy + dy) * resolution, interpolate_color(colors.0, colors.1, t));
+        }
+    }
+}
+
+// The four symmetric points of a first quadrant offset, without repeating points on the axes
+fn mirror<F: FnMut(i32, i32)>(x: i32, y: i32, plot: &mut F) {
+    plot(x, y);
+    if x != 0 {
+        plot(-x, y);
+    }
+    if y != 0 {
+        plot(x, -y);
+        if x != 0 {
+            plot(-x, -y);
+        }
+    }
+}
+
+// Midpoint circle algorithm. Walks the octant from the top of the circle to the diagonal and
+// reports both mirrored first quadrant offsets, skipping the repeat on the diagonal.
+fn midpoint_circle<F: FnMut(i32, i32)>(radius: i32, mut plot: F) {
+    let mut x = 0;
+    let mut y = radius;
+    let mut d = 1 - radius;
+
+    while x <= y {
+        plot(x, y);
+        if x != y {
+            plot(y, x);
+        }
+        if d < 0 {
+            d += 2 * x + 3;
+        } else {
+            d += 2 * (x - y) + 5;
+            y -= 1;
+        }
+        x += 1;
+    }
+}
+
+// Midpoint ellipse algorithm over the first quadrant. Region 1 steps x while the slope is
+// shallower than -1, region 2 steps y for the steep part down to the x axis.
+fn midpoint_ellipse<F: FnMut(i32, i32)>(rx: i32, ry: i32, mut plot: F) {
+    if rx == 0 || ry == 0 {
+        for x in 0..=rx {
+            plot(x, 0);
+        }
+        for y in 1..=ry {
+            plot(0, y);
+        }
+        return;
+    }
+
+    let (a2, b2) = (rx as f64 * rx as f64, ry as f64 * ry as f64);
+    let mut x = 0;
+    let mut y = ry;
+    let mut dx = 0.0;
+    let mut dy = 2.0 * a2 * y as f64;
+
+    let mut d1 = b2 - a2 * ry as f64 + 0.25 * a2;
+    while dx < dy {
+        plot(x, y);
+        x += 1;
+        dx += 2.0 * b2;
+        if d1 < 0.0 {
+            d1 += dx + b2;
+        } else {
+            y -= 1;
+            dy -= 2.0 * a2;
+            d1 += dx - dy + b2;
+        }
+    }
+
+    let mut d2 = b2 * (x as f64 + 0.5).powi(2) + a2 * (y as f64 - 1.0).powi(2) - a2 * b2;
+    while y >= 0 {
+        plot(x, y);
+        y -= 1;
+        dy -= 2.0 * a2;
+        if d2 > 0.0 {
+            d2 += a2 - dy;
+        } else {
+            x += 1;
+            dx += 2.0 * b2;
+            d2 += dx - dy + a2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const WHITE: Color = Color { r: 255, g: 255, b: 255, a: 255 };
+
+    // Offsets from the center of every plotted cell, in plotting order
+    fn outline(ellipse: &Ellipse, start: f32, end: f32) -> Vec<(i32, i32)> {
+        let mut cells = Vec::new();
+        draw_arc(ellipse, start, end, 1, (WHITE, WHITE), |x, y, _| cells.push((x - ellipse.cx, y - ellipse.cy)));
+        cells
+    }
+
+    #[test]
+    fn midpoint_circle_is_eightfold_symmetric() {
+        for radius in [1, 2, 5, 10, 23] {
+            let cells = outline(&Ellipse::circle(40, 40, radius), 0.0, 2.0 * PI);
+            let set: HashSet<_> = cells.iter().copied().collect();
+            assert_eq!(set.len(), cells.len(), "radius {} plots a cell twice", radius);
+            for &(x, y) in &cells {
+                for mirrored in [(-x, y), (x, -y), (y, x), (-y, -x)] {
+                    assert!(set.contains(&mirrored), "radius {} misses {:?}", radius, mirrored);
+                }
+                let distance = ((x * x + y * y) as f32).sqrt();
+                assert!((distance - radius as f32).abs() < 1.0, "radius {} plots {:?}", radius, (x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn zero_radius_circle_is_a_single_cell() {
+        let circle = Ellipse::circle(7, 9, 0);
+        assert_eq!(outline(&circle, 0.0, 2.0 * PI), vec![(0, 0)]);
+        let mut filled = Vec::new();
+        fill_ellipse(&circle, 1, (WHITE, WHITE), |x, y, _| filled.push((x, y)));
+        assert_eq!(filled, vec![(7, 9)]);
+    }
+
+    #[test]
+    fn arcs_wrapping_past_a_full_turn_match_the_same_range_below_it() {
+        let circle = Ellipse::circle(0, 0, 12);
+        // The right half, from straight up on screen round to straight down
+        let wrapped: HashSet<_> = outline(&circle, 1.5 * PI, 2.5 * PI).into_iter().collect();
+        let unwrapped: HashSet<_> = outline(&circle, -0.5 * PI, 0.5 * PI).into_iter().collect();
+        let full = outline(&circle, 0.0, 2.0 * PI);
+
+        for &(x, y) in &full {
+            if x > 0 {
+                assert!(wrapped.contains(&(x, y)) && unwrapped.contains(&(x, y)), "missing {:?}", (x, y));
+            }
+        }
+        for cells in [&wrapped, &unwrapped] {
+            assert!(cells.iter().all(|&(x, _)| x >= 0), "{:?}", cells);
+        }
+
+        let mut pie = Vec::new();
+        fill_pie(&circle, 1.5 * PI, 2.5 * PI, 1, (WHITE, WHITE), |x, y, _| pie.push((x, y)));
+        assert!(pie.contains(&(12, 0)) && pie.contains(&(0, 0)));
+        assert!(pie.iter().all(|&(x, _)| x >= 0), "{:?}", pie);
+    }
+}
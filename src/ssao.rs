@@ -0,0 +1,241 @@
+use crate::deferred::GBuffer;
+use crate::math::{Mat4, Vec3};
+use crate::viewport::Viewport;
+use std::f32::consts::PI;
+
+// Screen space ambient occlusion.
+//
+// For every covered pixel of the G-buffer, points of a hemisphere kernel around the surface
+// normal are projected back onto the screen and tested against the depth buffer. Samples
+// behind what the depth buffer holds there are occluded, weighted down when the occluder is
+// much further from the pixel than the radius, so silhouettes in front of distant geometry do
+// not cast dark halos. The kernel is rotated around the normal in a 4x4 pixel pattern, which
+// trades banding for noise that the blur then averages away. Like the rest of the rasterizer
+// it works on cells, with one result per cell. The result is the fraction of
+// ambient light reaching each pixel, which the lighting pass multiplies into the ambient term.
+
+// Rotations of the kernel over a 4x4 tile, in steps of a sixteenth of a turn, ordered so
+// neighbouring pixels differ a lot
+const ROTATIONS: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+pub struct Ssao {
+    // World space radius of the sampled hemisphere
+    pub radius: f32,
+    // Offset along the normal against self occlusion from depth precision
+    pub bias: f32,
+    // Exponent on the result, above 1 darkens the occlusion
+    pub intensity: f32,
+    // Half width of the box blur in cells, 0 leaves the noise
+    pub blur_radius: i32,
+    kernel: Vec<Vec3>,
+    width: u32,
+    height: u32,
+    visibility: Vec<f32>,
+    scratch: Vec<f32>,
+}
+
+impl Ssao {
+    pub fn new(width: u32, height: u32, samples: usize, radius: f32) -> Self {
+        let size = (width * height) as usize;
+        let mut ssao = Self {
+            radius,
+            bias: 0.02,
+            intensity: 1.5,
+            blur_radius: 2,
+            kernel: Vec::new(),
+            width,
+            height,
+            visibility: vec![1.0; size],
+            scratch: vec![1.0; size],
+        };
+        ssao.set_samples(samples);
+        ssao
+    }
+
+    pub fn samples(&self) -> usize {
+        self.kernel.len()
+    }
+
+    // Spreads the samples over the unit hemisphere around +z on a spiral, with more of them
+    // close to the center where occluders matter most
+    pub fn set_samples(&mut self, samples: usize) {
+        let samples = samples.max(1);
+        let golden_angle = PI * (3.0 - 5.0f32.sqrt());
+        self.kernel = (0..samples)
+            .map(|i| {
+                let t = (i as f32 + 0.5) / samples as f32;
+                let z = 1.0 - t;
+                let ring = (1.0 - z * z).sqrt();
+                let angle = i as f32 * golden_angle;
+                let scale = 0.1 + 0.9 * t * t;
+                Vec3::new(angle.cos() * ring, angle.sin() * ring, z) * scale
+            })
+            .collect();
+    }
+
+    // Fraction of the ambient light reaching the pixel, 1 where nothing was computed
+    pub fn visibility(&self, x: i32, y: i32) -> f32 {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            1.0
+        } else {
+            self.visibility[(y as u32 * self.width + x as u32) as usize]
+        }
+    }
+
+    // Computes the occlusion of the G-buffer as seen through the view projection and viewport it
+    // was rasterized with, once per cell of `resolution` pixels
+    pub fn compute(&mut self, gbuffer: &GBuffer, view_projection: &Mat4, viewport: &Viewport, resolution: i32) {
+        let resolution = resolution.max(1);
+        let (width, height) = (self.width.min(gbuffer.width()) as i32, self.height.min(gbuffer.height()) as i32);
+        self.visibility.fill(1.0);
+        for y in (0..height).step_by(resolution as usize) {
+            for x in (0..width).step_by(resolution as usize) {
+                let Some(surface) = gbuffer.surface(x, y) else {
+                    continue;
+                };
+                let (cell_x, cell_y) = ((x / resolution) & 3, (y / resolution) & 3);
+                let rotation = ROTATIONS[cell_y as usize][cell_x as usize] as f32 / 16.0 * 2.0 * PI;
+                let occlusion = self.occlusion(gbuffer, view_projection, viewport, surface.position, surface.normal, rotation);
+                let visibility = (1.0 - occlusion).powf(self.intensity);
+                for row in y..(y + resolution).min(height) {
+                    let start = (row as u32 * self.width) as usize;
+                    self.visibility[start + x as usize..start + (x + resolution).min(width) as usize].fill(visibility);
+                }
+            }
+        }
+        self.blur(gbuffer, resolution);
+    }
+
+    fn occlusion(&self, gbuffer: &GBuffer, view_projection: &Mat4, viewport: &Viewport, position: Vec3, normal: Vec3, rotation: f32) -> f32 {
+        // Tangent frame around the normal, turned by the rotation of the pixel
+        let helper = if normal.y.abs() < 0.9 { Vec3::UP } else { Vec3::new(1.0, 0.0, 0.0) };
+        let tangent = helper.cross(normal).normalized();
+        let bitangent = normal.cross(tangent);
+        let (sin, cos) = rotation.sin_cos();
+        let (tangent, bitangent) = (tangent * cos + bitangent * sin, bitangent * cos - tangent * sin);
+
+        let origin = position + normal * self.bias;
+        let mut occlusion = 0.0;
+        for k in &self.kernel {
+            let sample = origin + (tangent * k.x + bitangent * k.y + normal * k.z) * self.radius;
+            let [cx, cy, cz, w] = view_projection.transform(sample);
+            if w <= 1e-4 {
+                continue;
+            }
+            let (sx, sy, sample_depth) = viewport.to_window(cx / w, cy / w, cz / w);
+            let (sx, sy) = (sx.floor() as i32, sy.floor() as i32);
+            let (Some(depth), Some(occluder)) = (gbuffer.depth(sx, sy), gbuffer.surface(sx, sy)) else {
+                continue;
+            };
+            // Only what rises above the surface can occlude it. A neighbour in the same plane may
+            // still be in front of the sample, from the depth being stored at pixel centers.
+            if depth < sample_depth && (occluder.position - position).dot(normal) > self.bias {
+                // Smoothly ignore occluders far outside the radius
+                let range = (self.radius / (position - occluder.position).length()).min(1.0);
+                occlusion += range * range * (3.0 - 2.0 * range);
+            }
+        }
+        occlusion / self.kernel.len() as f32
+    }
+
+    // Separable box blur over the covered pixels, so the background does not bleed in
+    fn blur(&mut self, gbuffer: &GBuffer, resolution: i32) {
+        if self.blur_radius > 0 {
+            let (radius, width) = (self.blur_radius, self.width);
+            blur_pass(gbuffer, &self.visibility, &mut self.scratch, width, radius, (resolution, 0));
+            blur_pass(gbuffer, &self.scratch, &mut self.visibility, width, radius, (0, resolution));
+        }
+    }
+}
+
+// One direction of the box blur, stepping whole cells
+fn blur_pass(gbuffer: &GBuffer, source: &[f32], target: &mut [f32], width: u32, radius: i32, (dx, dy): (i32, i32)) {
+    let width = width as usize;
+    for (i, value) in target.iter_mut().enumerate() {
+        let (x, y) = ((i % width) as i32, (i / width) as i32);
+        if gbuffer.surface(x, y).is_none() {
+            *value = source[i];
+            continue;
+        }
+        let (mut sum, mut count) = (0.0, 0);
+        for d in -radius..=radius {
+            let (nx, ny) = (x + d * dx, y + d * dy);
+            if nx >= 0 && (nx as usize) < width && gbuffer.surface(nx, ny).is_some() {
+                sum += source[ny as usize * width + nx as usize];
+                count += 1;
+            }
+        }
+        *value = sum / count as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::light::Surface;
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+
+    // The visibility of every pixel covered by the planes, given as a point and a normal, and
+    // whether the pixel is within `near` of `point`
+    fn occlusion_of(planes: &[(Vec3, Vec3)], point: Vec3, near: f32) -> Vec<(f32, bool)> {
+        let viewport = Viewport::new(0, 0, WIDTH, HEIGHT);
+        let camera = Camera::new(Vec3::new(0.0, 3.0, 6.0), Vec3::ZERO);
+        let view_projection = camera.view_projection(viewport.aspect());
+
+        // Every pixel center's view ray intersected with the nearest plane
+        let mut gbuffer = GBuffer::new(WIDTH, HEIGHT);
+        for y in 0..HEIGHT as i32 {
+            for x in 0..WIDTH as i32 {
+                let ndc_x = (x as f32 + 0.5) / WIDTH as f32 * 2.0 - 1.0;
+                let ndc_y = 1.0 - (y as f32 + 0.5) / HEIGHT as f32 * 2.0;
+                let ray = camera.ray(ndc_x, ndc_y, viewport.aspect());
+                for &(on_plane, normal) in planes {
+                    let t = (on_plane - camera.position).dot(normal) / ray.dot(normal);
+                    if t > 0.0 && t.is_finite() {
+                        let position = camera.position + ray * t;
+                        let [_, _, z, w] = view_projection.transform(position);
+                        let depth = viewport.to_window(0.0, 0.0, z / w).2;
+                        gbuffer.write(x, y, 1, depth, &Surface { position, normal, ..Surface::default() });
+                    }
+                }
+            }
+        }
+
+        let mut ssao = Ssao::new(WIDTH, HEIGHT, 16, 0.5);
+        ssao.compute(&gbuffer, &view_projection, &viewport, 1);
+        (0..HEIGHT as i32)
+            .flat_map(|y| (0..WIDTH as i32).map(move |x| (x, y)))
+            .filter_map(|(x, y)| {
+                let surface = gbuffer.surface(x, y)?;
+                Some((ssao.visibility(x, y), (surface.position - point).length() < near))
+            })
+            .collect()
+    }
+
+    fn mean(values: impl Iterator<Item = f32>) -> f32 {
+        let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+        sum / count as f32
+    }
+
+    #[test]
+    fn a_flat_plane_is_not_occluded() {
+        let covered = occlusion_of(&[(Vec3::ZERO, Vec3::UP)], Vec3::ZERO, 0.0);
+        assert!(covered.len() > 1000);
+        let darkest = covered.iter().map(|&(v, _)| v).fold(1.0, f32::min);
+        let average = mean(covered.iter().map(|&(v, _)| v));
+        assert!(average > 0.99 && darkest > 0.9, "mean {} darkest {}", average, darkest);
+    }
+
+    #[test]
+    fn a_crease_is_occluded() {
+        // The floor meeting a wall facing the camera
+        let crease = Vec3::new(0.0, 0.0, -1.0);
+        let covered = occlusion_of(&[(Vec3::ZERO, Vec3::UP), (crease, Vec3::new(0.0, 0.0, 1.0))], crease, 0.2);
+        let near = mean(covered.iter().filter(|&&(_, near)| near).map(|&(v, _)| v));
+        let away = mean(covered.iter().filter(|&&(_, near)| !near).map(|&(v, _)| v));
+        assert!(near < 0.93 && away > near + 0.05, "near {} away {}", near, away);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::blend::BlendState;
+use crate::clip::ClipRect;
+use crate::color::ColorSpace;
+use crate::stencil::StencilState;
+
+// Fixed function state applied to every fragment written to the framebuffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderState {
+    pub blend: BlendState,
+    // Fragments behind the stored depth (larger z) are dropped
+    pub depth_test: bool,
+    pub depth_write: bool,
+    // Translucent fragments go to the framebuffer's A-buffer and are blended at resolve time
+    pub a_buffer: bool,
+    // Off for passes that only mark the stencil or depth buffer
+    pub color_write: bool,
+    pub stencil: Option<StencilState>,
+    // Fragments outside the rectangle are dropped before any test
+    pub scissor: Option<ClipRect>,
+    // Where vertex colors are interpolated and fragments blended
+    pub color_space: ColorSpace,
+}
+
+impl RenderState {
+    // Solid geometry, depth tested and written
+    pub const OPAQUE: RenderState = RenderState {
+        blend: BlendState::OPAQUE,
+        depth_test: true,
+        depth_write: true,
+        a_buffer: false,
+        color_write: true,
+        stencil: None,
+        scissor: None,
+        color_space: ColorSpace::Srgb,
+    };
+    // Translucent geometry is hidden by opaque geometry in front of it but hides nothing itself
+    pub const TRANSPARENT: RenderState = RenderState {
+        blend: BlendState::ALPHA,
+        depth_test: true,
+        depth_write: false,
+        ..RenderState::OPAQUE
+    };
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        Self::OPAQUE
+    }
+}
@@ -0,0 +1,195 @@
+// Stencil testing with an 8 bit value per pixel, following the usual GPU model.
+//
+// A fragment passes when `func(reference & read_mask, stored & read_mask)` holds. Depending
+// on whether it failed the stencil test, failed the depth test or passed both, one of three
+// operations updates the stored value, and only the bits in `write_mask` are changed.
+// Marking pixels in one pass and testing them in the next gives outlines, portals and mirrors.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareFunc {
+    Never,
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+    Always,
+}
+
+impl CompareFunc {
+    // Compares the incoming value with the stored one
+    pub fn compare<T: PartialOrd>(self, value: T, stored: T) -> bool {
+        match self {
+            CompareFunc::Never => false,
+            CompareFunc::Less => value < stored,
+            CompareFunc::LessEqual => value <= stored,
+            CompareFunc::Equal => value == stored,
+            CompareFunc::NotEqual => value != stored,
+            CompareFunc::GreaterEqual => value >= stored,
+            CompareFunc::Greater => value > stored,
+            CompareFunc::Always => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    // Saturating at 255 and 0
+    Increment,
+    Decrement,
+    // Wrapping around
+    IncrementWrap,
+    DecrementWrap,
+    Invert,
+}
+
+impl StencilOp {
+    fn apply(self, stored: u8, reference: u8) -> u8 {
+        match self {
+            StencilOp::Keep => stored,
+            StencilOp::Zero => 0,
+            StencilOp::Replace => reference,
+            StencilOp::Increment => stored.saturating_add(1),
+            StencilOp::Decrement => stored.saturating_sub(1),
+            StencilOp::IncrementWrap => stored.wrapping_add(1),
+            StencilOp::DecrementWrap => stored.wrapping_sub(1),
+            StencilOp::Invert => !stored,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StencilState {
+    pub func: CompareFunc,
+    pub reference: u8,
+    pub read_mask: u8,
+    pub write_mask: u8,
+    pub stencil_fail: StencilOp,
+    pub depth_fail: StencilOp,
+    pub pass: StencilOp,
+}
+
+impl StencilState {
+    // Always passes and writes `reference` wherever the fragment is drawn
+    pub const fn mark(reference: u8) -> Self {
+        Self {
+            func: CompareFunc::Always,
+            reference,
+            read_mask: 0xff,
+            write_mask: 0xff,
+            stencil_fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass: StencilOp::Replace,
+        }
+    }
+
+    // Passes where the stored value compares with `reference` by `func`, leaves the buffer alone
+    pub const fn test(func: CompareFunc, reference: u8) -> Self {
+        Self {
+            func,
+            reference,
+            read_mask: 0xff,
+            write_mask: 0xff,
+            stencil_fail: StencilOp::Keep,
+            depth_fail: StencilOp::Keep,
+            pass: StencilOp::Keep,
+        }
+    }
+
+    pub fn passes(&self, stored: u8) -> bool {
+        self.func.compare(self.reference & self.read_mask, stored & self.read_mask)
+    }
+
+    // The stored value after `op`, with the bits outside the write mask kept
+    pub fn update(&self, stored: u8, op: StencilOp) -> u8 {
+        (stored & !self.write_mask) | (op.apply(stored, self.reference) & self.write_mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::Framebuffer;
+    use crate::state::RenderState;
+    use sdl2::pixels::Color;
+
+    #[test]
+    fn every_compare_function() {
+        let expected = [
+            (CompareFunc::Never, [false, false, false]),
+            (CompareFunc::Less, [true, false, false]),
+            (CompareFunc::LessEqual, [true, true, false]),
+            (CompareFunc::Equal, [false, true, false]),
+            (CompareFunc::NotEqual, [true, false, true]),
+            (CompareFunc::GreaterEqual, [false, true, true]),
+            (CompareFunc::Greater, [false, false, true]),
+            (CompareFunc::Always, [true, true, true]),
+        ];
+        // The reference 5 against stored values above, equal to and below it
+        for (func, results) in expected {
+            let state = StencilState::test(func, 5);
+            assert_eq!([6, 5, 4].map(|stored| state.passes(stored)), results, "{:?}", func);
+        }
+    }
+
+    #[test]
+    fn every_operation() {
+        let state = StencilState::mark(7);
+        let expected = [
+            (StencilOp::Keep, [0, 100, 255]),
+            (StencilOp::Zero, [0, 0, 0]),
+            (StencilOp::Replace, [7, 7, 7]),
+            (StencilOp::Increment, [1, 101, 255]),
+            (StencilOp::Decrement, [0, 99, 254]),
+            (StencilOp::IncrementWrap, [1, 101, 0]),
+            (StencilOp::DecrementWrap, [255, 99, 254]),
+            (StencilOp::Invert, [255, 155, 0]),
+        ];
+        for (op, results) in expected {
+            assert_eq!([0, 100, 255].map(|stored| state.update(stored, op)), results, "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn masks_limit_the_bits_compared_and_written() {
+        let state = StencilState { read_mask: 0x0f, write_mask: 0xf0, ..StencilState::test(CompareFunc::Equal, 0x35) };
+        assert!(state.passes(0xa5));
+        assert!(!state.passes(0x34));
+        assert_eq!(state.update(0x1f, StencilOp::Replace), 0x3f);
+        assert_eq!(state.update(0xff, StencilOp::IncrementWrap), 0x0f);
+    }
+
+    #[test]
+    fn the_outcome_picks_the_operation() {
+        let stencil = StencilState {
+            stencil_fail: StencilOp::Replace,
+            depth_fail: StencilOp::Increment,
+            pass: StencilOp::Invert,
+            ..StencilState::test(CompareFunc::Equal, 9)
+        };
+        let state = RenderState { stencil: Some(stencil), ..RenderState::OPAQUE };
+        let white = Color::RGB(255, 255, 255);
+        let mut frame = Framebuffer::new(3, 1);
+        frame.clear_stencil(9);
+        frame.write_fragment(1, 0, 1, white, 0.5, &RenderState::OPAQUE);
+
+        // Failing the stencil test, failing the depth test and passing both
+        let mark = RenderState {
+            stencil: Some(StencilState::mark(3)),
+            color_write: false,
+            depth_write: false,
+            ..RenderState::OPAQUE
+        };
+        frame.write_fragment(0, 0, 1, white, 1.0, &mark);
+        frame.write_fragment(0, 0, 1, white, 0.0, &state);
+        frame.write_fragment(1, 0, 1, white, 0.9, &state);
+        frame.write_fragment(2, 0, 1, white, 0.0, &state);
+        assert_eq!([0, 1, 2].map(|x| frame.stencil(x, 0).unwrap()), [9, 10, !9]);
+        assert_eq!(frame.pixel(0, 0), Some(Color::RGB(0, 0, 0)));
+        assert_eq!(frame.pixel(2, 0), Some(white));
+    }
+}
@@ -0,0 +1,266 @@
+use crate::color::{linear_to_srgb, srgb_to_linear};
+use crate::framebuffer::Framebuffer;
+use sdl2::pixels::Color;
+use std::f32::consts::PI;
+
+// Supersampling : the scene is rendered into a framebuffer `factor` times larger in both
+// directions and filtered down to the target, which gives an anti-aliased reference image.
+//
+// The reduction is a separable resampling filter evaluated in linear light, first along rows
+// and then along columns. Filter widths are measured in target pixels, so a wider filter
+// blends in samples from the neighbouring pixels too. The filters with negative lobes
+// (Mitchell, Lanczos) sharpen and can ring slightly at hard edges.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DownsampleFilter {
+    // Average of the samples inside the pixel
+    Box,
+    // Linear falloff over one pixel around the center
+    Tent,
+    // Mitchell–Netravali cubic with B = C = 1/3
+    Mitchell,
+    // Windowed sinc with three lobes
+    Lanczos,
+}
+
+impl DownsampleFilter {
+    pub fn radius(self) -> f32 {
+        match self {
+            DownsampleFilter::Box => 0.5,
+            DownsampleFilter::Tent => 1.0,
+            DownsampleFilter::Mitchell => 2.0,
+            DownsampleFilter::Lanczos => 3.0,
+        }
+    }
+
+    // Weight of a sample `x` target pixels from the pixel center
+    pub fn weight(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            DownsampleFilter::Box => if x <= 0.5 { 1.0 } else { 0.0 },
+            DownsampleFilter::Tent => (1.0 - x).max(0.0),
+            DownsampleFilter::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+                } else if x < 2.0 {
+                    ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+                } else {
+                    0.0
+                }
+            }
+            DownsampleFilter::Lanczos => {
+                if x < 1e-6 {
+                    1.0
+                } else if x < 3.0 {
+                    let px = PI * x;
+                    3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+// Normalized (source index, weight) taps of every target pixel along one axis
+type Taps = Vec<Vec<(usize, f32)>>;
+
+// Target width and height, factor and filter, all that the taps depend on
+type TapsKey = (usize, usize, u32, DownsampleFilter);
+
+fn taps(target_size: usize, source_size: usize, factor: u32, filter: DownsampleFilter) -> Taps {
+    let factor = factor as f32;
+    let reach = (filter.radius() * factor).ceil() as isize;
+    (0..target_size)
+        .map(|t| {
+            let center = (t as f32 + 0.5) * factor;
+            let mut taps: Vec<(usize, f32)> = (center as isize - reach..=center as isize + reach)
+                .filter(|&s| s >= 0 && (s as usize) < source_size)
+                .map(|s| (s as usize, filter.weight((s as f32 + 0.5 - center) / factor)))
+                .filter(|&(_, weight)| weight != 0.0)
+                .collect();
+            let sum: f32 = taps.iter().map(|&(_, weight)| weight).sum();
+            if sum != 0.0 {
+                taps.iter_mut().for_each(|tap| tap.1 /= sum);
+            }
+            taps
+        })
+        .collect()
+}
+
+pub struct Supersampler {
+    factor: u32,
+    filter: DownsampleFilter,
+    frame: Framebuffer,
+    linear: Vec<[f32; 4]>,
+    rows: Vec<[f32; 4]>,
+    // Row and column taps of the last resolve
+    taps: Option<(TapsKey, Taps, Taps)>,
+}
+
+impl Supersampler {
+    // For a target of the given size, rendering `factor` x `factor` samples per pixel
+    pub fn new(width: u32, height: u32, factor: u32, filter: DownsampleFilter) -> Self {
+        let factor = factor.max(1);
+        Self {
+            factor,
+            filter,
+            frame: Framebuffer::new(width * factor, height * factor),
+            linear: Vec::new(),
+            rows: Vec::new(),
+            taps: None,
+        }
+    }
+
+    pub fn factor(&self) -> u32 {
+        self.factor
+    }
+
+    pub fn filter(&self) -> DownsampleFilter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: DownsampleFilter) {
+        self.filter = filter;
+    }
+
+    // The large framebuffer to render into
+    pub fn frame(&mut self) -> &mut Framebuffer {
+        &mut self.frame
+    }
+
+    // Filters the large framebuffer down into `target`, which has to be 1 / factor its size
+    pub fn resolve(&mut self, target: &mut Framebuffer) -> Result<(), String> {
+        let (source_width, source_height) = (self.frame.width() as usize, self.frame.height() as usize);
+        let (width, height) = (target.width() as usize, target.height() as usize);
+        let factor = self.factor as usize;
+        if width * factor != source_width || height * factor != source_height {
+            return Err(format!(
+                "Cannot resolve {}x{} samples into {}x{} pixels at {}x supersampling",
+                source_width, source_height, width, height, self.factor
+            ));
+        }
+
+        let key: TapsKey = (width, height, self.factor, self.filter);
+        if self.taps.as_ref().map(|(cached, _, _)| *cached) != Some(key) {
+            let row_taps = taps(width, source_width, self.factor, self.filter);
+            let column_taps = taps(height, source_height, self.factor, self.filter);
+            self.taps = Some((key, row_taps, column_taps));
+        }
+        let (_, row_taps, column_taps) = self.taps.as_ref().unwrap();
+
+        self.linear.clear();
+        self.linear.extend(self.frame.post_image().pixels.iter().map(|c| {
+            [srgb_to_linear(c.r), srgb_to_linear(c.g), srgb_to_linear(c.b), c.a as f32 / 255.0]
+        }));
+
+        // Along the rows, into a buffer of target width and source height
+        self.rows.clear();
+        self.rows.resize(width * source_height, [0.0; 4]);
+        for y in 0..source_height {
+            let source_row = &self.linear[y * source_width..(y + 1) * source_width];
+            for (x, taps) in row_taps.iter().enumerate() {
+                let out = &mut self.rows[y * width + x];
+                for &(s, weight) in taps {
+                    for c in 0..4 {
+                        out[c] += source_row[s][c] * weight;
+                    }
+                }
+            }
+        }
+
+        // Along the columns, into the target
+        let image = target.post_image();
+        for (y, taps) in column_taps.iter().enumerate() {
+            for x in 0..width {
+                let mut sum = [0.0; 4];
+                for &(s, weight) in taps {
+                    let value = self.rows[s * width + x];
+                    for c in 0..4 {
+                        sum[c] += value[c] * weight;
+                    }
+                }
+                image.pixels[y * width + x] = Color::RGBA(
+                    linear_to_srgb(sum[0]),
+                    linear_to_srgb(sum[1]),
+                    linear_to_srgb(sum[2]),
+                    (sum[3].clamp(0.0, 1.0) * 255.0).round() as u8,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [DownsampleFilter; 4] =
+        [DownsampleFilter::Box, DownsampleFilter::Tent, DownsampleFilter::Mitchell, DownsampleFilter::Lanczos];
+
+    fn fill(supersampler: &mut Supersampler, color: impl Fn(usize) -> Color) {
+        for (i, pixel) in supersampler.frame().post_image().pixels.iter_mut().enumerate() {
+            *pixel = color(i);
+        }
+    }
+
+    #[test]
+    fn constant_images_stay_constant() {
+        let color = Color::RGBA(200, 30, 120, 180);
+        for filter in FILTERS {
+            for factor in [2, 3, 4] {
+                let mut supersampler = Supersampler::new(7, 5, factor, filter);
+                fill(&mut supersampler, |_| color);
+                let mut target = Framebuffer::new(7, 5);
+                supersampler.resolve(&mut target).unwrap();
+                assert!(target.post_image().pixels.iter().all(|&pixel| pixel == color), "{:?} at {}x", filter, factor);
+            }
+        }
+    }
+
+    #[test]
+    fn taps_are_normalized() {
+        for filter in FILTERS {
+            for factor in [1, 2, 3, 4] {
+                for (pixel, taps) in taps(9, 9 * factor as usize, factor, filter).iter().enumerate() {
+                    let sum: f32 = taps.iter().map(|&(_, weight)| weight).sum();
+                    assert!((sum - 1.0).abs() < 1e-5, "{:?} at {}x, pixel {}: {}", filter, factor, pixel, sum);
+                }
+            }
+        }
+        // Lanczos has negative lobes, so normalizing has to keep their sign
+        let lanczos = taps(9, 36, 4, DownsampleFilter::Lanczos);
+        assert!(lanczos[4].iter().any(|&(_, weight)| weight < 0.0));
+    }
+
+    #[test]
+    fn target_size_has_to_match() {
+        let mut supersampler = Supersampler::new(8, 6, 2, DownsampleFilter::Box);
+        assert!(supersampler.resolve(&mut Framebuffer::new(8, 6)).is_ok());
+        assert!(supersampler.resolve(&mut Framebuffer::new(16, 12)).is_err());
+        assert!(supersampler.resolve(&mut Framebuffer::new(8, 5)).is_err());
+    }
+
+    #[test]
+    fn changing_the_filter_replaces_the_cached_taps() {
+        let stripes = |i: usize| if i % 5 < 2 { Color::RGB(255, 255, 255) } else { Color::RGB(0, 0, 0) };
+        let resolve = |supersampler: &mut Supersampler| {
+            let mut target = Framebuffer::new(6, 4);
+            supersampler.resolve(&mut target).unwrap();
+            target.post_image().pixels.to_vec()
+        };
+
+        let mut reused = Supersampler::new(6, 4, 3, DownsampleFilter::Box);
+        fill(&mut reused, stripes);
+        let boxed = resolve(&mut reused);
+        reused.set_filter(DownsampleFilter::Lanczos);
+        let lanczos = resolve(&mut reused);
+
+        let mut fresh = Supersampler::new(6, 4, 3, DownsampleFilter::Lanczos);
+        fill(&mut fresh, stripes);
+        assert_eq!(lanczos, resolve(&mut fresh));
+        assert_ne!(lanczos, boxed);
+    }
+}
@@ -0,0 +1,824 @@
+use crate::color::parse_color;
+use crate::path::{Path, Point, Transform};
+use crate::scanline::FillRule;
+use sdl2::pixels::Color;
+use std::f32::consts::PI;
+
+// Loading a subset of SVG into paths for the rasterizer.
+//
+// Supported are the <path>, <rect>, <circle>, <ellipse>, <polygon> and <polyline> elements,
+// nested <g> groups, the `transform` attribute and the fill, stroke and fill-rule properties,
+// given either as attributes or in a `style` attribute. Properties are inherited from the
+// enclosing groups. Everything else (gradients, text, CSS, clip paths, ...) is skipped, and so
+// are the shapes inside definitions, which are only drawn where something references them.
+// All transforms, including the viewBox mapping, are applied while parsing, so the shapes
+// come out in the pixel coordinates of the document.
+
+#[derive(Clone, Debug)]
+pub struct SvgShape {
+    pub path: Path,
+    pub fill: Option<Color>,
+    pub stroke: Option<Color>,
+    pub fill_rule: FillRule,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SvgDocument {
+    pub width: f32,
+    pub height: f32,
+    pub shapes: Vec<SvgShape>,
+}
+
+impl SvgDocument {
+    // Scales and moves the document so it fits centered into the given box
+    pub fn fit(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        if self.width <= 0.0 || self.height <= 0.0 {
+            return;
+        }
+        let scale = (width / self.width).min(height / self.height);
+        let offset_x = x + (width - self.width * scale) / 2.0;
+        let offset_y = y + (height - self.height * scale) / 2.0;
+        let transform = Transform::translate(offset_x, offset_y).then(&Transform::scale(scale, scale));
+        for shape in self.shapes.iter_mut() {
+            shape.path = shape.path.transformed(&transform);
+        }
+        self.width *= scale;
+        self.height *= scale;
+    }
+}
+
+pub fn load_svg(file: &str) -> Result<SvgDocument, String> {
+    let source = std::fs::read_to_string(file).map_err(|e| format!("Could not read {}: {}", file, e))?;
+    parse_svg(&source)
+}
+
+// Properties inherited down the element tree
+#[derive(Clone, Copy)]
+struct Style {
+    transform: Transform,
+    fill: Option<Color>,
+    stroke: Option<Color>,
+    fill_rule: FillRule,
+}
+
+// Containers whose content is never rendered in place
+const NON_RENDERED: [&str; 8] =
+    ["defs", "clipPath", "mask", "symbol", "pattern", "marker", "linearGradient", "radialGradient"];
+
+pub fn parse_svg(source: &str) -> Result<SvgDocument, String> {
+    let mut document = SvgDocument::default();
+    let mut stack = vec![Style {
+        transform: Transform::identity(),
+        fill: Some(Color::RGB(0, 0, 0)),
+        stroke: None,
+        fill_rule: FillRule::NonZero,
+    }];
+    let mut found_root = false;
+    // How many non-rendered containers enclose the current tag
+    let mut hidden_depth: usize = 0;
+
+    for tag in Tags::new(source) {
+        let tag = tag?;
+        if tag.closing {
+            if (tag.name == "svg" || tag.name == "g") && stack.len() > 1 {
+                stack.pop();
+            } else if NON_RENDERED.contains(&tag.name) {
+                hidden_depth = hidden_depth.saturating_sub(1);
+            }
+            continue;
+        }
+        if NON_RENDERED.contains(&tag.name) {
+            if !tag.self_closing {
+                hidden_depth += 1;
+            }
+            continue;
+        }
+
+        let mut style = *stack.last().unwrap();
+        if tag.name == "svg" && !found_root {
+            found_root = true;
+            let (width, height, view_box) = root_size(&tag)?;
+            document.width = width;
+            document.height = height;
+            if let Some((vx, vy, vw, vh)) = view_box {
+                if vw > 0.0 && vh > 0.0 {
+                    style.transform = Transform::scale(width / vw, height / vh).then(&Transform::translate(-vx, -vy));
+                }
+            }
+        }
+        apply_style(&tag, &mut style)?;
+
+        match tag.name {
+            "svg" | "g" if !tag.self_closing => stack.push(style),
+            "path" | "rect" | "circle" | "ellipse" | "polygon" | "polyline" if hidden_depth == 0 => {
+                // Open subpaths such as polylines are still filled as if closed, like SVG does
+                document.shapes.push(SvgShape {
+                    path: shape_path(&tag)?.transformed(&style.transform),
+                    fill: style.fill,
+                    stroke: style.stroke,
+                    fill_rule: style.fill_rule,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if !found_root {
+        return Err("No <svg> element found".to_string());
+    }
+    Ok(document)
+}
+
+// x, y, width and height of the viewBox
+type ViewBox = (f32, f32, f32, f32);
+
+fn root_size(tag: &Tag) -> Result<(f32, f32, Option<ViewBox>), String> {
+    let view_box = match tag.attribute("viewBox") {
+        Some(value) => {
+            let numbers = parse_numbers(value)?;
+            if numbers.len() != 4 {
+                return Err(format!("Invalid viewBox \"{}\"", value));
+            }
+            Some((numbers[0], numbers[1], numbers[2], numbers[3]))
+        }
+        None => None,
+    };
+    let length = |name: &str, fallback: f32| -> Result<f32, String> {
+        match tag.attribute(name) {
+            Some(value) if !value.ends_with('%') => parse_length(value),
+            _ => Ok(fallback),
+        }
+    };
+    let (default_width, default_height) = view_box.map_or((300.0, 150.0), |v| (v.2, v.3));
+    Ok((length("width", default_width)?, length("height", default_height)?, view_box))
+}
+
+fn apply_style(tag: &Tag, style: &mut Style) -> Result<(), String> {
+    if let Some(value) = tag.attribute("transform") {
+        style.transform = style.transform.then(&parse_transform(value)?);
+    }
+
+    let mut properties: Vec<(&str, &str)> = tag.attributes.clone();
+    if let Some(inline) = tag.attribute("style") {
+        for declaration in inline.split(';') {
+            if let Some((name, value)) = declaration.split_once(':') {
+                properties.push((name.trim(), value.trim()));
+            }
+        }
+    }
+
+    // Inline style declarations come last and win over the presentation attributes
+    for (name, value) in properties {
+        match name {
+            "fill" => style.fill = parse_paint(value, style.fill)?,
+            "stroke" => style.stroke = parse_paint(value, style.stroke)?,
+            "fill-rule" => {
+                style.fill_rule = match value {
+                    "evenodd" => FillRule::EvenOdd,
+                    _ => FillRule::NonZero,
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn shape_path(tag: &Tag) -> Result<Path, String> {
+    let number = |name: &str| -> Result<f32, String> { tag.attribute(name).map_or(Ok(0.0), parse_length) };
+    let mut path = Path::new();
+
+    match tag.name {
+        "path" => return parse_path_data(tag.attribute("d").unwrap_or("")),
+        "rect" => {
+            let (x, y, w, h) = (number("x")?, number("y")?, number("width")?, number("height")?);
+            if w <= 0.0 || h <= 0.0 {
+                return Ok(path);
+            }
+            // A missing corner radius takes the value of the other one
+            let (mut rx, mut ry) = (number("rx")?, number("ry")?);
+            if tag.attribute("rx").is_none() {
+                rx = ry;
+            }
+            if tag.attribute("ry").is_none() {
+                ry = rx;
+            }
+            let (rx, ry) = (rx.clamp(0.0, w / 2.0), ry.clamp(0.0, h / 2.0));
+            if rx == 0.0 || ry == 0.0 {
+                path.move_to(x, y).line_to(x + w, y).line_to(x + w, y + h).line_to(x, y + h).close();
+            } else {
+                path.move_to(x + rx, y).line_to(x + w - rx, y);
+                arc_to(&mut path, (x + w - rx, y), (rx, ry), 0.0, false, true, (x + w, y + ry));
+                path.line_to(x + w, y + h - ry);
+                arc_to(&mut path, (x + w, y + h - ry), (rx, ry), 0.0, false, true, (x + w - rx, y + h));
+                path.line_to(x + rx, y + h);
+                arc_to(&mut path, (x + rx, y + h), (rx, ry), 0.0, false, true, (x, y + h - ry));
+                path.line_to(x, y + ry);
+                arc_to(&mut path, (x, y + ry), (rx, ry), 0.0, false, true, (x + rx, y));
+                path.close();
+            }
+        }
+        "circle" | "ellipse" => {
+            let (cx, cy) = (number("cx")?, number("cy")?);
+            let (rx, ry) = if tag.name == "circle" { (number("r")?, number("r")?) } else { (number("rx")?, number("ry")?) };
+            if rx > 0.0 && ry > 0.0 {
+                path.move_to(cx + rx, cy);
+                arc_to(&mut path, (cx + rx, cy), (rx, ry), 0.0, false, true, (cx - rx, cy));
+                arc_to(&mut path, (cx - rx, cy), (rx, ry), 0.0, false, true, (cx + rx, cy));
+                path.close();
+            }
+        }
+        "polygon" | "polyline" => {
+            let numbers = parse_numbers(tag.attribute("points").unwrap_or(""))?;
+            for (i, pair) in numbers.chunks_exact(2).enumerate() {
+                if i == 0 {
+                    path.move_to(pair[0], pair[1]);
+                } else {
+                    path.line_to(pair[0], pair[1]);
+                }
+            }
+            if tag.name == "polygon" && numbers.len() >= 4 {
+                path.close();
+            }
+        }
+        _ => {}
+    }
+    Ok(path)
+}
+
+// Colors
+
+fn parse_paint(value: &str, inherited: Option<Color>) -> Result<Option<Color>, String> {
+    match value.trim() {
+        "none" => Ok(None),
+        "inherit" => Ok(inherited),
+        value if value.starts_with("url(") => Ok(inherited),
+        value => parse_color(value).map(Some),
+    }
+}
+
+// Numbers, lengths and transforms
+
+fn parse_length(value: &str) -> Result<f32, String> {
+    let trimmed = value.trim().trim_end_matches("px");
+    trimmed.parse().map_err(|_| format!("Invalid length \"{}\"", value))
+}
+
+fn parse_numbers(value: &str) -> Result<Vec<f32>, String> {
+    let mut scanner = Scanner::new(value);
+    let mut numbers = Vec::new();
+    while scanner.has_number() {
+        numbers.push(scanner.number()?);
+    }
+    Ok(numbers)
+}
+
+pub fn parse_transform(value: &str) -> Result<Transform, String> {
+    let mut transform = Transform::identity();
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let open = rest.find('(').ok_or_else(|| format!("Invalid transform \"{}\"", value))?;
+        let close = rest[open..].find(')').map(|i| open + i).ok_or_else(|| format!("Invalid transform \"{}\"", value))?;
+        let name = rest[..open].trim().trim_start_matches(',').trim();
+        let args = parse_numbers(&rest[open + 1..close])?;
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0.0);
+
+        let next = match (name, args.len()) {
+            ("matrix", 6) => Transform { a: arg(0), b: arg(1), c: arg(2), d: arg(3), e: arg(4), f: arg(5) },
+            ("translate", 1 | 2) => Transform::translate(arg(0), arg(1)),
+            ("scale", 1) => Transform::scale(arg(0), arg(0)),
+            ("scale", 2) => Transform::scale(arg(0), arg(1)),
+            ("rotate", 1) => Transform::rotate(arg(0).to_radians()),
+            ("rotate", 3) => Transform::translate(arg(1), arg(2))
+                .then(&Transform::rotate(arg(0).to_radians()))
+                .then(&Transform::translate(-arg(1), -arg(2))),
+            ("skewX", 1) => Transform::skew(arg(0).to_radians(), 0.0),
+            ("skewY", 1) => Transform::skew(0.0, arg(0).to_radians()),
+            _ => return Err(format!("Invalid transform \"{}\"", value)),
+        };
+        transform = transform.then(&next);
+        rest = rest[close + 1..].trim_start();
+    }
+    Ok(transform)
+}
+
+// Path data
+
+pub fn parse_path_data(d: &str) -> Result<Path, String> {
+    let mut path = Path::new();
+    let mut scanner = Scanner::new(d);
+    let mut pen: Point = (0.0, 0.0);
+    let mut start: Point = (0.0, 0.0);
+    // Control points of the previous curve, reflected by the smooth S / T commands
+    let mut last_cubic: Option<Point> = None;
+    let mut last_quad: Option<Point> = None;
+    let mut command = None;
+
+    loop {
+        scanner.skip_separators();
+        if let Some(letter) = scanner.command() {
+            command = Some(letter);
+        } else if scanner.at_end() {
+            break;
+        } else if command.is_none() || !scanner.has_number() {
+            return Err(format!("Invalid path data at \"{}\"", scanner.rest()));
+        }
+        let letter = command.unwrap();
+        let relative = letter.is_ascii_lowercase();
+        let origin = if relative { pen } else { (0.0, 0.0) };
+        let point = |scanner: &mut Scanner| -> Result<Point, String> {
+            Ok((origin.0 + scanner.number()?, origin.1 + scanner.number()?))
+        };
+        let reflect = |control: Option<Point>| control.map_or(pen, |c| (2.0 * pen.0 - c.0, 2.0 * pen.1 - c.1));
+
+        let (mut cubic, mut quad) = (None, None);
+        match letter.to_ascii_uppercase() {
+            'M' => {
+                pen = point(&mut scanner)?;
+                start = pen;
+                path.move_to(pen.0, pen.1);
+                // Further coordinate pairs after a move are implicit lines
+                command = Some(if relative { 'l' } else { 'L' });
+            }
+            'L' => {
+                pen = point(&mut scanner)?;
+                path.line_to(pen.0, pen.1);
+            }
+            'H' => {
+                pen.0 = origin.0 + scanner.number()?;
+                path.line_to(pen.0, pen.1);
+            }
+            'V' => {
+                pen.1 = origin.1 + scanner.number()?;
+                path.line_to(pen.0, pen.1);
+            }
+            'C' | 'S' => {
+                let c1 = if letter.eq_ignore_ascii_case(&'C') { point(&mut scanner)? } else { reflect(last_cubic) };
+                let c2 = point(&mut scanner)?;
+                let end = point(&mut scanner)?;
+                path.cubic_to(c1.0, c1.1, c2.0, c2.1, end.0, end.1);
+                cubic = Some(c2);
+                pen = end;
+            }
+            'Q' | 'T' => {
+                let c = if letter.eq_ignore_ascii_case(&'Q') { point(&mut scanner)? } else { reflect(last_quad) };
+                let end = point(&mut scanner)?;
+                path.quad_to(c.0, c.1, end.0, end.1);
+                quad = Some(c);
+                pen = end;
+            }
+            'A' => {
+                let radii = (scanner.number()?, scanner.number()?);
+                let rotation = scanner.number()?;
+                let large_arc = scanner.flag()?;
+                let sweep = scanner.flag()?;
+                let end = point(&mut scanner)?;
+                arc_to(&mut path, pen, radii, rotation.to_radians(), large_arc, sweep, end);
+                pen = end;
+            }
+            'Z' => {
+                path.close();
+                pen = start;
+                command = None;
+            }
+            _ => return Err(format!("Unsupported path command '{}'", letter)),
+        }
+
+        // Only a smooth curve right after the same kind of curve reflects its control point
+        last_cubic = cubic;
+        last_quad = quad;
+    }
+    Ok(path)
+}
+
+// Elliptical arc from `from` to `to` as cubic curves of at most 90 degrees each,
+// using the endpoint to center conversion from the SVG implementation notes
+fn arc_to(path: &mut Path, from: Point, radii: (f32, f32), rotation: f32, large_arc: bool, sweep: bool, to: Point) {
+    let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+    if rx == 0.0 || ry == 0.0 || from == to {
+        path.line_to(to.0, to.1);
+        return;
+    }
+    let (sin, cos) = rotation.sin_cos();
+
+    let dx = (from.0 - to.0) / 2.0;
+    let dy = (from.1 - to.1) / 2.0;
+    let x1 = cos * dx + sin * dy;
+    let y1 = -sin * dx + cos * dy;
+
+    // Radii too small to reach the end point are scaled up
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+
+    let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut factor = (numerator / denominator).max(0.0).sqrt();
+    if large_arc == sweep {
+        factor = -factor;
+    }
+    let cx1 = factor * rx * y1 / ry;
+    let cy1 = -factor * ry * x1 / rx;
+    let cx = cos * cx1 - sin * cy1 + (from.0 + to.0) / 2.0;
+    let cy = sin * cx1 + cos * cy1 + (from.1 + to.1) / 2.0;
+
+    let angle = |ux: f32, uy: f32| uy.atan2(ux);
+    let theta1 = angle((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let mut delta = angle((-x1 - cx1) / rx, (-y1 - cy1) / ry) - theta1;
+    if sweep && delta < 0.0 {
+        delta += 2.0 * PI;
+    } else if !sweep && delta > 0.0 {
+        delta -= 2.0 * PI;
+    }
+
+    let segments = (delta.abs() / (PI / 2.0)).ceil().max(1.0) as usize;
+    let step = delta / segments as f32;
+    let alpha = 4.0 / 3.0 * (step / 4.0).tan();
+    let on_ellipse = |t: f32| {
+        let (s, c) = t.sin_cos();
+        (cx + rx * c * cos - ry * s * sin, cy + rx * c * sin + ry * s * cos)
+    };
+    let derivative = |t: f32| {
+        let (s, c) = t.sin_cos();
+        (-rx * s * cos - ry * c * sin, -rx * s * sin + ry * c * cos)
+    };
+
+    for i in 0..segments {
+        let t1 = theta1 + step * i as f32;
+        let t2 = t1 + step;
+        let (p1, d1) = (on_ellipse(t1), derivative(t1));
+        let (d2, p2) = (derivative(t2), if i + 1 == segments { to } else { on_ellipse(t2) });
+        path.cubic_to(
+            p1.0 + alpha * d1.0,
+            p1.1 + alpha * d1.1,
+            p2.0 - alpha * d2.0,
+            p2.1 - alpha * d2.1,
+            p2.0,
+            p2.1,
+        );
+    }
+}
+
+// Reads numbers, flags and command letters out of attribute values
+struct Scanner<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    fn bytes(&self) -> &'a [u8] {
+        self.text.as_bytes()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.text.len()
+    }
+
+    fn skip_separators(&mut self) {
+        while self.pos < self.text.len() && matches!(self.bytes()[self.pos], b' ' | b'\t' | b'\n' | b'\r' | b',') {
+            self.pos += 1;
+        }
+    }
+
+    fn has_number(&mut self) -> bool {
+        self.skip_separators();
+        matches!(self.bytes().get(self.pos), Some(b'0'..=b'9' | b'-' | b'+' | b'.'))
+    }
+
+    fn peek_command(&mut self) -> Option<char> {
+        self.skip_separators();
+        match self.bytes().get(self.pos) {
+            Some(&c) if c.is_ascii_alphabetic() && c != b'e' && c != b'E' => Some(c as char),
+            _ => None,
+        }
+    }
+
+    fn command(&mut self) -> Option<char> {
+        let command = self.peek_command();
+        if command.is_some() {
+            self.pos += 1;
+        }
+        command
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        self.skip_separators();
+        let bytes = self.bytes();
+        let start = self.pos;
+        let mut end = start;
+        if matches!(bytes.get(end), Some(b'-' | b'+')) {
+            end += 1;
+        }
+        let mut seen_dot = false;
+        while let Some(&c) = bytes.get(end) {
+            if c.is_ascii_digit() {
+                end += 1;
+            } else if c == b'.' && !seen_dot {
+                seen_dot = true;
+                end += 1;
+            } else {
+                break;
+            }
+        }
+        if matches!(bytes.get(end), Some(b'e' | b'E')) {
+            let mut exponent = end + 1;
+            if matches!(bytes.get(exponent), Some(b'-' | b'+')) {
+                exponent += 1;
+            }
+            if bytes.get(exponent).is_some_and(u8::is_ascii_digit) {
+                end = exponent;
+                while bytes.get(end).is_some_and(u8::is_ascii_digit) {
+                    end += 1;
+                }
+            }
+        }
+        self.pos = end;
+        self.text[start..end].parse().map_err(|_| format!("Expected a number at \"{}\"", &self.text[start..]))
+    }
+
+    // Arc flags are single digits and may be written without separators
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separators();
+        let flag = match self.bytes().get(self.pos) {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(format!("Expected an arc flag at \"{}\"", self.rest())),
+        };
+        self.pos += 1;
+        Ok(flag)
+    }
+}
+
+// A minimal XML tag reader. Text, comments, processing instructions and doctypes are skipped
+// and entities are left as they are, which is all the supported elements need.
+struct Tag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str)>,
+    closing: bool,
+    self_closing: bool,
+}
+
+impl<'a> Tag<'a> {
+    fn attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes.iter().find(|(key, _)| *key == name).map(|(_, value)| *value)
+    }
+}
+
+struct Tags<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl<'a> Tags<'a> {
+    fn new(source: &'a str) -> Self {
+        Self { source, pos: 0 }
+    }
+
+    fn parse_tag(&self, body: &'a str) -> Result<Tag<'a>, String> {
+        let closing = body.starts_with('/');
+        let self_closing = body.ends_with('/');
+        let body = body.trim_start_matches('/').trim_end_matches('/');
+        let name_end = body.find(|c: char| c.is_whitespace()).unwrap_or(body.len());
+        let name = &body[..name_end];
+
+        let mut attributes = Vec::new();
+        let mut rest = body[name_end..].trim_start();
+        while !rest.is_empty() {
+            let equals = rest.find('=').ok_or_else(|| format!("Invalid attribute in <{}>", name))?;
+            let key = rest[..equals].trim();
+            let after = rest[equals + 1..].trim_start();
+            let quote = after.chars().next().filter(|c| *c == '"' || *c == '\'');
+            let quote = quote.ok_or_else(|| format!("Unquoted attribute {} in <{}>", key, name))?;
+            let value_end = after[1..].find(quote).ok_or_else(|| format!("Unterminated attribute {} in <{}>", key, name))?;
+            attributes.push((key, &after[1..value_end + 1]));
+            rest = after[value_end + 2..].trim_start();
+        }
+        Ok(Tag { name, attributes, closing, self_closing })
+    }
+}
+
+impl<'a> Iterator for Tags<'a> {
+    type Item = Result<Tag<'a>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.pos + self.source[self.pos..].find('<')?;
+            let rest = &self.source[start..];
+
+            let skip_to = |terminator: &str| rest.find(terminator).map(|end| start + end + terminator.len());
+            let skipped = if rest.starts_with("<!--") {
+                Some(skip_to("-->"))
+            } else if rest.starts_with("<![CDATA[") {
+                Some(skip_to("]]>"))
+            } else if rest.starts_with("<?") {
+                Some(skip_to("?>"))
+            } else if rest.starts_with("<!") {
+                Some(skip_to(">"))
+            } else {
+                None
+            };
+            if let Some(end) = skipped {
+                match end {
+                    Some(end) => {
+                        self.pos = end;
+                        continue;
+                    }
+                    None => {
+                        self.pos = self.source.len();
+                        return Some(Err("Unterminated markup".to_string()));
+                    }
+                }
+            }
+
+            // Attribute values may contain '>' so the end of the tag has to skip quoted text
+            let mut quote = None;
+            let mut end = None;
+            for (i, c) in rest.char_indices().skip(1) {
+                match (quote, c) {
+                    (None, '"' | '\'') => quote = Some(c),
+                    (Some(q), c) if c == q => quote = None,
+                    (None, '>') => {
+                        end = Some(i);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            let Some(end) = end else {
+                self.pos = self.source.len();
+                return Some(Err("Unterminated tag".to_string()));
+            };
+            self.pos = start + end + 1;
+            return Some(self.parse_tag(rest[1..end].trim()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::PathCommand::{self, *};
+
+    fn assert_commands(path: &Path, expected: &[PathCommand]) {
+        assert_eq!(path.commands.len(), expected.len(), "{:?}", path.commands);
+        let close = |a: Point, b: Point| (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4;
+        for (&actual, &expected) in path.commands.iter().zip(expected) {
+            let same = match (actual, expected) {
+                (MoveTo(a), MoveTo(b)) | (LineTo(a), LineTo(b)) => close(a, b),
+                (QuadTo(a1, a2), QuadTo(b1, b2)) => close(a1, b1) && close(a2, b2),
+                (CubicTo(a1, a2, a3), CubicTo(b1, b2, b3)) => close(a1, b1) && close(a2, b2) && close(a3, b3),
+                (Close, Close) => true,
+                _ => false,
+            };
+            assert!(same, "{:?} instead of {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn lines_with_relative_commands_and_implicit_lines() {
+        let path = parse_path_data("m 10 10 20 0 v 5 h -5 z l 1 1 M 0 0 2 2 3 3 H 4 V 5 Z").unwrap();
+        assert_commands(
+            &path,
+            &[
+                MoveTo((10.0, 10.0)),
+                LineTo((30.0, 10.0)),
+                LineTo((30.0, 15.0)),
+                LineTo((25.0, 15.0)),
+                Close,
+                LineTo((11.0, 11.0)),
+                MoveTo((0.0, 0.0)),
+                LineTo((2.0, 2.0)),
+                LineTo((3.0, 3.0)),
+                LineTo((4.0, 3.0)),
+                LineTo((4.0, 5.0)),
+                Close,
+            ],
+        );
+    }
+
+    #[test]
+    fn smooth_curves_reflect_the_previous_control_point() {
+        let path = parse_path_data("M0 0 C 0 10 10 10 10 0 S 20 -10 20 0 s 10 10 10 0").unwrap();
+        assert_commands(
+            &path,
+            &[
+                MoveTo((0.0, 0.0)),
+                CubicTo((0.0, 10.0), (10.0, 10.0), (10.0, 0.0)),
+                CubicTo((10.0, -10.0), (20.0, -10.0), (20.0, 0.0)),
+                CubicTo((20.0, 10.0), (30.0, 10.0), (30.0, 0.0)),
+            ],
+        );
+
+        let path = parse_path_data("M0 0 Q 5 10 10 0 T 20 0 t 10 0").unwrap();
+        assert_commands(
+            &path,
+            &[
+                MoveTo((0.0, 0.0)),
+                QuadTo((5.0, 10.0), (10.0, 0.0)),
+                QuadTo((15.0, -10.0), (20.0, 0.0)),
+                QuadTo((25.0, 10.0), (30.0, 0.0)),
+            ],
+        );
+
+        // Without a curve of the same kind before, the control point is the current point
+        let path = parse_path_data("M0 0 L 10 0 T 20 0 S 30 5 40 0").unwrap();
+        assert_commands(
+            &path,
+            &[
+                MoveTo((0.0, 0.0)),
+                LineTo((10.0, 0.0)),
+                QuadTo((10.0, 0.0), (20.0, 0.0)),
+                CubicTo((20.0, 0.0), (30.0, 5.0), (40.0, 0.0)),
+            ],
+        );
+    }
+
+    #[test]
+    fn arc_flags_need_no_separators() {
+        let packed = parse_path_data("M0 0 a1 1 0 01 1 1").unwrap();
+        let spaced = parse_path_data("M0 0 a 1 1 0 0 1 1 1").unwrap();
+        assert_eq!(packed.commands, spaced.commands);
+        assert!(packed.commands.len() > 1);
+        match packed.commands.last() {
+            Some(CubicTo(_, _, end)) => assert_eq!(*end, (1.0, 1.0)),
+            other => panic!("arc ended with {:?}", other),
+        }
+        assert!(parse_path_data("M0 0 a1 1 0 21 1 1").is_err());
+    }
+
+    #[test]
+    fn exponents_are_not_commands() {
+        let path = parse_path_data("M1e-3 2E2L1e1,0l.5e1-1E+1").unwrap();
+        assert_commands(&path, &[MoveTo((0.001, 200.0)), LineTo((10.0, 0.0)), LineTo((15.0, -10.0))]);
+        assert!(parse_path_data("M0 0 L 1 2 E 3 4").is_err());
+    }
+
+    #[test]
+    fn view_box_maps_to_the_document_size() {
+        let document = parse_svg(
+            r#"<svg width="200" height="100" viewBox="10 20 50 25"><rect x="10" y="20" width="50" height="25"/></svg>"#,
+        )
+        .unwrap();
+        assert_eq!((document.width, document.height), (200.0, 100.0));
+        assert_commands(
+            &document.shapes[0].path,
+            &[MoveTo((0.0, 0.0)), LineTo((200.0, 0.0)), LineTo((200.0, 100.0)), LineTo((0.0, 100.0)), Close],
+        );
+
+        // Without a size the viewBox gives it
+        let document = parse_svg(r#"<svg viewBox="0 0 40 30"/>"#).unwrap();
+        assert_eq!((document.width, document.height), (40.0, 30.0));
+    }
+
+    #[test]
+    fn style_overrides_presentation_attributes() {
+        let document = parse_svg(
+            r##"<svg><g fill="red" stroke="blue">
+                <rect width="1" height="1" fill="lime" style="fill: #000080; fill-rule:evenodd"/>
+                <rect width="1" height="1" style="stroke:none"/>
+            </g></svg>"##,
+        )
+        .unwrap();
+        let (first, second) = (&document.shapes[0], &document.shapes[1]);
+        assert_eq!(first.fill, Some(Color::RGB(0, 0, 128)));
+        assert_eq!(first.stroke, Some(Color::RGB(0, 0, 255)));
+        assert_eq!(first.fill_rule, FillRule::EvenOdd);
+        assert_eq!(second.fill, Some(Color::RGB(255, 0, 0)));
+        assert_eq!(second.stroke, None);
+    }
+
+    #[test]
+    fn shapes_in_definitions_are_not_drawn() {
+        let document = parse_svg(
+            r#"<svg>
+                <defs><linearGradient id="a"/><g><circle r="5"/></g><clipPath id="b"><rect width="1" height="1"/></clipPath></defs>
+                <mask id="c"><path d="M0 0 L 1 1"/></mask>
+                <symbol/>
+                <ellipse rx="2" ry="1"/>
+            </svg>"#,
+        )
+        .unwrap();
+        assert_eq!(document.shapes.len(), 1);
+    }
+
+    #[test]
+    fn invalid_documents_are_errors() {
+        assert!(parse_path_data("M 0 0 L 1").is_err());
+        assert!(parse_path_data("0 0 L 1 1").is_err());
+        assert!(parse_svg(r#"<svg><path d="M 0 0 X 1 1"/></svg>"#).is_err());
+        assert!(parse_svg(r#"<g><rect width="1" height="1"/></g>"#).is_err());
+        assert!(parse_transform("scale)(2").is_err());
+        assert!(parse_transform("translate(1").is_err());
+        assert!(parse_transform("translate(1, 2) scale(2)").is_ok());
+    }
+}
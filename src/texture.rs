@@ -0,0 +1,71 @@
+use crate::color::premultiply;
+use sdl2::pixels::Color;
+
+// RGBA textures sampled with wrapping texture coordinates, (0, 0) is the top left corner
+#[derive(Clone, Debug)]
+pub struct Texture {
+    width: u32,
+    height: u32,
+    texels: Vec<Color>,
+}
+
+impl Texture {
+    pub fn new(width: u32, height: u32, texels: Vec<Color>) -> Result<Self, String> {
+        if width == 0 || height == 0 || texels.len() != (width * height) as usize {
+            return Err(format!("Texture of {}x{} needs {} texels, got {}", width, height, width * height, texels.len()));
+        }
+        Ok(Self { width, height, texels })
+    }
+
+    // A `cells` x `cells` checkerboard of the two colors, at least one texel in size
+    pub fn checkerboard(size: u32, cells: u32, c1: Color, c2: Color) -> Self {
+        let size = size.max(1);
+        let cell = (size / cells.max(1)).max(1);
+        let texels = (0..size * size)
+            .map(|i| if ((i % size) / cell + (i / size) / cell).is_multiple_of(2) { c1 } else { c2 })
+            .collect();
+        Self { width: size, height: size, texels }
+    }
+
+    // A copy with the color channels scaled by alpha, for the premultiplied blend mode
+    pub fn premultiplied(&self) -> Self {
+        Self { texels: self.texels.iter().map(|&texel| premultiply(texel)).collect(), ..self.clone() }
+    }
+
+    // Tangent space normal map of a height map, whose red channel is the height. `strength`
+    // scales the slopes, x follows the texture to the right and y down, z is up from the surface.
+    pub fn normal_map(heights: &Texture, strength: f32) -> Self {
+        let (width, height) = (heights.width, heights.height);
+        let at = |x: u32, y: u32| heights.texel(x % width, y % height).r as f32 / 255.0;
+        let texels = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let dx = (at(x + 1, y) - at(x + width - 1, y)) / 2.0 * strength;
+                let dy = (at(x, y + 1) - at(x, y + height - 1)) / 2.0 * strength;
+                let length = (dx * dx + dy * dy + 1.0).sqrt();
+                let byte = |v: f32| ((v / length * 0.5 + 0.5) * 255.0).round() as u8;
+                Color::RGB(byte(-dx), byte(-dy), byte(1.0))
+            })
+            .collect();
+        Self { width, height, texels }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn texel(&self, x: u32, y: u32) -> Color {
+        self.texels[(y * self.width + x) as usize]
+    }
+
+    // Nearest texel, coordinates outside [0, 1) repeat the texture
+    pub fn sample(&self, u: f32, v: f32) -> Color {
+        let x = (u.rem_euclid(1.0) * self.width as f32) as u32;
+        let y = (v.rem_euclid(1.0) * self.height as f32) as u32;
+        self.texel(x.min(self.width - 1), y.min(self.height - 1))
+    }
+}
@@ -0,0 +1,104 @@
+use crate::light::{PointLight, ShadingModel, Surface};
+use crate::math::Vec3;
+use crate::mesh::Mesh;
+use std::collections::HashMap;
+
+// Toon or cel shading, for a stylized look.
+//
+// The light each point light delivers is quantized into a few flat bands instead of falling
+// off smoothly, and the specular highlight is a hard edged spot. Silhouette outlines come from
+// back face inflation : a copy of the mesh pushed out along its normals, with its winding
+// reversed so that only the inside of the shell survives back face culling. Drawn in a flat
+// color and depth tested, the shell shows only as a rim around the silhouette and along creases.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Toon {
+    // Number of lit levels per light, at least 1
+    pub bands: u32,
+    // Light reaching every point regardless of the lights
+    pub ambient: Vec3,
+    pub specular: f32,
+    pub shininess: f32,
+}
+
+impl Default for Toon {
+    fn default() -> Self {
+        Self { bands: 3, ambient: Vec3::splat(0.08), specular: 0.5, shininess: 32.0 }
+    }
+}
+
+impl ShadingModel for Toon {
+    fn shade(&self, surface: &Surface, lights: &[PointLight], eye: Vec3, ambient_visibility: f32) -> Vec3 {
+        let bands = self.bands.max(1) as f32;
+        let view = (eye - surface.position).normalized();
+        let mut result = self.ambient.mul_elements(surface.albedo) * ambient_visibility;
+        for light in lights {
+            let to_light = light.position - surface.position;
+            let distance = to_light.length();
+            if distance >= light.range {
+                continue;
+            }
+            let l = to_light / distance;
+            let n_dot_l = surface.normal.dot(l);
+            if n_dot_l <= 0.0 {
+                continue;
+            }
+            // Rounded up, so everything the light reaches gets at least the first band, and
+            // scaled by the intensity afterwards, so there are exactly `bands` levels
+            let level = ((light.attenuation(distance) * n_dot_l).clamp(0.0, 1.0) * bands).ceil() / bands * light.intensity;
+            let half = (l + view).normalized();
+            let highlight = if surface.normal.dot(half).max(0.0).powf(self.shininess) > 0.5 { self.specular } else { 0.0 };
+            result += (surface.albedo + Vec3::splat(highlight)).mul_elements(light.color) * level;
+        }
+        result
+    }
+}
+
+// The shell for the outline of the mesh, `width` out along its normals in model space. Normals
+// are averaged over the vertices sharing a position, so hard edges do not tear the shell open.
+pub fn outline_shell(mesh: &Mesh, width: f32) -> Mesh {
+    let key = |position: Vec3| position.to_array().map(f32::to_bits);
+    let mut normals: HashMap<[u32; 3], Vec3> = HashMap::new();
+    for vertex in &mesh.vertices {
+        *normals.entry(key(vertex.position)).or_insert(Vec3::ZERO) += vertex.normal;
+    }
+    let mut shell = mesh.clone();
+    for vertex in &mut shell.vertices {
+        vertex.position += normals[&key(vertex.position)].normalized() * width;
+    }
+    for triangle in &mut shell.triangles {
+        triangle.swap(1, 2);
+    }
+    shell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lighting_falls_into_the_configured_bands() {
+        for bands in 1..=5 {
+            let toon = Toon { bands, ambient: Vec3::ZERO, specular: 0.0, shininess: 32.0 };
+            // A bright light, to check it cannot add levels beyond the configured ones
+            let light = PointLight::new(Vec3::new(0.0, 0.01, 0.0), Vec3::splat(1.0), 3.0, 100.0);
+            let mut levels: Vec<f32> = Vec::new();
+            for step in 0..200 {
+                for distance in [0.01, 0.5, 1.0, 2.0, 4.0] {
+                    let angle = step as f32 / 200.0 * std::f32::consts::FRAC_PI_2;
+                    let surface = Surface {
+                        position: Vec3::new(0.0, 0.01 - distance, 0.0),
+                        normal: Vec3::new(angle.sin(), angle.cos(), 0.0),
+                        albedo: Vec3::splat(1.0),
+                        ..Surface::default()
+                    };
+                    let shaded = toon.shade(&surface, &[light], Vec3::new(0.0, 10.0, 10.0), 1.0).x;
+                    if !levels.iter().any(|&level| (level - shaded).abs() < 1e-5) {
+                        levels.push(shaded);
+                    }
+                }
+            }
+            assert_eq!(levels.len(), bands as usize, "levels {:?}", levels);
+        }
+    }
+}
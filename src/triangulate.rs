@@ -0,0 +1,321 @@
+// Polygon triangulation for the triangle filler.
+//
+// Convex outlines are split into a fan around their first vertex. Everything else goes
+// through ear clipping: holes are first bridged into the outer ring, turning the shape
+// into one weakly simple polygon, then convex corners whose triangle contains no other
+// vertex are cut off one at a time. Input is expected to be a simple polygon, both
+// clockwise and counter-clockwise outlines are accepted. Collinear and repeated vertices
+// are dropped instead of producing zero-area triangles.
+//
+// The returned triangles index into the outer points followed by the points of each hole,
+// in the order they were passed in, and are wound the same way as the outer outline.
+
+pub type Point = (f32, f32);
+
+pub fn triangulate(outer: &[Point], holes: &[&[Point]]) -> Vec<[usize; 3]> {
+    let mut points: Vec<Point> = outer.to_vec();
+    let mut ring: Vec<usize> = (0..outer.len()).collect();
+    if ring.len() < 3 {
+        return Vec::new();
+    }
+
+    let outer_area = signed_area(&points, &ring);
+    if outer_area == 0.0 {
+        return Vec::new();
+    }
+    // Work on a counter-clockwise ring (y up), flip the triangles back at the end
+    let flipped = outer_area < 0.0;
+    if flipped {
+        ring.reverse();
+    }
+
+    drop_collinear(&points, &mut ring);
+
+    let mut triangles = if holes.is_empty() && is_convex(&points, &ring) {
+        fan(&ring)
+    } else {
+        let mut hole_rings = Vec::new();
+        for hole in holes {
+            let start = points.len();
+            points.extend_from_slice(hole);
+            let mut hole_ring: Vec<usize> = (start..points.len()).collect();
+            let area = signed_area(&points, &hole_ring);
+            if hole_ring.len() < 3 || area == 0.0 {
+                continue;
+            }
+            // Holes have to run against the outer ring for the bridge to stitch them in
+            if area > 0.0 {
+                hole_ring.reverse();
+            }
+            hole_rings.push(hole_ring);
+        }
+
+        // Bridging the rightmost hole first keeps later bridges from crossing earlier ones
+        hole_rings.sort_by(|a, b| max_x(&points, b).total_cmp(&max_x(&points, a)));
+        for hole_ring in &hole_rings {
+            bridge_hole(&points, &mut ring, hole_ring);
+        }
+        ear_clip(&points, ring)
+    };
+
+    if flipped {
+        for triangle in triangles.iter_mut() {
+            triangle.swap(1, 2);
+        }
+    }
+    triangles
+}
+
+// Twice the signed area of the ring, positive when counter-clockwise with y pointing up
+fn signed_area(points: &[Point], ring: &[usize]) -> f32 {
+    let mut area = 0.0;
+    for i in 0..ring.len() {
+        let (x0, y0) = points[ring[i]];
+        let (x1, y1) = points[ring[(i + 1) % ring.len()]];
+        area += x0 * y1 - x1 * y0;
+    }
+    area
+}
+
+fn cross(a: Point, b: Point, c: Point) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+// Removes corners that do not turn, including repeated points
+fn drop_collinear(points: &[Point], ring: &mut Vec<usize>) {
+    let mut i = 0;
+    while ring.len() > 3 && i < ring.len() {
+        let n = ring.len();
+        if cross(points[ring[(i + n - 1) % n]], points[ring[i]], points[ring[(i + 1) % n]]) == 0.0 {
+            ring.remove(i);
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+fn max_x(points: &[Point], ring: &[usize]) -> f32 {
+    ring.iter().map(|&i| points[i].0).fold(f32::MIN, f32::max)
+}
+
+// Convex when every corner turns left and the turns add up to a single revolution,
+// which rules out star shapes whose corners all turn the same way
+fn is_convex(points: &[Point], ring: &[usize]) -> bool {
+    let mut turning = 0.0;
+    for i in 0..ring.len() {
+        let a = points[ring[i]];
+        let b = points[ring[(i + 1) % ring.len()]];
+        let c = points[ring[(i + 2) % ring.len()]];
+        let turn = cross(a, b, c);
+        if turn < 0.0 {
+            return false;
+        }
+        let dot = (b.0 - a.0) * (c.0 - b.0) + (b.1 - a.1) * (c.1 - b.1);
+        turning += turn.atan2(dot);
+    }
+    (turning - 2.0 * std::f32::consts::PI).abs() < 1e-3
+}
+
+fn fan(ring: &[usize]) -> Vec<[usize; 3]> {
+    (1..ring.len() - 1).map(|i| [ring[0], ring[i], ring[i + 1]]).collect()
+}
+
+// Point inside or on the border of the counter-clockwise triangle abc
+fn in_triangle(p: Point, a: Point, b: Point, c: Point) -> bool {
+    cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+}
+
+// Connects the rightmost hole vertex to a visible outer vertex and splices the hole in
+// as outer -> hole -> back along the same bridge
+fn bridge_hole(points: &[Point], ring: &mut Vec<usize>, hole_ring: &[usize]) {
+    let (hole_pos, &m) = hole_ring
+        .iter()
+        .enumerate()
+        .max_by(|a, b| points[*a.1].0.total_cmp(&points[*b.1].0))
+        .unwrap();
+    let mp = points[m];
+
+    // Closest edge hit by a ray from the hole vertex towards +x
+    let mut hit: Option<(usize, f32)> = None;
+    for i in 0..ring.len() {
+        let a = points[ring[i]];
+        let b = points[ring[(i + 1) % ring.len()]];
+        if a.1 == b.1 || a.1.min(b.1) > mp.1 || a.1.max(b.1) < mp.1 {
+            continue;
+        }
+        let x = a.0 + (mp.1 - a.1) / (b.1 - a.1) * (b.0 - a.0);
+        if x >= mp.0 && hit.is_none_or(|(_, best)| x < best) {
+            // The edge endpoint further right is the first bridge candidate
+            let pick = if a.0 > b.0 { i } else { (i + 1) % ring.len() };
+            hit = Some((pick, x));
+        }
+    }
+    let Some((mut bridge, hit_x)) = hit else {
+        return;
+    };
+
+    // A reflex vertex inside the triangle (hole vertex, hit point, candidate) would block
+    // the bridge, the one closest in angle to the ray is always visible
+    let candidate = points[ring[bridge]];
+    let hit_point = (hit_x, mp.1);
+    let (a, b, c) = if cross(mp, hit_point, candidate) >= 0.0 {
+        (mp, hit_point, candidate)
+    } else {
+        (mp, candidate, hit_point)
+    };
+    let mut best_angle = f32::MAX;
+    for i in 0..ring.len() {
+        let p = points[ring[i]];
+        if i == bridge || p == candidate || p == mp {
+            continue;
+        }
+        let prev = points[ring[(i + ring.len() - 1) % ring.len()]];
+        let next = points[ring[(i + 1) % ring.len()]];
+        if cross(prev, p, next) >= 0.0 || !in_triangle(p, a, b, c) {
+            continue;
+        }
+        let angle = ((p.1 - mp.1).abs()).atan2(p.0 - mp.0);
+        if angle < best_angle {
+            best_angle = angle;
+            bridge = i;
+        }
+    }
+
+    let mut spliced = Vec::with_capacity(hole_ring.len() + 2);
+    for k in 0..=hole_ring.len() {
+        spliced.push(hole_ring[(hole_pos + k) % hole_ring.len()]);
+    }
+    spliced.push(ring[bridge]);
+    ring.splice(bridge + 1..bridge + 1, spliced);
+}
+
+fn ear_clip(points: &[Point], mut ring: Vec<usize>) -> Vec<[usize; 3]> {
+    let mut triangles = Vec::with_capacity(ring.len().saturating_sub(2));
+    let mut i = 0;
+    let mut stalled = 0;
+
+    while ring.len() > 3 {
+        let n = ring.len();
+        let (prev, cur, next) = (ring[(i + n - 1) % n], ring[i % n], ring[(i + 1) % n]);
+        let (a, b, c) = (points[prev], points[cur], points[next]);
+        let turn = cross(a, b, c);
+
+        // Collinear and duplicate corners add no area
+        if turn == 0.0 {
+            ring.remove(i % n);
+            stalled = 0;
+            continue;
+        }
+
+        if turn > 0.0 && is_ear(points, &ring, a, b, c) {
+            triangles.push([prev, cur, next]);
+            ring.remove(i % n);
+            stalled = 0;
+            continue;
+        }
+
+        i = (i + 1) % n;
+        stalled += 1;
+        // No ear left, the input was not simple or lost precision. Cut the next convex corner
+        // anyway so the rest of the shape still gets covered.
+        if stalled > n {
+            let n = ring.len();
+            let forced = (0..n)
+                .find(|&k| cross(points[ring[(k + n - 1) % n]], points[ring[k]], points[ring[(k + 1) % n]]) > 0.0)
+                .unwrap_or(0);
+            triangles.push([ring[(forced + n - 1) % n], ring[forced], ring[(forced + 1) % n]]);
+            ring.remove(forced);
+            stalled = 0;
+        }
+    }
+
+    if ring.len() == 3 && cross(points[ring[0]], points[ring[1]], points[ring[2]]) != 0.0 {
+        triangles.push([ring[0], ring[1], ring[2]]);
+    }
+    triangles
+}
+
+// No other vertex of the ring may sit inside the candidate ear. Vertices sharing a position
+// with a corner are bridge duplicates and are skipped.
+fn is_ear(points: &[Point], ring: &[usize], a: Point, b: Point, c: Point) -> bool {
+    ring.iter().all(|&k| {
+        let p = points[k];
+        p == a || p == b || p == c || !in_triangle(p, a, b, c)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(points: &[Point], triangles: &[[usize; 3]]) -> f32 {
+        triangles
+            .iter()
+            .map(|t| cross(points[t[0]], points[t[1]], points[t[2]]) / 2.0)
+            .sum()
+    }
+
+    #[test]
+    fn convex_polygon_is_fanned() {
+        let square = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)];
+        let triangles = triangulate(&square, &[]);
+        assert_eq!(triangles, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn clockwise_input_keeps_its_winding() {
+        let square = [(0.0, 0.0), (0.0, 2.0), (2.0, 2.0), (2.0, 0.0)];
+        let triangles = triangulate(&square, &[]);
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(area(&square, &triangles), -4.0);
+    }
+
+    #[test]
+    fn concave_polygon_covers_its_area() {
+        let arrow = [(0.0, 0.0), (4.0, 2.0), (0.0, 4.0), (1.0, 2.0)];
+        let triangles = triangulate(&arrow, &[]);
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(area(&arrow, &triangles), 6.0);
+        assert!(triangles.iter().all(|t| cross(arrow[t[0]], arrow[t[1]], arrow[t[2]]) > 0.0));
+    }
+
+    #[test]
+    fn collinear_vertices_are_dropped() {
+        let points = [(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 1.0)];
+        let triangles = triangulate(&points, &[]);
+        assert_eq!(area(&points, &triangles), 4.0);
+        assert!(triangles.iter().all(|t| cross(points[t[0]], points[t[1]], points[t[2]]) != 0.0));
+    }
+
+    #[test]
+    fn degenerate_input_gives_no_triangles() {
+        assert!(triangulate(&[], &[]).is_empty());
+        assert!(triangulate(&[(0.0, 0.0), (1.0, 1.0)], &[]).is_empty());
+        assert!(triangulate(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)], &[]).is_empty());
+        assert!(triangulate(&[(1.0, 1.0), (1.0, 1.0), (1.0, 1.0), (1.0, 1.0)], &[]).is_empty());
+    }
+
+    #[test]
+    fn hole_is_bridged_and_left_empty() {
+        let outer = [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+        let hole = [(3.0, 3.0), (7.0, 3.0), (7.0, 7.0), (3.0, 7.0)];
+        let triangles = triangulate(&outer, &[&hole]);
+
+        let mut points = outer.to_vec();
+        points.extend_from_slice(&hole);
+        assert_eq!(area(&points, &triangles), 84.0);
+        assert!(triangles.iter().all(|t| t.iter().all(|&i| i < points.len())));
+    }
+
+    #[test]
+    fn clockwise_outline_with_hole() {
+        let outer = [(0.0, 0.0), (0.0, 10.0), (10.0, 10.0), (10.0, 0.0)];
+        let hole = [(3.0, 3.0), (3.0, 7.0), (7.0, 7.0), (7.0, 3.0)];
+        let triangles = triangulate(&outer, &[&hole]);
+
+        let mut points = outer.to_vec();
+        points.extend_from_slice(&hole);
+        assert_eq!(area(&points, &triangles), -84.0);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::clip::ClipRect;
+
+// Mapping from normalized device coordinates to the window.
+//
+// Geometry is projected into the [-1, 1] cube first, with y pointing up and z growing away
+// from the viewer. The viewport stretches that cube over its pixel rectangle and depth range,
+// so several views can share one framebuffer. It does not clip anything by itself, a scissor
+// rectangle in the render state keeps fragments inside, usually `rect()` of the viewport.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+}
+
+impl Viewport {
+    // Depth range 0 to 1
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height, min_depth: 0.0, max_depth: 1.0 }
+    }
+
+    pub fn with_depth_range(self, min_depth: f32, max_depth: f32) -> Self {
+        Self { min_depth, max_depth, ..self }
+    }
+
+    // Width over height, for projections that keep shapes undistorted
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height.max(1) as f32
+    }
+
+    pub fn rect(&self) -> ClipRect {
+        ClipRect::new(self.x, self.y, self.width, self.height)
+    }
+
+    // Window position and depth of a point in normalized device coordinates
+    pub fn to_window(&self, x: f32, y: f32, z: f32) -> (f32, f32, f32) {
+        (
+            self.x as f32 + (x + 1.0) * 0.5 * self.width as f32,
+            self.y as f32 + (1.0 - y) * 0.5 * self.height as f32,
+            self.min_depth + (z + 1.0) * 0.5 * (self.max_depth - self.min_depth),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framebuffer::Framebuffer;
+    use crate::state::RenderState;
+    use sdl2::pixels::Color;
+
+    #[test]
+    fn corners_of_the_cube_map_to_the_rectangle() {
+        let viewport = Viewport::new(100, 50, 200, 80).with_depth_range(0.25, 0.75);
+        assert_eq!(viewport.to_window(-1.0, 1.0, -1.0), (100.0, 50.0, 0.25));
+        assert_eq!(viewport.to_window(1.0, -1.0, 1.0), (300.0, 130.0, 0.75));
+        assert_eq!(viewport.to_window(0.0, 0.0, 0.0), (200.0, 90.0, 0.5));
+        // y points up in normalized device coordinates and down in the window
+        assert!(viewport.to_window(0.0, 0.5, 0.0).1 < viewport.to_window(0.0, -0.5, 0.0).1);
+        assert_eq!(viewport.rect(), ClipRect { x_min: 100, y_min: 50, x_max: 299, y_max: 129 });
+        assert_eq!(viewport.aspect(), 2.5);
+    }
+
+    #[test]
+    fn the_scissor_rejects_fragments_outside_the_viewport() {
+        let viewport = Viewport::new(2, 2, 4, 4);
+        let state = RenderState { scissor: Some(viewport.rect()), ..RenderState::OPAQUE };
+        let white = Color::RGB(255, 255, 255);
+        let mut frame = Framebuffer::new(8, 8);
+        // A cell straddling the corner is cut, cells outside leave the frame alone
+        frame.write_fragment(0, 0, 3, white, 0.5, &state);
+        frame.write_fragment(6, 6, 2, white, 0.5, &state);
+        frame.write_fragment(5, 0, 1, white, 0.5, &state);
+
+        let written: Vec<(i32, i32)> =
+            (0..8).flat_map(|y| (0..8).map(move |x| (x, y))).filter(|&(x, y)| frame.pixel(x, y) == Some(white)).collect();
+        assert_eq!(written, vec![(2, 2)]);
+        assert_eq!(frame.depth(0, 0), Some(f32::INFINITY));
+    }
+}